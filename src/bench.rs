@@ -0,0 +1,118 @@
+// Replays a recorded session through the frame path, from contacts to OSC
+// sent, timing each frame and counting what it allocates.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::allocs;
+use crate::driver::{ControlEvent, Driver};
+use crate::output::Outputs;
+use crate::sensel::contact::Contact;
+use crate::session::Session;
+use crate::stats::Series;
+
+/// One pass over a session.
+#[derive(Default)]
+pub struct Run {
+    pub frames: u64,
+    // handling each frame
+    pub time: Series,
+    pub allocations: u64,
+}
+
+/// Buffers kept from one pass to the next, as the scan loop keeps them.
+#[derive(Default)]
+pub struct Bench {
+    contacts: Vec<Contact>,
+    events: Vec<ControlEvent>,
+}
+
+impl Bench {
+    /// Play every frame of `session` as fast as possible, then lift any
+    /// contacts still held. Filters see the session's own timing.
+    pub fn run(&mut self, session: &Session, driver: &mut Driver, outputs: &mut Outputs) -> Run {
+        let mut run = Run::default();
+        let start = Instant::now();
+        let allocations = allocs::count();
+        for frame in &session.frames {
+            let started = Instant::now();
+            let now = start + Duration::from_secs_f32(frame.time.max(0.0));
+            self.contacts.clear();
+            self.contacts.extend_from_slice(&frame.contacts);
+            self.events.clear();
+            driver.process(&self.contacts, now, &mut self.events);
+            outputs.emit(driver.interface(), &self.events, SystemTime::now());
+            run.time.record(started.elapsed());
+            run.frames += 1;
+        }
+        self.events.clear();
+        driver.release_all(&mut self.events);
+        outputs.emit(driver.interface(), &self.events, SystemTime::now());
+        run.allocations = allocs::count() - allocations;
+        run
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::filter::{FilterConfig, FilterSpec};
+    use crate::interface::Interface;
+    use crate::sensel::contact::State;
+    use crate::session::SessionFrame;
+    use crate::transport::OscSender;
+
+    fn contact(id: u8, state: State, x: f32) -> Contact {
+        Contact {
+            id, state, x, y: 10.0, total_force: 200.0, area: 4.0,
+            ellipse: None, delta: None, bounding_box: None, peak: None,
+        }
+    }
+
+    #[test]
+    fn steady_state_does_not_allocate() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let dest = format!("udp://{}", receiver.local_addr().unwrap()).parse().unwrap();
+        let interface = Interface::from_json(r#"[
+            {"id": "pad", "type": "pad", "address": "/pad", "args": [1], "x": 0, "y": 0, "width": 50, "height": 50},
+            {"id": "fader", "type": "horz_slider", "address": "/fader", "x": 50, "y": 0, "width": 50, "height": 50}
+        ]"#).unwrap();
+        let filters = FilterConfig {
+            position: vec![FilterSpec::Median { window: 3 }, FilterSpec::Ema { alpha: 0.5 }],
+            force: vec![],
+        };
+        let mut driver = Driver::new(interface, filters, HashMap::new());
+        let mut outputs = Outputs {
+            osc: OscSender::new(&[dest]).unwrap(),
+            namespace: None,
+            midi: vec![],
+            bundle: true,
+            latency: Duration::from_millis(0),
+            monitor: None,
+            encoder: Default::default(),
+        };
+
+        let mut frames = vec![SessionFrame {
+            time: 0.0,
+            contacts: vec![contact(1, State::CONTACT_START, 10.0), contact(2, State::CONTACT_START, 60.0)],
+        }];
+        for i in 1..20 {
+            frames.push(SessionFrame {
+                time: i as f32 * 0.002,
+                contacts: vec![contact(1, State::CONTACT_MOVE, 10.0 + i as f32), contact(2, State::CONTACT_MOVE, 60.0)],
+            });
+        }
+        // contact 2 is still held at the end
+        frames.push(SessionFrame { time: 0.05, contacts: vec![contact(1, State::CONTACT_END, 30.0)] });
+        let session = Session { width: 230.0, height: 130.0, frames };
+
+        let mut bench = Bench::default();
+        assert!(bench.run(&session, &mut driver, &mut outputs).allocations > 0);
+        let run = bench.run(&session, &mut driver, &mut outputs);
+        assert_eq!(run.frames, 21);
+        assert_eq!(run.allocations, 0);
+    }
+}
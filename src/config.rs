@@ -0,0 +1,471 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use toml::value::{Table, Value};
+
+use crate::control::Setting;
+use crate::filter::{FilterConfig, FilterSpec};
+use crate::sensel::bindings::SenselScanMode;
+use crate::sensel::contact;
+use crate::sensel::reader::Buffering;
+use crate::sensel::scan_mode::{SCAN_MODE_ASYNC, SCAN_MODE_SYNC};
+
+const SCAN_DETAILS: [&str; 3] = ["high", "medium", "low"];
+const CONTACT_MASKS: [(&str, contact::Mask); 4] = [
+    ("ellipse", contact::Mask::ELLIPSE),
+    ("deltas", contact::Mask::DELTAS),
+    ("bounding_box", contact::Mask::BOUNDING_BOX),
+    ("peak", contact::Mask::PEAK),
+];
+
+/// The device and its settings, anything left out keeps the device's own.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceConfig {
+    // the first device found if not given
+    pub serial: Option<String>,
+    // talk to the device on this serial port rather than through libsensel
+    pub port: Option<String>,
+    pub scan_mode: Option<SenselScanMode>,
+    // what the scan loop does with several frames from one read
+    pub buffering: Option<Buffering>,
+    pub contacts_mask: Option<contact::Mask>,
+    // of normalised LED brightness
+    pub led_gamma: Option<f32>,
+    // in the order of `Setting::ALL`
+    pub settings: Vec<(Setting, i32)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputConfig {
+    // the default destination if empty
+    pub osc: Vec<String>,
+    pub bundle: bool,
+    // ms
+    pub latency: u64,
+    pub control: Option<String>,
+    pub oscquery: Option<u16>,
+    pub midi: Option<String>,
+    pub record: Option<String>,
+    pub mpe: Option<u8>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> OutputConfig {
+        OutputConfig {
+            osc: vec![],
+            bundle: true,
+            latency: 0,
+            control: None,
+            oscquery: None,
+            midi: None,
+            record: None,
+            mpe: None,
+        }
+    }
+}
+
+/// Everything a rig needs, read from a TOML file:
+///
+/// ```toml
+/// interfaces = ["main.svg", "drums.json"]
+/// page_swipe = 3
+///
+/// [device]
+/// serial = "SM01182109153"
+/// scan_mode = "sync"
+/// buffering = "all"
+/// scan_detail = "medium"
+/// max_frame_rate = 500
+/// contacts_mask = ["ellipse", "peak"]
+/// led_gamma = 2.2
+/// contacts_enable_blob_merge = false
+///
+/// [outputs]
+/// osc = ["udp://127.0.0.1:57120"]
+/// latency = 5
+/// control = "127.0.0.1:9000"
+///
+/// [filters]
+/// position = "one_euro(beta=0.01)"
+/// [filters.controls.volume]
+/// force = "ema(alpha=0.2)"
+/// ```
+///
+/// Unknown keys are errors, so typos don't go unnoticed at soundcheck.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub interfaces: Vec<String>,
+    pub page_swipe: Option<usize>,
+    pub device: DeviceConfig,
+    pub outputs: OutputConfig,
+    // defaults for every control
+    pub filters: FilterConfig,
+    // by control id
+    pub control_filters: BTreeMap<String, FilterConfig>,
+}
+
+fn table<'a>(value: &'a Value, key: &str) -> Result<&'a Table, String> {
+    value.as_table().ok_or_else(|| format!("{}: expected a table", key))
+}
+
+fn check_keys(table: &Table, prefix: &str, allowed: &[&str]) -> Result<(), String> {
+    match table.keys().find(|k| !allowed.contains(&k.as_str())) {
+        Some(key) => Err(format!("unknown key '{}{}'", prefix, key)),
+        None => Ok(()),
+    }
+}
+
+fn string(value: &Value, key: &str) -> Result<String, String> {
+    value.as_str().map(str::to_string).ok_or_else(|| format!("{}: expected a string", key))
+}
+
+fn strings(value: &Value, key: &str) -> Result<Vec<String>, String> {
+    value.as_array()
+        .ok_or_else(|| format!("{}: expected an array of strings", key))?
+        .iter()
+        .map(|v| string(v, key))
+        .collect()
+}
+
+fn integer(value: &Value, key: &str, max: i64) -> Result<i64, String> {
+    match value.as_integer() {
+        Some(i) if i >= 0 && i <= max => Ok(i),
+        Some(i) => Err(format!("{}: {} out of range", key, i)),
+        None => Err(format!("{}: expected an integer", key)),
+    }
+}
+
+fn positive(value: &Value, key: &str) -> Result<f64, String> {
+    match value.as_float().or_else(|| value.as_integer().map(|i| i as f64)) {
+        Some(f) if f > 0.0 => Ok(f),
+        Some(f) => Err(format!("{}: {} out of range", key, f)),
+        None => Err(format!("{}: expected a number", key)),
+    }
+}
+
+fn filter_config(value: &Value, key: &str) -> Result<FilterConfig, String> {
+    let t = table(value, key)?;
+    let mut config = FilterConfig::default();
+    for (channel, spec) in t {
+        let chain = FilterSpec::parse_chain(&string(spec, key)?).map_err(|e| format!("{}: {}", key, e))?;
+        config.set(channel, chain).map_err(|e| format!("{}: {}", key, e))?;
+    }
+    Ok(config)
+}
+
+fn chain_string(chain: &[FilterSpec]) -> String {
+    chain.iter().map(ToString::to_string).collect::<Vec<_>>().join("+")
+}
+
+fn filter_table(config: &FilterConfig) -> Table {
+    let mut t = Table::new();
+    if !config.position.is_empty() {
+        t.insert("position".to_string(), Value::String(chain_string(&config.position)));
+    }
+    if !config.force.is_empty() {
+        t.insert("force".to_string(), Value::String(chain_string(&config.force)));
+    }
+    t
+}
+
+fn setting_value(setting: Setting, value: &Value) -> Result<i32, String> {
+    let key = format!("device.{}", setting.name());
+    match (setting, value) {
+        (Setting::ScanDetail, Value::String(s)) => SCAN_DETAILS.iter()
+            .position(|d| d == s)
+            .map(|d| d as i32)
+            .ok_or_else(|| format!("{}: unknown scan detail '{}'", key, s)),
+        (Setting::ScanDetail, _) => integer(value, &key, 2).map(|i| i as i32),
+        (Setting::ContactsEnableBlobMerge, Value::Boolean(b))
+        | (Setting::DynamicBaselineEnabled, Value::Boolean(b)) => Ok(*b as i32),
+        _ => integer(value, &key, i64::from(i32::MAX)).map(|i| i as i32),
+    }
+}
+
+impl DeviceConfig {
+    fn set(&mut self, setting: Setting, value: i32) {
+        self.settings.retain(|&(s, _)| s != setting);
+        self.settings.push((setting, value));
+        self.settings.sort_by_key(|&(s, _)| Setting::ALL.iter().position(|&a| a == s));
+    }
+
+    fn set_value(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        match key {
+            "serial" => self.serial = Some(string(value, "device.serial")?),
+            "port" => self.port = Some(string(value, "device.port")?),
+            "scan_mode" => self.scan_mode = Some(match value.as_str() {
+                Some("sync") => SCAN_MODE_SYNC,
+                Some("async") => SCAN_MODE_ASYNC,
+                _ => return Err("device.scan_mode: expected \"sync\" or \"async\"".to_string()),
+            }),
+            "buffering" => self.buffering = Some(value.as_str().and_then(Buffering::from_name)
+                .ok_or("device.buffering: expected \"all\" or \"newest\"")?),
+            "contacts_mask" => {
+                let mut mask = contact::Mask::empty();
+                for name in strings(value, "device.contacts_mask")? {
+                    mask |= CONTACT_MASKS.iter()
+                        .find(|&&(n, _)| n == name)
+                        .map(|&(_, m)| m)
+                        .ok_or_else(|| format!("device.contacts_mask: unknown '{}'", name))?;
+                }
+                self.contacts_mask = Some(mask);
+            }
+            "led_gamma" => self.led_gamma = Some(positive(value, "device.led_gamma")? as f32),
+            _ => {
+                let setting = Setting::from_name(key).ok_or_else(|| format!("unknown key 'device.{}'", key))?;
+                let value = setting_value(setting, value)?;
+                self.set(setting, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Set from the command line, `NAME=VALUE` with the names of the file.
+    pub fn set_arg(&mut self, arg: &str) -> Result<(), String> {
+        let mut parts = arg.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let value = parts.next().ok_or_else(|| format!("expected NAME=VALUE, got '{}'", arg))?;
+        let value = match value {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            _ if name == "contacts_mask" => Value::Array(value.split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| Value::String(s.to_string()))
+                .collect()),
+            _ => value.parse().map(Value::Integer)
+                .or_else(|_| value.parse().map(Value::Float))
+                .unwrap_or_else(|_| Value::String(value.to_string())),
+        };
+        self.set_value(name, &value)
+    }
+
+    fn to_table(&self) -> Table {
+        let mut t = Table::new();
+        if let Some(ref serial) = self.serial {
+            t.insert("serial".to_string(), Value::String(serial.clone()));
+        }
+        if let Some(ref port) = self.port {
+            t.insert("port".to_string(), Value::String(port.clone()));
+        }
+        if let Some(mode) = self.scan_mode {
+            let mode = if mode == SCAN_MODE_ASYNC { "async" } else { "sync" };
+            t.insert("scan_mode".to_string(), Value::String(mode.to_string()));
+        }
+        if let Some(buffering) = self.buffering {
+            t.insert("buffering".to_string(), Value::String(buffering.name().to_string()));
+        }
+        if let Some(mask) = self.contacts_mask {
+            let names = CONTACT_MASKS.iter()
+                .filter(|&&(_, m)| mask.contains(m))
+                .map(|&(n, _)| Value::String(n.to_string()))
+                .collect();
+            t.insert("contacts_mask".to_string(), Value::Array(names));
+        }
+        if let Some(gamma) = self.led_gamma {
+            t.insert("led_gamma".to_string(), Value::Float(f64::from(gamma)));
+        }
+        for &(setting, value) in &self.settings {
+            let value = match setting {
+                Setting::ScanDetail => Value::String(SCAN_DETAILS[value as usize].to_string()),
+                Setting::ContactsEnableBlobMerge | Setting::DynamicBaselineEnabled => Value::Boolean(value != 0),
+                _ => Value::Integer(i64::from(value)),
+            };
+            t.insert(setting.name().to_string(), value);
+        }
+        t
+    }
+}
+
+impl OutputConfig {
+    fn from_value(value: &Value) -> Result<OutputConfig, String> {
+        let t = table(value, "outputs")?;
+        check_keys(t, "outputs.", &["osc", "bundle", "latency", "control", "oscquery", "midi", "record", "mpe"])?;
+        let mut config = OutputConfig::default();
+        if let Some(v) = t.get("osc") {
+            config.osc = strings(v, "outputs.osc")?;
+        }
+        if let Some(v) = t.get("bundle") {
+            config.bundle = v.as_bool().ok_or("outputs.bundle: expected true or false")?;
+        }
+        if let Some(v) = t.get("latency") {
+            config.latency = integer(v, "outputs.latency", i64::MAX)? as u64;
+        }
+        if let Some(v) = t.get("control") {
+            // a bare port is fine too
+            config.control = Some(match v.as_integer() {
+                Some(port) => port.to_string(),
+                None => string(v, "outputs.control")?,
+            });
+        }
+        if let Some(v) = t.get("oscquery") {
+            config.oscquery = Some(integer(v, "outputs.oscquery", 65535)? as u16);
+        }
+        if let Some(v) = t.get("midi") {
+            config.midi = Some(string(v, "outputs.midi")?);
+        }
+        if let Some(v) = t.get("record") {
+            config.record = Some(string(v, "outputs.record")?);
+        }
+        if let Some(v) = t.get("mpe") {
+            config.mpe = Some(integer(v, "outputs.mpe", 255)? as u8);
+        }
+        Ok(config)
+    }
+
+    fn to_table(&self) -> Table {
+        let mut t = Table::new();
+        let strings = |v: &[String]| Value::Array(v.iter().cloned().map(Value::String).collect());
+        t.insert("osc".to_string(), strings(&self.osc));
+        t.insert("bundle".to_string(), Value::Boolean(self.bundle));
+        t.insert("latency".to_string(), Value::Integer(self.latency as i64));
+        let optional = [
+            ("control", self.control.clone().map(Value::String)),
+            ("oscquery", self.oscquery.map(|p| Value::Integer(i64::from(p)))),
+            ("midi", self.midi.clone().map(Value::String)),
+            ("record", self.record.clone().map(Value::String)),
+            ("mpe", self.mpe.map(|m| Value::Integer(i64::from(m)))),
+        ];
+        for (key, value) in optional.iter() {
+            if let Some(value) = value {
+                t.insert(key.to_string(), value.clone());
+            }
+        }
+        t
+    }
+}
+
+impl Config {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        text.parse::<Value>()
+            .map_err(|e| e.to_string())
+            .and_then(|value| Config::from_value(&value))
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+    }
+
+    pub fn from_value(value: &Value) -> Result<Config, String> {
+        let t = table(value, "config")?;
+        check_keys(t, "", &["interfaces", "page_swipe", "device", "outputs", "filters"])?;
+        let mut config = Config::default();
+        if let Some(v) = t.get("interfaces") {
+            config.interfaces = strings(v, "interfaces")?;
+        }
+        if let Some(v) = t.get("page_swipe") {
+            config.page_swipe = Some(integer(v, "page_swipe", 10)? as usize);
+        }
+        if let Some(v) = t.get("device") {
+            for (key, value) in table(v, "device")? {
+                config.device.set_value(key, value)?;
+            }
+        }
+        if let Some(v) = t.get("outputs") {
+            config.outputs = OutputConfig::from_value(v)?;
+        }
+        if let Some(v) = t.get("filters") {
+            let mut filters = table(v, "filters")?.clone();
+            if let Some(controls) = filters.remove("controls") {
+                for (id, value) in table(&controls, "filters.controls")? {
+                    let key = format!("filters.controls.{}", id);
+                    config.control_filters.insert(id.clone(), filter_config(value, &key)?);
+                }
+            }
+            config.filters = filter_config(&Value::Table(filters), "filters")?;
+        }
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut t = Table::new();
+        let strings = |v: &[String]| Value::Array(v.iter().cloned().map(Value::String).collect());
+        t.insert("interfaces".to_string(), strings(&self.interfaces));
+        if let Some(fingers) = self.page_swipe {
+            t.insert("page_swipe".to_string(), Value::Integer(fingers as i64));
+        }
+        t.insert("device".to_string(), Value::Table(self.device.to_table()));
+        t.insert("outputs".to_string(), Value::Table(self.outputs.to_table()));
+        let mut filters = filter_table(&self.filters);
+        if !self.control_filters.is_empty() {
+            let controls = self.control_filters.iter()
+                .map(|(id, config)| (id.clone(), Value::Table(filter_table(config))))
+                .collect();
+            filters.insert("controls".to_string(), Value::Table(controls));
+        }
+        t.insert("filters".to_string(), Value::Table(filters));
+        // a table of plain values always serialises
+        toml::to_string(&Value::Table(t)).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        interfaces = ["main.svg"]
+
+        [device]
+        serial = "SM01182109153"
+        scan_mode = "async"
+        buffering = "newest"
+        scan_detail = "low"
+        max_frame_rate = 500
+        contacts_mask = ["ellipse", "peak"]
+        dynamic_baseline_enabled = false
+
+        [outputs]
+        osc = ["udp://127.0.0.1:9000"]
+        control = 9001
+        mpe = 8
+
+        [filters]
+        position = "ema(alpha=0.5)"
+        [filters.controls.volume]
+        force = "median(window=3)"
+    "#;
+
+    fn parse(text: &str) -> Result<Config, String> {
+        Config::from_value(&text.parse::<Value>().unwrap())
+    }
+
+    #[test]
+    fn read() {
+        let config = parse(CONFIG).unwrap();
+        assert_eq!(config.interfaces, vec!["main.svg"]);
+        assert_eq!(config.device.serial.as_deref(), Some("SM01182109153"));
+        assert_eq!(config.device.scan_mode, Some(SCAN_MODE_ASYNC));
+        assert_eq!(config.device.buffering, Some(Buffering::Newest));
+        assert_eq!(config.device.contacts_mask, Some(contact::Mask::ELLIPSE | contact::Mask::PEAK));
+        assert_eq!(config.device.settings, vec![
+            (Setting::ScanDetail, 2),
+            (Setting::MaxFrameRate, 500),
+            (Setting::DynamicBaselineEnabled, 0),
+        ]);
+        assert_eq!(config.outputs.control.as_deref(), Some("9001"));
+        assert!(config.outputs.bundle);
+        assert_eq!(config.filters.position, vec![FilterSpec::Ema { alpha: 0.5 }]);
+        assert_eq!(config.control_filters["volume"].force, vec![FilterSpec::Median { window: 3 }]);
+    }
+
+    #[test]
+    fn dump_reads_back() {
+        let mut config = parse(CONFIG).unwrap();
+        config.device.set_arg("contacts_min_force=24").unwrap();
+        config.device.set_arg("scan_detail=high").unwrap();
+        let dumped = config.to_toml();
+        assert_eq!(parse(&dumped).unwrap(), config);
+        assert_eq!(config.device.settings[0], (Setting::ScanDetail, 0));
+        assert!(dumped.contains("scan_detail = \"high\""));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("[device]\nscan_detial = 1").unwrap_err(), "unknown key 'device.scan_detial'");
+        assert_eq!(parse("[outputs]\nport = 1").unwrap_err(), "unknown key 'outputs.port'");
+        assert!(parse("[device]\nscan_mode = \"fast\"").is_err());
+        assert!(parse("[device]\nbuffering = \"some\"").is_err());
+        assert!(parse("[filters]\nposition = \"ema(alpha=2)\"").is_err());
+        assert!(DeviceConfig::default().set_arg("max_frame_rate").is_err());
+    }
+}
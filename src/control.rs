@@ -0,0 +1,519 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+use rosc::{decoder, encoder, OscMessage, OscPacket, OscType};
+
+use crate::interface::PageTarget;
+use crate::sensel::scan_detail::{SCAN_DETAIL_HIGH, SCAN_DETAIL_LOW, SCAN_DETAIL_MEDIUM};
+use crate::sensel::device::Device;
+use crate::sensel::reader::retry_time;
+use crate::sensel::remote::Remote;
+use crate::sensel::settings::SettingValue;
+
+const MAX_PACKET_SIZE: usize = 8192;
+
+/// Settings that can be both read and written over OSC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Setting {
+    ScanDetail,
+    BufferControl,
+    MaxFrameRate,
+    ContactsMinForce,
+    ContactsEnableBlobMerge,
+    DynamicBaselineEnabled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Query {
+    Setting(Setting),
+    ScanMode,
+    FrameContent,
+    ContactsMask,
+    Led(u8),
+    PowerButtonPressed,
+    Paused,
+    Page,
+}
+
+/// Commands accepted by the control server, all under `/sensel`:
+///
+/// - `/set/<setting> v`, `/set/led i v`, `/set/led_array v...`, and
+///   `/set/led_level i f`, `/set/led_levels f...` from 0 to 1
+/// - `/get/<setting>`, `/get/led i`, `/get/scan_mode`, `/get/frame_content`,
+///   `/get/contacts_mask`, `/get/power_button_pressed`, `/get/paused`,
+///   `/get/page`
+/// - `/interface/load path`
+/// - `/driver/pause [0|1]`, toggling without an argument
+/// - `/page i`, `/page name`, `/page next`, `/page prev`
+///
+/// Queries are answered to the sender as `/sensel/<name> [i] v`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Set(Setting, i32),
+    SetLed(u8, u16),
+    SetLedArray(Vec<u16>),
+    SetLedLevel(u8, f32),
+    SetLedLevels(Vec<f32>),
+    // LEDs the server has set, for the scan loop to show
+    ShowLeds(Vec<(u8, u16)>),
+    Get(Query),
+    LoadInterface(PathBuf),
+    Pause(Option<bool>),
+    Page(PageTarget),
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub command: Command,
+    pub from: SocketAddr,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 6] = [
+        Setting::ScanDetail,
+        Setting::BufferControl,
+        Setting::MaxFrameRate,
+        Setting::ContactsMinForce,
+        Setting::ContactsEnableBlobMerge,
+        Setting::DynamicBaselineEnabled,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Setting> {
+        match name {
+            "scan_detail" => Some(Setting::ScanDetail),
+            "buffer_control" => Some(Setting::BufferControl),
+            "max_frame_rate" => Some(Setting::MaxFrameRate),
+            "contacts_min_force" => Some(Setting::ContactsMinForce),
+            "contacts_enable_blob_merge" => Some(Setting::ContactsEnableBlobMerge),
+            "dynamic_baseline_enabled" => Some(Setting::DynamicBaselineEnabled),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Setting::ScanDetail => "scan_detail",
+            Setting::BufferControl => "buffer_control",
+            Setting::MaxFrameRate => "max_frame_rate",
+            Setting::ContactsMinForce => "contacts_min_force",
+            Setting::ContactsEnableBlobMerge => "contacts_enable_blob_merge",
+            Setting::DynamicBaselineEnabled => "dynamic_baseline_enabled",
+        }
+    }
+
+    /// The typed value for `value`, checking its range.
+    pub fn value(self, value: i32) -> Result<SettingValue, String> {
+        let out_of_range = || format!("{} out of range: {}", self.name(), value);
+        match self {
+            Setting::ScanDetail => match value {
+                0 => Ok(SettingValue::ScanDetail(SCAN_DETAIL_HIGH)),
+                1 => Ok(SettingValue::ScanDetail(SCAN_DETAIL_MEDIUM)),
+                2 => Ok(SettingValue::ScanDetail(SCAN_DETAIL_LOW)),
+                _ => Err(out_of_range()),
+            },
+            Setting::BufferControl if (0..=255).contains(&value) => Ok(SettingValue::BufferControl(value as u8)),
+            Setting::MaxFrameRate if (0..=65535).contains(&value) => Ok(SettingValue::MaxFrameRate(value as u16)),
+            Setting::ContactsMinForce if (0..=65535).contains(&value) => Ok(SettingValue::ContactsMinForce(value as u16)),
+            Setting::ContactsEnableBlobMerge => Ok(SettingValue::ContactsEnableBlobMerge(value != 0)),
+            Setting::DynamicBaselineEnabled => Ok(SettingValue::DynamicBaselineEnabled(value != 0)),
+            _ => Err(out_of_range()),
+        }
+    }
+
+    pub fn get<D: Device>(self, device: &D) -> Result<i32, String> {
+        let result = match self {
+            Setting::ScanDetail => device.get_scan_detail().map(|d| d as i32),
+            Setting::BufferControl => device.get_buffer_control().map(i32::from),
+            Setting::MaxFrameRate => device.get_max_frame_rate().map(i32::from),
+            Setting::ContactsMinForce => device.get_contacts_min_force().map(i32::from),
+            Setting::ContactsEnableBlobMerge => device.get_contacts_enable_blob_merge().map(i32::from),
+            Setting::DynamicBaselineEnabled => device.get_dynamic_baseline_enabled().map(i32::from),
+        };
+        result.map_err(|_| format!("failed to read {}", self.name()))
+    }
+}
+
+fn int_arg(msg: &OscMessage, index: usize) -> Result<i32, String> {
+    match msg.args.as_ref().and_then(|args| args.get(index)) {
+        Some(OscType::Int(i)) => Ok(*i),
+        Some(OscType::Long(l)) => Ok(*l as i32),
+        Some(OscType::Float(f)) => Ok(*f as i32),
+        Some(OscType::Double(d)) => Ok(*d as i32),
+        Some(OscType::Bool(b)) => Ok(*b as i32),
+        Some(other) => Err(format!("{}: expected a number, got {:?}", msg.addr, other)),
+        None => Err(format!("{}: missing argument {}", msg.addr, index + 1)),
+    }
+}
+
+fn float_arg(msg: &OscMessage, index: usize) -> Result<f32, String> {
+    match msg.args.as_ref().and_then(|args| args.get(index)) {
+        Some(OscType::Float(f)) => Ok(*f),
+        Some(OscType::Double(d)) => Ok(*d as f32),
+        Some(_) => int_arg(msg, index).map(|i| i as f32),
+        None => Err(format!("{}: missing argument {}", msg.addr, index + 1)),
+    }
+}
+
+fn num_args(msg: &OscMessage) -> usize {
+    msg.args.as_ref().map_or(0, Vec::len)
+}
+
+fn scan_detail_arg(msg: &OscMessage) -> Result<i32, String> {
+    match msg.args.as_ref().and_then(|args| args.first()) {
+        Some(OscType::String(s)) => match s.as_str() {
+            "high" => Ok(0),
+            "medium" => Ok(1),
+            "low" => Ok(2),
+            _ => Err(format!("unknown scan detail '{}'", s)),
+        },
+        _ => int_arg(msg, 0),
+    }
+}
+
+impl Command {
+    pub fn parse(msg: &OscMessage) -> Result<Command, String> {
+        let path: Vec<&str> = msg.addr.split('/').skip(1).collect();
+        match path.as_slice() {
+            ["sensel", "set", "led"] => {
+                let led = int_arg(msg, 0)?;
+                let value = int_arg(msg, 1)?;
+                if !(0..=255).contains(&led) || !(0..=65535).contains(&value) {
+                    return Err(format!("led out of range: {} {}", led, value));
+                }
+                Ok(Command::SetLed(led as u8, value as u16))
+            }
+            ["sensel", "set", "led_array"] => {
+                (0..num_args(msg))
+                    .map(|i| int_arg(msg, i).and_then(|v| {
+                        if (0..=65535).contains(&v) {
+                            Ok(v as u16)
+                        } else {
+                            Err(format!("led brightness out of range: {}", v))
+                        }
+                    }))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Command::SetLedArray)
+            }
+            ["sensel", "set", "led_level"] => {
+                let led = int_arg(msg, 0)?;
+                if !(0..=255).contains(&led) {
+                    return Err(format!("led out of range: {}", led));
+                }
+                Ok(Command::SetLedLevel(led as u8, float_arg(msg, 1)?))
+            }
+            ["sensel", "set", "led_levels"] => {
+                (0..num_args(msg))
+                    .map(|i| float_arg(msg, i))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Command::SetLedLevels)
+            }
+            ["sensel", "set", "scan_detail"] => Ok(Command::Set(Setting::ScanDetail, scan_detail_arg(msg)?)),
+            ["sensel", "set", name] => match Setting::from_name(name) {
+                Some(setting) => Ok(Command::Set(setting, int_arg(msg, 0)?)),
+                None => Err(format!("unknown setting '{}'", name)),
+            },
+            ["sensel", "get", "led"] => {
+                let led = int_arg(msg, 0)?;
+                if !(0..=255).contains(&led) {
+                    return Err(format!("led out of range: {}", led));
+                }
+                Ok(Command::Get(Query::Led(led as u8)))
+            }
+            ["sensel", "get", name] => match *name {
+                "scan_mode" => Ok(Command::Get(Query::ScanMode)),
+                "frame_content" => Ok(Command::Get(Query::FrameContent)),
+                "contacts_mask" => Ok(Command::Get(Query::ContactsMask)),
+                "power_button_pressed" => Ok(Command::Get(Query::PowerButtonPressed)),
+                "paused" => Ok(Command::Get(Query::Paused)),
+                "page" => Ok(Command::Get(Query::Page)),
+                _ => Setting::from_name(name)
+                    .map(|s| Command::Get(Query::Setting(s)))
+                    .ok_or_else(|| format!("unknown query '{}'", name)),
+            },
+            ["sensel", "interface", "load"] => match msg.args.as_ref().and_then(|args| args.first()) {
+                Some(OscType::String(path)) => Ok(Command::LoadInterface(PathBuf::from(path))),
+                _ => Err(format!("{}: expected a path", msg.addr)),
+            },
+            ["sensel", "driver", "pause"] => {
+                if num_args(msg) == 0 {
+                    Ok(Command::Pause(None))
+                } else {
+                    Ok(Command::Pause(Some(int_arg(msg, 0)? != 0)))
+                }
+            }
+            ["sensel", "page"] => match msg.args.as_ref().and_then(|args| args.first()) {
+                Some(OscType::String(page)) => Ok(Command::Page(PageTarget::parse(page))),
+                _ => {
+                    let page = int_arg(msg, 0)?;
+                    if page < 0 {
+                        return Err(format!("page out of range: {}", page));
+                    }
+                    Ok(Command::Page(PageTarget::Index(page as usize)))
+                }
+            },
+            _ => Err(format!("unknown command '{}'", msg.addr)),
+        }
+    }
+}
+
+/// The driver's state, which lives outside the device.
+pub struct Status<'a> {
+    pub paused: bool,
+    pub page: usize,
+    pub page_name: &'a str,
+}
+
+/// Announces the current page, as `/sensel/page i name`.
+pub fn page_message(page: usize, name: &str) -> OscMessage {
+    OscMessage {
+        addr: "/sensel/page".to_string(),
+        args: Some(vec![OscType::Int(page as i32), OscType::String(name.to_string())]),
+    }
+}
+
+pub fn query<D: Device>(device: &D, query: Query, status: &Status) -> Result<OscMessage, String> {
+    let (name, args) = match query {
+        Query::Setting(setting) => (setting.name(), vec![OscType::Int(setting.get(device)?)]),
+        Query::ScanMode => ("scan_mode", vec![OscType::Int(
+            device.get_scan_mode().map_err(|_| "failed to read scan_mode")? as i32)]),
+        Query::FrameContent => ("frame_content", vec![OscType::Int(
+            i32::from(device.get_frame_content().map_err(|_| "failed to read frame_content")?.bits()))]),
+        Query::ContactsMask => ("contacts_mask", vec![OscType::Int(
+            i32::from(device.get_contacts_mask().map_err(|_| "failed to read contacts_mask")?.bits()))]),
+        Query::Led(led) => ("led", vec![OscType::Int(i32::from(led)), OscType::Int(
+            i32::from(device.get_led_brightness(led).map_err(|_| format!("failed to read led {}", led))?))]),
+        Query::PowerButtonPressed => ("power_button_pressed", vec![OscType::Int(
+            device.get_power_button_pressed().map_err(|_| "failed to read power button")? as i32)]),
+        Query::Paused => ("paused", vec![OscType::Int(status.paused as i32)]),
+        Query::Page => return Ok(page_message(status.page, status.page_name)),
+    };
+    Ok(OscMessage {
+        addr: format!("/sensel/{}", name),
+        args: Some(args),
+    })
+}
+
+pub fn error_message(err: &str) -> OscMessage {
+    OscMessage {
+        addr: "/sensel/error".to_string(),
+        args: Some(vec![OscType::String(err.to_string())]),
+    }
+}
+
+/// Listens for control messages on a UDP port. LEDs are changed through the
+/// device's `Remote`, the rest is queued for the scan loop.
+pub struct ControlServer {
+    socket: UdpSocket,
+    rx: mpsc::Receiver<Request>,
+}
+
+impl ControlServer {
+    pub fn bind<A: ToSocketAddrs>(addr: A, remote: Remote) -> io::Result<ControlServer> {
+        let socket = UdpSocket::bind(addr)?;
+        let listener = socket.try_clone()?;
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut buf = [0u8; MAX_PACKET_SIZE];
+            let mut failures = 0;
+            loop {
+                let (size, from) = match listener.recv_from(&mut buf) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        // backing off, as an error that persists would
+                        // otherwise spin
+                        failures += 1;
+                        warn!("Control server: {}", e);
+                        thread::sleep(retry_time(failures));
+                        continue;
+                    }
+                };
+                failures = 0;
+                let packet = match decoder::decode(&buf[..size]) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        warn!("Control server: invalid packet from {}: {:?}", from, e);
+                        continue;
+                    }
+                };
+                let mut messages = vec![];
+                flatten(packet, &mut messages);
+                for msg in messages {
+                    let command = match Command::parse(&msg).and_then(|command| apply(&remote, command)) {
+                        Ok(Some(command)) => command,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            warn!("Control server: {}", e);
+                            let _ = send_to(&listener, from, error_message(&e));
+                            continue;
+                        }
+                    };
+                    if tx.send(Request { command, from }).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(ControlServer { socket, rx })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn try_recv(&self) -> Option<Request> {
+        self.rx.try_recv().ok()
+    }
+
+    pub fn reply(&self, to: SocketAddr, msg: OscMessage) {
+        if let Err(e) = send_to(&self.socket, to, msg) {
+            warn!("Control server: failed to reply to {}: {}", to, e);
+        }
+    }
+}
+
+// write to the device, passing on what the scan loop needs to know
+fn apply(remote: &Remote, command: Command) -> Result<Option<Command>, String> {
+    match command {
+        // written by the scan loop, which stops scanning around it
+        Command::Set(setting, value) => setting.value(value).map(|_| Some(Command::Set(setting, value))),
+        Command::SetLed(led, value) => remote.set_led_brightness(led, value)
+            .map(|_| Some(Command::ShowLeds(vec![(led, value)])))
+            .map_err(|_| format!("device rejected led {} {}", led, value)),
+        Command::SetLedArray(values) => {
+            let len = values.len();
+            remote.set_led_array(values.clone())
+                .map(|_| Some(Command::ShowLeds((0..).zip(values).collect())))
+                .map_err(|_| format!("device rejected led array of {} values", len))
+        }
+        Command::SetLedLevel(led, level) => remote.set_led_level(led, level)
+            .map(|value| Some(Command::ShowLeds(vec![(led, value)])))
+            .map_err(|_| format!("device rejected led {} level {}", led, level)),
+        Command::SetLedLevels(levels) => {
+            let len = levels.len();
+            remote.set_led_levels(levels)
+                .map(|values| Some(Command::ShowLeds((0..).zip(values).collect())))
+                .map_err(|_| format!("device rejected {} led levels", len))
+        }
+        command => Ok(Some(command)),
+    }
+}
+
+fn flatten(packet: OscPacket, messages: &mut Vec<OscMessage>) {
+    match packet {
+        OscPacket::Message(msg) => messages.push(msg),
+        OscPacket::Bundle(bundle) => {
+            for p in bundle.content {
+                flatten(p, messages);
+            }
+        }
+    }
+}
+
+fn send_to(socket: &UdpSocket, to: SocketAddr, msg: OscMessage) -> io::Result<()> {
+    let buf = encoder::encode(&OscPacket::Message(msg))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+    socket.send_to(&buf, to).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensel::register;
+    use crate::sensel::serial::fake;
+
+    fn parse(addr: &str, args: Vec<OscType>) -> Result<Command, String> {
+        let args = if args.is_empty() { None } else { Some(args) };
+        Command::parse(&OscMessage { addr: addr.to_string(), args })
+    }
+
+    fn string(s: &str) -> OscType {
+        OscType::String(s.to_string())
+    }
+
+    #[test]
+    fn parses_every_address() {
+        use OscType::{Float, Int};
+        assert_eq!(parse("/sensel/set/led", vec![Int(2), Int(100)]), Ok(Command::SetLed(2, 100)));
+        assert_eq!(parse("/sensel/set/led_array", vec![Int(1), Float(2.0)]), Ok(Command::SetLedArray(vec![1, 2])));
+        assert_eq!(parse("/sensel/set/led_level", vec![Int(3), Float(0.5)]), Ok(Command::SetLedLevel(3, 0.5)));
+        assert_eq!(parse("/sensel/set/led_levels", vec![Float(0.5), Int(1)]), Ok(Command::SetLedLevels(vec![0.5, 1.0])));
+        assert_eq!(parse("/sensel/set/max_frame_rate", vec![Int(500)]), Ok(Command::Set(Setting::MaxFrameRate, 500)));
+        assert_eq!(parse("/sensel/set/scan_detail", vec![Int(1)]), Ok(Command::Set(Setting::ScanDetail, 1)));
+        assert_eq!(parse("/sensel/get/led", vec![Int(4)]), Ok(Command::Get(Query::Led(4))));
+        assert_eq!(parse("/sensel/get/scan_mode", vec![]), Ok(Command::Get(Query::ScanMode)));
+        assert_eq!(parse("/sensel/get/page", vec![]), Ok(Command::Get(Query::Page)));
+        assert_eq!(parse("/sensel/get/buffer_control", vec![]), Ok(Command::Get(Query::Setting(Setting::BufferControl))));
+        assert_eq!(parse("/sensel/interface/load", vec![string("a.json")]), Ok(Command::LoadInterface(PathBuf::from("a.json"))));
+        assert_eq!(parse("/sensel/page", vec![Int(2)]), Ok(Command::Page(PageTarget::Index(2))));
+        assert_eq!(parse("/sensel/page", vec![string("next")]), Ok(Command::Page(PageTarget::Next)));
+        assert_eq!(parse("/sensel/page", vec![string("drums")]), Ok(Command::Page(PageTarget::Name("drums".to_string()))));
+
+        assert_eq!(parse("/sensel/set/gain", vec![Int(1)]), Err("unknown setting 'gain'".to_string()));
+        assert_eq!(parse("/sensel/get/gain", vec![]), Err("unknown query 'gain'".to_string()));
+        assert_eq!(parse("/sensel/interface/load", vec![Int(1)]), Err("/sensel/interface/load: expected a path".to_string()));
+        assert_eq!(parse("/sensel/page", vec![Int(-1)]), Err("page out of range: -1".to_string()));
+        assert_eq!(parse("/other", vec![]), Err("unknown command '/other'".to_string()));
+    }
+
+    #[test]
+    fn pause_toggles_without_an_argument() {
+        assert_eq!(parse("/sensel/driver/pause", vec![]), Ok(Command::Pause(None)));
+        assert_eq!(parse("/sensel/driver/pause", vec![OscType::Int(1)]), Ok(Command::Pause(Some(true))));
+        assert_eq!(parse("/sensel/driver/pause", vec![OscType::Bool(false)]), Ok(Command::Pause(Some(false))));
+    }
+
+    #[test]
+    fn scan_detail_is_named_or_numbered() {
+        assert_eq!(parse("/sensel/set/scan_detail", vec![string("high")]), Ok(Command::Set(Setting::ScanDetail, 0)));
+        assert_eq!(parse("/sensel/set/scan_detail", vec![string("low")]), Ok(Command::Set(Setting::ScanDetail, 2)));
+        assert_eq!(parse("/sensel/set/scan_detail", vec![string("fine")]), Err("unknown scan detail 'fine'".to_string()));
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        use OscType::Int;
+        assert_eq!(parse("/sensel/set/led", vec![Int(256), Int(1)]), Err("led out of range: 256 1".to_string()));
+        assert_eq!(parse("/sensel/set/led", vec![Int(0), Int(65536)]), Err("led out of range: 0 65536".to_string()));
+        assert_eq!(parse("/sensel/set/led_array", vec![Int(1), Int(-1)]), Err("led brightness out of range: -1".to_string()));
+        assert_eq!(parse("/sensel/set/led_level", vec![Int(-1), Int(1)]), Err("led out of range: -1".to_string()));
+        assert_eq!(parse("/sensel/get/led", vec![Int(300)]), Err("led out of range: 300".to_string()));
+        assert_eq!(parse("/sensel/set/led", vec![Int(1)]), Err("/sensel/set/led: missing argument 2".to_string()));
+
+        assert_eq!(Setting::ScanDetail.value(2), Ok(SettingValue::ScanDetail(SCAN_DETAIL_LOW)));
+        assert!(Setting::ScanDetail.value(3).is_err());
+        assert!(Setting::BufferControl.value(256).is_err());
+        assert_eq!(Setting::MaxFrameRate.value(65535), Ok(SettingValue::MaxFrameRate(65535)));
+        assert_eq!(Setting::MaxFrameRate.value(-1), Err("max_frame_rate out of range: -1".to_string()));
+        assert!(Setting::ContactsMinForce.value(65536).is_err());
+    }
+
+    #[test]
+    fn apply_writes_leds_and_leaves_settings_to_the_scan_loop() {
+        let (device, regs) = fake::open(&[]);
+        let remote = device.remote();
+        let device = device.start_scanning().unwrap();
+        let applying = thread::spawn(move || {
+            (apply(&remote, Command::Set(Setting::MaxFrameRate, 500)),
+             apply(&remote, Command::Set(Setting::MaxFrameRate, -1)),
+             apply(&remote, Command::SetLed(0, 1)),
+             apply(&remote, Command::Pause(None)))
+        });
+        // the scan loop carries out the LED writes
+        while !applying.is_finished() {
+            let _ = device.read_sensor();
+        }
+        let (set, rejected, led, paused) = applying.join().unwrap();
+        assert_eq!(set, Ok(Some(Command::Set(Setting::MaxFrameRate, 500))));
+        assert_eq!(rejected, Err("max_frame_rate out of range: -1".to_string()));
+        // the fake has no LEDs
+        assert_eq!(led, Err("device rejected led 0 1".to_string()));
+        assert_eq!(paused, Ok(Some(Command::Pause(None))));
+        let rate = usize::from(register::SCAN_FRAME_RATE.addr);
+        assert_eq!(regs.lock().unwrap()[rate..rate + 2], [0, 0]);
+    }
+}
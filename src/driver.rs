@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::mem;
+use std::time::Instant;
+
+use crate::filter::{ContactFilter, FilterConfig};
+use crate::interface::{ControlType, Interface, MAX_FORCE};
+use crate::sensel::contact::{self, Contact, State};
+use crate::sensel::frame;
+
+/// The least an interface needs of each frame, to keep USB traffic and
+/// latency down: contacts, with only the optional contact data its controls
+/// use. Contacts are asked for even without controls, a reload may add some.
+pub fn required_content(interface: &Interface) -> (frame::Mask, contact::Mask) {
+    let contacts = interface.controls.iter()
+        .map(|control| match control.control_type {
+            // position and force come with every contact
+            ControlType::Pad | ControlType::VertSlider | ControlType::HorzSlider | ControlType::Keyboard { .. } =>
+                contact::Mask::empty(),
+        })
+        .fold(contact::Mask::empty(), |mask, needed| mask | needed);
+    (frame::Mask::CONTACTS, contacts)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Press,
+    Move,
+    Release,
+}
+
+/// A contact's update on the control it was captured by. `x` and `y` are
+/// relative to the control's rect in [0, 1], `force` is in grams.
+/// `start_x` and `start_y` are where the contact was first pressed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControlEvent {
+    pub control: usize,
+    pub contact: u8,
+    pub phase: Phase,
+    pub x: f32,
+    pub y: f32,
+    pub force: f32,
+    pub start_x: f32,
+    pub start_y: f32,
+}
+
+impl ControlEvent {
+    /// Force as a fraction of the maximum the Morph reports.
+    pub fn pressure(&self) -> f32 {
+        (self.force / MAX_FORCE).clamp(0.0, 1.0)
+    }
+
+    /// Position along a slider in [0, 1]. y grows downwards on the Morph,
+    /// so the top of a vertical slider is 1.
+    pub fn slider_position(&self, control_type: ControlType) -> f32 {
+        match control_type {
+            ControlType::VertSlider => 1.0 - self.y,
+            _ => self.x,
+        }
+    }
+
+    /// The key of a keyboard control the contact was pressed on.
+    pub fn key(&self, keys: u8) -> u8 {
+        ((self.start_x * f32::from(keys)) as u8).min(keys - 1)
+    }
+}
+
+struct Tracked {
+    control: usize,
+    filter: ContactFilter,
+    last: Instant,
+    event: ControlEvent,
+}
+
+/// Turns the contact stream into control events. A contact belongs to the
+/// control it started on until it is lifted, even if it slides outside.
+pub struct Driver {
+    interface: Interface,
+    defaults: FilterConfig,
+    overrides: HashMap<String, FilterConfig>,
+    filters: Vec<FilterConfig>,
+    // filters of released contacts by control, reused for the next press
+    spare: Vec<Vec<ContactFilter>>,
+    tracked: HashMap<u8, Tracked>,
+}
+
+impl Driver {
+    /// `defaults` apply to any channel a control does not filter itself,
+    /// `overrides` (by control id) take precedence over the interface file.
+    pub fn new(interface: Interface, defaults: FilterConfig, overrides: HashMap<String, FilterConfig>) -> Self {
+        let mut driver = Driver {
+            interface: Interface::default(),
+            defaults,
+            overrides,
+            filters: vec![],
+            spare: vec![],
+            tracked: HashMap::new(),
+        };
+        driver.set_interface(interface);
+        driver
+    }
+
+    pub fn interface(&self) -> &Interface {
+        &self.interface
+    }
+
+    /// Replace the interface. Held contacts are forgotten, call `release_all`
+    /// first to let their controls know.
+    pub fn set_interface(&mut self, interface: Interface) {
+        self.filters = self.filter_configs(&interface);
+        self.spare = self.filters.iter().map(|_| vec![]).collect();
+        self.interface = interface;
+        self.tracked.clear();
+    }
+
+    /// Release contacts held on controls that are not in `interface`, by id,
+    /// before it replaces the current one with `update_interface`.
+    pub fn release_removed(&mut self, interface: &Interface, events: &mut Vec<ControlEvent>) {
+        let controls = &self.interface.controls;
+        let removed: Vec<u8> = self.tracked.iter()
+            .filter(|(_, t)| interface.find(&controls[t.control].id).is_none())
+            .map(|(&id, _)| id)
+            .collect();
+        for id in removed {
+            let tracked = self.tracked.remove(&id).unwrap();
+            events.push(ControlEvent { phase: Phase::Release, ..tracked.event });
+            self.spare[tracked.control].push(tracked.filter);
+        }
+    }
+
+    /// Replace the interface, keeping contacts held on controls that are
+    /// still there. Their filters carry on unless the control's changed.
+    pub fn update_interface(&mut self, interface: Interface) {
+        let filters = self.filter_configs(&interface);
+        let old_filters = mem::replace(&mut self.filters, filters);
+        let old = mem::replace(&mut self.interface, interface);
+        self.spare = self.filters.iter().map(|_| vec![]).collect();
+
+        let (new, filters) = (&self.interface, &self.filters);
+        self.tracked.retain(|_, tracked| match new.find(&old.controls[tracked.control].id) {
+            Some(control) => {
+                if filters[control] != old_filters[tracked.control] {
+                    tracked.filter = ContactFilter::new(&filters[control]);
+                }
+                tracked.control = control;
+                tracked.event.control = control;
+                true
+            }
+            None => false,
+        });
+    }
+
+    fn filter_configs(&self, interface: &Interface) -> Vec<FilterConfig> {
+        interface.controls.iter()
+            .map(|control| {
+                let mut config = self.defaults.clone();
+                config.merge(&control.filters);
+                if let Some(o) = self.overrides.get(&control.id) {
+                    config.merge(o);
+                }
+                config
+            })
+            .collect()
+    }
+
+    /// Release every held contact, as if they had all been lifted.
+    pub fn release_all(&mut self, events: &mut Vec<ControlEvent>) {
+        for (_, tracked) in self.tracked.drain() {
+            events.push(ControlEvent { phase: Phase::Release, ..tracked.event });
+            self.spare[tracked.control].push(tracked.filter);
+        }
+    }
+
+    pub fn process(&mut self, contacts: &[Contact], now: Instant, events: &mut Vec<ControlEvent>) {
+        for contact in contacts {
+            match contact.state {
+                State::CONTACT_START => self.press(contact, now, events),
+                State::CONTACT_MOVE => self.update(contact, now, Phase::Move, events),
+                State::CONTACT_END => self.update(contact, now, Phase::Release, events),
+                State::CONTACT_INVALID => (),
+            }
+        }
+    }
+
+    fn press(&mut self, contact: &Contact, now: Instant, events: &mut Vec<ControlEvent>) {
+        if let Some(control) = self.interface.hit(contact.x, contact.y) {
+            let mut filter = match self.spare[control].pop() {
+                Some(mut filter) => {
+                    filter.reset();
+                    filter
+                }
+                None => ContactFilter::new(&self.filters[control]),
+            };
+            let (x, y, force) = filter.apply(contact.x, contact.y, contact.total_force, 0.0);
+            let (x, y) = self.interface.controls[control].rect.normalise(x, y);
+            let event = ControlEvent {
+                control,
+                contact: contact.id,
+                phase: Phase::Press,
+                x,
+                y,
+                force,
+                start_x: x,
+                start_y: y,
+            };
+            events.push(event);
+            self.tracked.insert(contact.id, Tracked { control, filter, last: now, event });
+        }
+    }
+
+    fn update(&mut self, contact: &Contact, now: Instant, phase: Phase, events: &mut Vec<ControlEvent>) {
+        let tracked = match self.tracked.get_mut(&contact.id) {
+            Some(tracked) => tracked,
+            None => return,
+        };
+
+        let dt = now.duration_since(tracked.last);
+        let dt = dt.as_secs() as f32 + dt.subsec_nanos() as f32 * 1e-9;
+        tracked.last = now;
+
+        let (x, y, force) = tracked.filter.apply(contact.x, contact.y, contact.total_force, dt);
+        let (x, y) = self.interface.controls[tracked.control].rect.normalise(x, y);
+        tracked.event = ControlEvent { phase, x, y, force, ..tracked.event };
+        events.push(tracked.event);
+
+        if phase == Phase::Release {
+            if let Some(tracked) = self.tracked.remove(&contact.id) {
+                self.spare[tracked.control].push(tracked.filter);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pages::{Page, Pages};
+
+    fn contact(id: u8, state: State, x: f32, y: f32) -> Contact {
+        Contact {
+            id, state, x, y, total_force: 100.0, area: 1.0,
+            ellipse: None, delta: None, bounding_box: None, peak: None,
+        }
+    }
+
+    fn page(name: &str, controls: &str) -> Page {
+        Page { name: name.to_string(), interface: Interface::from_json(controls).unwrap() }
+    }
+
+    #[test]
+    fn controls_need_contacts_alone() {
+        let interface = Interface::from_json(r#"[
+            { "type": "pad", "id": "pad", "x": 0, "y": 0, "width": 10, "height": 10, "address": "/pad" },
+            { "type": "vert_slider", "id": "v", "x": 10, "y": 0, "width": 10, "height": 50, "address": "/v" },
+            { "type": "horz_slider", "id": "h", "x": 20, "y": 0, "width": 50, "height": 10, "address": "/h" },
+            { "type": "keyboard", "id": "keys", "x": 0, "y": 60, "width": 100, "height": 20, "address": "/keys" }
+        ]"#).unwrap();
+        assert_eq!(required_content(&interface), (frame::Mask::CONTACTS, contact::Mask::empty()));
+        // a reload may add controls to an empty page
+        assert_eq!(required_content(&Interface::default()), (frame::Mask::CONTACTS, contact::Mask::empty()));
+    }
+
+    #[test]
+    fn reload_keeps_the_page_and_held_contacts() {
+        let pad = r#"{ "type": "pad", "id": "pad", "x": 0, "y": 0, "width": 10, "height": 10, "address": "/pad" }"#;
+        let slider = r#"{ "type": "horz_slider", "id": "h", "x": 20, "y": 0, "width": 50, "height": 10, "address": "/h" }"#;
+        let mut pages = Pages::new(vec![page("a", "[]"), page("b", &format!("[{}, {}]", slider, pad))]).unwrap();
+        pages.set_current(1);
+        let mut driver = Driver::new(pages.page().interface.clone(), FilterConfig::default(), HashMap::new());
+        let (now, mut events) = (Instant::now(), vec![]);
+        driver.process(&[contact(1, State::CONTACT_START, 5.0, 5.0), contact(2, State::CONTACT_START, 30.0, 5.0)], now, &mut events);
+        assert_eq!(events.len(), 2);
+
+        // the page moves to the front, keeping the pad but dropping the slider
+        let mut new = Pages::new(vec![page("b", &format!("[{}]", pad)), page("a", "[]")]).unwrap();
+        new.follow(&pages);
+        assert_eq!((new.current(), new.page().name.as_str()), (0, "b"));
+
+        let interface = new.page().interface.clone();
+        events.clear();
+        driver.release_removed(&interface, &mut events);
+        assert_eq!(events.iter().map(|e| (e.contact, e.phase)).collect::<Vec<_>>(), vec![(2, Phase::Release)]);
+        driver.update_interface(interface);
+
+        events.clear();
+        driver.process(&[contact(1, State::CONTACT_MOVE, 6.0, 5.0), contact(2, State::CONTACT_MOVE, 31.0, 5.0)], now, &mut events);
+        assert_eq!(events.iter().map(|e| (e.control, e.contact, e.phase)).collect::<Vec<_>>(), vec![(0, 1, Phase::Move)]);
+    }
+}
@@ -0,0 +1,186 @@
+// OSC encoding of a frame's messages into buffers kept from frame to frame,
+// so that sending doesn't allocate once they've grown to fit. rosc 0.1 only
+// encodes into a new Vec per packet, hence the copy of its wire format here.
+
+use std::ops::Range;
+use std::time::SystemTime;
+
+use crate::driver::ControlEvent;
+use crate::interface::{Arg, Control};
+use crate::osc;
+
+struct Encoded {
+    bytes: Range<usize>,
+    addr: Range<usize>,
+}
+
+/// The messages of one frame, each the control's args, a keyboard's note
+/// and the value, encoded.
+#[derive(Default)]
+pub struct FrameEncoder {
+    messages: Vec<u8>,
+    encoded: Vec<Encoded>,
+    packet: Vec<u8>,
+}
+
+fn pad(buf: &mut Vec<u8>) {
+    let len = rosc::encoder::pad(buf.len() as u64) as usize;
+    buf.resize(len, 0);
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+    pad(buf);
+}
+
+impl FrameEncoder {
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.encoded.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.encoded.is_empty()
+    }
+
+    /// Encode the message for an event, if its control sends one.
+    pub fn push(&mut self, control: &Control, event: &ControlEvent) {
+        let value = match osc::value(control, event) {
+            Some(value) => value,
+            None => return,
+        };
+        let note = osc::note(control, event);
+
+        let buf = &mut self.messages;
+        let start = buf.len();
+        push_str(buf, &control.address);
+        let addr = start..start + control.address.len();
+
+        buf.push(b',');
+        for arg in &control.args {
+            buf.push(match arg {
+                Arg::Int(_) => b'i',
+                Arg::Float(_) => b'f',
+                Arg::Str(_) => b's',
+            });
+        }
+        if note.is_some() {
+            buf.push(b'i');
+        }
+        buf.push(b'f');
+        buf.push(0);
+        pad(buf);
+
+        for arg in &control.args {
+            match arg {
+                Arg::Int(i) => buf.extend_from_slice(&i.to_be_bytes()),
+                Arg::Float(f) => buf.extend_from_slice(&f.to_bits().to_be_bytes()),
+                Arg::Str(s) => push_str(buf, s),
+            }
+        }
+        if let Some(note) = note {
+            buf.extend_from_slice(&note.to_be_bytes());
+        }
+        buf.extend_from_slice(&value.to_bits().to_be_bytes());
+
+        self.encoded.push(Encoded { bytes: start..buf.len(), addr });
+    }
+
+    /// Each message with its address.
+    pub fn messages(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.encoded.iter().map(move |e| {
+            let addr = std::str::from_utf8(&self.messages[e.addr.clone()]).unwrap_or("");
+            (addr, &self.messages[e.bytes.clone()])
+        })
+    }
+
+    /// The messages `accept` takes by address as one bundle at `time`, none
+    /// if it takes none of them.
+    pub fn bundle<F: Fn(&str) -> bool>(&mut self, time: SystemTime, accept: F) -> Option<&[u8]> {
+        let (secs, frac) = osc::ntp_time(time);
+        self.packet.clear();
+        push_str(&mut self.packet, "#bundle");
+        self.packet.extend_from_slice(&secs.to_be_bytes());
+        self.packet.extend_from_slice(&frac.to_be_bytes());
+        let mut any = false;
+        for e in &self.encoded {
+            let addr = std::str::from_utf8(&self.messages[e.addr.clone()]).unwrap_or("");
+            if accept(addr) {
+                self.packet.extend_from_slice(&(e.bytes.len() as u32).to_be_bytes());
+                self.packet.extend_from_slice(&self.messages[e.bytes.clone()]);
+                any = true;
+            }
+        }
+        if any { Some(&self.packet) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use rosc::{encoder, OscBundle, OscMessage, OscPacket, OscType};
+
+    use super::*;
+    use crate::driver::Phase;
+    use crate::interface::Interface;
+
+    const INTERFACE: &str = r#"[
+        {"id": "pad", "type": "pad", "address": "/pad", "args": [1, 0.5, "hi"],
+         "x": 0, "y": 0, "width": 10, "height": 10},
+        {"id": "keys", "type": "keyboard", "address": "/keys", "low_note": 60, "keys": 12,
+         "x": 10, "y": 0, "width": 120, "height": 10}
+    ]"#;
+
+    // the message rosc would encode for an event
+    fn message(control: &Control, event: &ControlEvent) -> Option<OscMessage> {
+        osc::value(control, event).map(|value| {
+            let mut args: Vec<OscType> = control.args.iter().map(Arg::to_osc).collect();
+            if let Some(note) = osc::note(control, event) {
+                args.push(OscType::Int(note));
+            }
+            args.push(OscType::Float(value));
+            OscMessage { addr: control.address.clone(), args: Some(args) }
+        })
+    }
+
+    fn event(control: usize, phase: Phase) -> ControlEvent {
+        ControlEvent { control, contact: 1, phase, x: 0.3, y: 0.4, force: 1000.0, start_x: 0.3, start_y: 0.4 }
+    }
+
+    #[test]
+    fn encodes_as_rosc_does() {
+        let interface = Interface::from_json(INTERFACE).unwrap();
+        let events = [event(0, Phase::Press), event(1, Phase::Move), event(1, Phase::Release)];
+        let mut frame = FrameEncoder::default();
+        for e in &events {
+            frame.push(&interface.controls[e.control], e);
+        }
+        let messages: Vec<_> = events.iter()
+            .filter_map(|e| message(&interface.controls[e.control], e))
+            .collect();
+        assert_eq!(frame.messages().count(), messages.len());
+        for ((addr, bytes), msg) in frame.messages().zip(&messages) {
+            assert_eq!(addr, msg.addr);
+            assert_eq!(bytes, &encoder::encode(&OscPacket::Message(msg.clone())).unwrap()[..]);
+        }
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_500_000_000_250);
+        let bundle = frame.bundle(time, |_| true).unwrap().to_vec();
+        let content = messages.into_iter().map(OscPacket::Message).collect();
+        let (secs, frac) = osc::ntp_time(time);
+        let expected = OscPacket::Bundle(OscBundle { timetag: OscType::Time(secs, frac), content });
+        assert_eq!(bundle, encoder::encode(&expected).unwrap());
+    }
+
+    #[test]
+    fn bundles_only_accepted_messages() {
+        let interface = Interface::from_json(INTERFACE).unwrap();
+        let mut frame = FrameEncoder::default();
+        let e = event(0, Phase::Press);
+        frame.push(&interface.controls[0], &e);
+        assert!(frame.bundle(UNIX_EPOCH, |addr| addr == "/keys").is_none());
+        assert!(frame.bundle(UNIX_EPOCH, |addr| addr == "/pad").is_some());
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::fmt;
+
+pub const MAX_MEDIAN_WINDOW: usize = 31;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterSpec {
+    // exponential moving average, alpha in (0, 1], 1 means no smoothing
+    Ema { alpha: f32 },
+    // One Euro filter (Casiez et al. 2012), cutoffs in Hz
+    OneEuro { min_cutoff: f32, beta: f32, d_cutoff: f32 },
+    // median of the last window samples
+    Median { window: usize },
+    // hysteresis, output only follows once input moves more than width away
+    Deadband { width: f32 },
+    // limit rate of change to rate units per second
+    Slew { rate: f32 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterError(pub String);
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid filter: {}", self.0)
+    }
+}
+
+/// Filters applied to a single control, one chain for the contact position
+/// (applied to x and y separately) and one for its force.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FilterConfig {
+    pub position: Vec<FilterSpec>,
+    pub force: Vec<FilterSpec>,
+}
+
+impl FilterConfig {
+    /// Set a chain by channel name, `position` or `force`.
+    pub fn set(&mut self, channel: &str, chain: Vec<FilterSpec>) -> Result<(), FilterError> {
+        match channel {
+            "position" => self.position = chain,
+            "force" => self.force = chain,
+            _ => return Err(FilterError(format!("unknown filter channel '{}'", channel))),
+        }
+        Ok(())
+    }
+
+    /// Channels set in `other` replace those in `self`.
+    pub fn merge(&mut self, other: &FilterConfig) {
+        if !other.position.is_empty() {
+            self.position = other.position.clone();
+        }
+        if !other.force.is_empty() {
+            self.force = other.force.clone();
+        }
+    }
+}
+
+impl FilterSpec {
+    /// Parse a filter chain, for example `median(window=5)+ema(alpha=0.3)`.
+    /// Parameters left out take their default values.
+    pub fn parse_chain(spec: &str) -> Result<Vec<FilterSpec>, FilterError> {
+        spec.split('+')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(FilterSpec::parse)
+            .collect()
+    }
+
+    pub fn parse(spec: &str) -> Result<FilterSpec, FilterError> {
+        let (name, params) = match spec.find('(') {
+            Some(open) => {
+                if !spec.ends_with(')') {
+                    return Err(FilterError(format!("missing ')' in '{}'", spec)));
+                }
+                (spec[..open].trim(), &spec[open + 1..spec.len() - 1])
+            }
+            None => (spec.trim(), ""),
+        };
+
+        let mut filter = match name {
+            "ema" => FilterSpec::Ema { alpha: 0.5 },
+            "one_euro" => FilterSpec::OneEuro { min_cutoff: 1.0, beta: 0.007, d_cutoff: 1.0 },
+            "median" => FilterSpec::Median { window: 5 },
+            "deadband" => FilterSpec::Deadband { width: 0.5 },
+            "slew" => FilterSpec::Slew { rate: 1000.0 },
+            _ => return Err(FilterError(format!("unknown filter '{}'", name))),
+        };
+
+        for param in params.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut kv = param.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim();
+            let value = kv.next()
+                .and_then(|v| v.trim().parse::<f32>().ok())
+                .ok_or_else(|| FilterError(format!("bad parameter '{}' for '{}'", param, name)))?;
+            filter.set_param(key, value)?;
+        }
+
+        filter.validate()?;
+        Ok(filter)
+    }
+
+    fn set_param(&mut self, key: &str, value: f32) -> Result<(), FilterError> {
+        match (self, key) {
+            (FilterSpec::Ema { alpha }, "alpha") => *alpha = value,
+            (FilterSpec::OneEuro { min_cutoff, .. }, "min_cutoff") => *min_cutoff = value,
+            (FilterSpec::OneEuro { beta, .. }, "beta") => *beta = value,
+            (FilterSpec::OneEuro { d_cutoff, .. }, "d_cutoff") => *d_cutoff = value,
+            (FilterSpec::Median { window }, "window") => {
+                if value < 1.0 || value.fract() != 0.0 {
+                    return Err(FilterError(format!("median window must be a positive whole number, not {}", value)));
+                }
+                *window = value as usize;
+            }
+            (FilterSpec::Deadband { width }, "width") => *width = value,
+            (FilterSpec::Slew { rate }, "rate") => *rate = value,
+            (filter, _) => return Err(FilterError(format!("unknown parameter '{}' for {:?}", key, filter))),
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), FilterError> {
+        let ok = match *self {
+            FilterSpec::Ema { alpha } => alpha > 0.0 && alpha <= 1.0,
+            FilterSpec::OneEuro { min_cutoff, beta, d_cutoff } => min_cutoff > 0.0 && beta >= 0.0 && d_cutoff > 0.0,
+            FilterSpec::Median { window } => (1..=MAX_MEDIAN_WINDOW).contains(&window),
+            FilterSpec::Deadband { width } => width >= 0.0,
+            FilterSpec::Slew { rate } => rate > 0.0,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(FilterError(format!("parameter out of range in {:?}", self)))
+        }
+    }
+}
+
+impl fmt::Display for FilterSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FilterSpec::Ema { alpha } => write!(f, "ema(alpha={})", alpha),
+            FilterSpec::OneEuro { min_cutoff, beta, d_cutoff } =>
+                write!(f, "one_euro(min_cutoff={},beta={},d_cutoff={})", min_cutoff, beta, d_cutoff),
+            FilterSpec::Median { window } => write!(f, "median(window={})", window),
+            FilterSpec::Deadband { width } => write!(f, "deadband(width={})", width),
+            FilterSpec::Slew { rate } => write!(f, "slew(rate={})", rate),
+        }
+    }
+}
+
+fn lowpass_alpha(cutoff: f32, dt: f32) -> f32 {
+    let tau = 1.0 / (2.0 * PI * cutoff);
+    1.0 / (1.0 + tau / dt)
+}
+
+struct Filter {
+    spec: FilterSpec,
+    prev: Option<f32>,
+    prev_input: f32,
+    deriv: f32,
+    window: VecDeque<f32>,
+    sorted: Vec<f32>,
+}
+
+impl Filter {
+    fn new(spec: FilterSpec) -> Self {
+        let window = match spec {
+            FilterSpec::Median { window } => window,
+            _ => 0,
+        };
+        Filter {
+            spec,
+            prev: None,
+            prev_input: 0.0,
+            deriv: 0.0,
+            window: VecDeque::with_capacity(window),
+            sorted: Vec::with_capacity(window),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.prev = None;
+        self.prev_input = 0.0;
+        self.deriv = 0.0;
+        self.window.clear();
+        self.sorted.clear();
+    }
+
+    fn apply(&mut self, x: f32, dt: f32) -> f32 {
+        let prev = match self.prev {
+            Some(prev) => prev,
+            None => {
+                // first sample of a contact passes straight through
+                self.prev = Some(x);
+                self.prev_input = x;
+                if let FilterSpec::Median { .. } = self.spec {
+                    self.window.push_back(x);
+                }
+                return x;
+            }
+        };
+        let dt = dt.max(1e-4);
+
+        let y = match self.spec {
+            FilterSpec::Ema { alpha } => prev + alpha * (x - prev),
+            FilterSpec::OneEuro { min_cutoff, beta, d_cutoff } => {
+                let dx = (x - self.prev_input) / dt;
+                self.deriv += lowpass_alpha(d_cutoff, dt) * (dx - self.deriv);
+                let cutoff = min_cutoff + beta * self.deriv.abs();
+                prev + lowpass_alpha(cutoff, dt) * (x - prev)
+            }
+            FilterSpec::Median { window } => {
+                if self.window.len() == window {
+                    self.window.pop_front();
+                }
+                self.window.push_back(x);
+                self.sorted.clear();
+                self.sorted.extend(self.window.iter());
+                self.sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+                self.sorted[self.sorted.len() / 2]
+            }
+            FilterSpec::Deadband { width } => {
+                if x > prev + width {
+                    x - width
+                } else if x < prev - width {
+                    x + width
+                } else {
+                    prev
+                }
+            }
+            FilterSpec::Slew { rate } => {
+                let max_step = rate * dt;
+                prev + (x - prev).max(-max_step).min(max_step)
+            }
+        };
+
+        self.prev = Some(y);
+        self.prev_input = x;
+        y
+    }
+}
+
+/// Runtime state for a chain of filters over one value stream.
+pub struct Chain(Vec<Filter>);
+
+impl Chain {
+    pub fn new(specs: &[FilterSpec]) -> Self {
+        Chain(specs.iter().cloned().map(Filter::new).collect())
+    }
+
+    /// Start over as if new, keeping the buffers.
+    pub fn reset(&mut self) {
+        self.0.iter_mut().for_each(Filter::reset);
+    }
+
+    /// Filter the next sample, `dt` is the time in seconds since the last one.
+    pub fn apply(&mut self, x: f32, dt: f32) -> f32 {
+        self.0.iter_mut().fold(x, |x, filter| filter.apply(x, dt))
+    }
+}
+
+/// Filter state for a single contact.
+pub struct ContactFilter {
+    x: Chain,
+    y: Chain,
+    force: Chain,
+}
+
+impl ContactFilter {
+    pub fn new(config: &FilterConfig) -> Self {
+        ContactFilter {
+            x: Chain::new(&config.position),
+            y: Chain::new(&config.position),
+            force: Chain::new(&config.force),
+        }
+    }
+
+    /// Make ready for another contact.
+    pub fn reset(&mut self) {
+        self.x.reset();
+        self.y.reset();
+        self.force.reset();
+    }
+
+    pub fn apply(&mut self, x: f32, y: f32, force: f32, dt: f32) -> (f32, f32, f32) {
+        (self.x.apply(x, dt), self.y.apply(y, dt), self.force.apply(force, dt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(spec: FilterSpec, input: &[f32], dt: f32) -> Vec<f32> {
+        let mut chain = Chain::new(&[spec]);
+        input.iter().map(|&x| chain.apply(x, dt)).collect()
+    }
+
+    #[test]
+    fn filters_smooth_as_specified() {
+        assert_eq!(run(FilterSpec::Ema { alpha: 0.5 }, &[0.0, 8.0, 8.0], 0.01), vec![0.0, 4.0, 6.0]);
+        assert_eq!(run(FilterSpec::Median { window: 3 }, &[1.0, 9.0, 2.0, 3.0, 100.0], 0.01),
+                   vec![1.0, 9.0, 2.0, 3.0, 3.0]);
+        assert_eq!(run(FilterSpec::Deadband { width: 1.0 }, &[0.0, 0.5, 3.0, 2.5, 1.0], 0.01),
+                   vec![0.0, 0.0, 2.0, 2.0, 2.0]);
+        assert_eq!(run(FilterSpec::Slew { rate: 100.0 }, &[0.0, 10.0, 10.0, -10.0], 0.05),
+                   vec![0.0, 5.0, 10.0, 5.0]);
+
+        // a still input stays put, a fast one is followed more closely than
+        // at the minimum cutoff alone
+        let slow = FilterSpec::OneEuro { min_cutoff: 1.0, beta: 0.0, d_cutoff: 1.0 };
+        let fast = FilterSpec::OneEuro { min_cutoff: 1.0, beta: 1.0, d_cutoff: 1.0 };
+        assert_eq!(run(slow, &[3.0, 3.0, 3.0], 0.01), vec![3.0, 3.0, 3.0]);
+        let ramp: Vec<f32> = (0..10).map(|i| i as f32 * 10.0).collect();
+        let (slow, fast) = (run(slow, &ramp, 0.01), run(fast, &ramp, 0.01));
+        assert!(slow[9] < fast[9] && fast[9] < ramp[9]);
+    }
+
+    #[test]
+    fn reset_forgets_the_last_contact() {
+        let mut chain = Chain::new(&[FilterSpec::Ema { alpha: 0.5 }]);
+        chain.apply(0.0, 0.01);
+        chain.reset();
+        assert_eq!(chain.apply(8.0, 0.01), 8.0);
+    }
+
+    #[test]
+    fn parses_chains_and_rejects_bad_specs() {
+        assert_eq!(FilterSpec::parse_chain("median(window=3) + ema(alpha=0.25)+slew").unwrap(), vec![
+            FilterSpec::Median { window: 3 },
+            FilterSpec::Ema { alpha: 0.25 },
+            FilterSpec::Slew { rate: 1000.0 },
+        ]);
+        assert_eq!(FilterSpec::parse_chain("").unwrap(), vec![]);
+        let one_euro = FilterSpec::OneEuro { min_cutoff: 2.0, beta: 0.1, d_cutoff: 1.0 };
+        assert_eq!(FilterSpec::parse_chain(&one_euro.to_string()).unwrap(), vec![one_euro]);
+
+        for bad in ["kalman", "ema(alpha=0.5", "ema(alpha=x)", "ema(beta=1)", "ema(alpha=0)",
+                    "median(window=32)", "median(window=2.5)", "median(window=0.9)", "median(window=-3)",
+                    "median(window=inf)", "deadband(width=-1)", "slew(rate=0)", "ema+bogus"] {
+            assert!(FilterSpec::parse_chain(bad).is_err(), "{}", bad);
+        }
+    }
+}
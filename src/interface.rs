@@ -0,0 +1,384 @@
+use std::fmt;
+use std::io;
+
+use serde_json::Value;
+
+use crate::filter::{FilterConfig, FilterError, FilterSpec};
+
+// maximum force reported by the Morph, in grams
+pub const MAX_FORCE: f32 = 5000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlType {
+    Pad,
+    VertSlider,
+    HorzSlider,
+    // a row of keys dividing the rect left to right, starting at low_note
+    Keyboard { low_note: u8, keys: u8 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x <= self.x + self.width &&
+            y >= self.y && y <= self.y + self.height
+    }
+
+    /// Position relative to the rect, each axis clamped to [0, 1].
+    pub fn normalise(&self, x: f32, y: f32) -> (f32, f32) {
+        let nx = if self.width > 0.0 { (x - self.x) / self.width } else { 0.0 };
+        let ny = if self.height > 0.0 { (y - self.y) / self.height } else { 0.0 };
+        (nx.clamp(0.0, 1.0), ny.clamp(0.0, 1.0))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Float(f32),
+    Str(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pressure {
+    None,
+    Channel,
+    Poly,
+}
+
+/// How a control is sent over MIDI, channels are 0-15.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiMapping {
+    pub channel: u8,
+    // note played by a pad
+    pub note: Option<u8>,
+    // controller sent by a slider, or with a pad's force
+    pub cc: Option<u8>,
+    // send cc as 14-bit, with the LSB on cc + 32
+    pub cc14: bool,
+    pub pressure: Pressure,
+    // a slider sends pitch bend rather than a controller
+    pub pitch_bend: bool,
+}
+
+/// A page to switch to, relative to the current one or by position or name.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PageTarget {
+    Next,
+    Prev,
+    Index(usize),
+    Name(String),
+}
+
+impl PageTarget {
+    pub fn parse(s: &str) -> PageTarget {
+        match s {
+            "next" => PageTarget::Next,
+            "prev" => PageTarget::Prev,
+            _ => s.parse().map(PageTarget::Index).unwrap_or_else(|_| PageTarget::Name(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Control {
+    pub id: String,
+    pub control_type: ControlType,
+    pub rect: Rect,
+    pub address: String,
+    pub args: Vec<Arg>,
+    pub min: f32,
+    pub max: f32,
+    pub filters: FilterConfig,
+    pub midi: Option<MidiMapping>,
+    // pressing the control switches page rather than sending anything
+    pub page: Option<PageTarget>,
+}
+
+impl Control {
+    /// Map a value in [0, 1] into the control's range.
+    pub fn scale(&self, value: f32) -> f32 {
+        self.min + value * (self.max - self.min)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Interface {
+    pub controls: Vec<Control>,
+}
+
+#[derive(Debug)]
+pub enum InterfaceError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for InterfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterfaceError::Io(err) => write!(f, "{}", err),
+            InterfaceError::Json(err) => write!(f, "{}", err),
+            InterfaceError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<io::Error> for InterfaceError {
+    fn from(err: io::Error) -> Self {
+        InterfaceError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for InterfaceError {
+    fn from(err: serde_json::Error) -> Self {
+        InterfaceError::Json(err)
+    }
+}
+
+impl From<FilterError> for InterfaceError {
+    fn from(err: FilterError) -> Self {
+        InterfaceError::Invalid(err.to_string())
+    }
+}
+
+impl Interface {
+    #[cfg(test)]
+    pub fn from_json(json: &str) -> Result<Interface, InterfaceError> {
+        let value: Value = serde_json::from_str(json)?;
+        Interface::from_value(&value)
+    }
+
+    /// The IR is an array of controls, optionally wrapped as `{"controls": [...]}`.
+    pub fn from_value(value: &Value) -> Result<Interface, InterfaceError> {
+        let controls = match value {
+            Value::Array(controls) => controls,
+            Value::Object(obj) => match obj.get("controls") {
+                Some(Value::Array(controls)) => controls,
+                _ => return Err(invalid("expected 'controls' array")),
+            },
+            _ => return Err(invalid("expected array of controls")),
+        };
+
+        let controls = controls.iter()
+            .enumerate()
+            .map(|(index, control)| parse_control(index, control))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (i, control) in controls.iter().enumerate() {
+            if controls[..i].iter().any(|c| c.id == control.id) {
+                return Err(invalid(&format!("duplicate control id '{}'", control.id)));
+            }
+        }
+
+        Ok(Interface { controls })
+    }
+
+    /// Index of the topmost control containing the point; later controls
+    /// are drawn over earlier ones.
+    pub fn hit(&self, x: f32, y: f32) -> Option<usize> {
+        self.controls.iter().rposition(|c| c.rect.contains(x, y))
+    }
+
+    pub fn find(&self, id: &str) -> Option<usize> {
+        self.controls.iter().position(|c| c.id == id)
+    }
+}
+
+fn invalid(msg: &str) -> InterfaceError {
+    InterfaceError::Invalid(msg.to_string())
+}
+
+fn get_f32(obj: &serde_json::Map<String, Value>, key: &str) -> Option<f32> {
+    obj.get(key).and_then(Value::as_f64).map(|v| v as f32)
+}
+
+fn parse_control(index: usize, value: &Value) -> Result<Control, InterfaceError> {
+    let obj = value.as_object()
+        .ok_or_else(|| invalid(&format!("control {} is not an object", index)))?;
+
+    let control_type = match obj.get("type").and_then(Value::as_str) {
+        Some("pad") => ControlType::Pad,
+        Some("vert_slider") => ControlType::VertSlider,
+        Some("horz_slider") => ControlType::HorzSlider,
+        Some("keyboard") => {
+            let low_note = obj.get("low_note").and_then(Value::as_u64).unwrap_or(48);
+            let keys = obj.get("keys").and_then(Value::as_u64).unwrap_or(12);
+            if keys == 0 || low_note.checked_add(keys).is_none_or(|n| n > 128) {
+                return Err(invalid(&format!("control {} has invalid keyboard range", index)));
+            }
+            ControlType::Keyboard { low_note: low_note as u8, keys: keys as u8 }
+        }
+        Some(other) => return Err(invalid(&format!("control {} has unknown type '{}'", index, other))),
+        None => return Err(invalid(&format!("control {} has no type", index))),
+    };
+
+    let id = match obj.get("id") {
+        Some(Value::String(id)) => id.clone(),
+        Some(Value::Number(id)) => id.to_string(),
+        _ => index.to_string(),
+    };
+
+    let rect = match (get_f32(obj, "x"), get_f32(obj, "y"), get_f32(obj, "width"), get_f32(obj, "height")) {
+        (Some(x), Some(y), Some(width), Some(height)) if width >= 0.0 && height >= 0.0 =>
+            Rect { x, y, width, height },
+        _ => return Err(invalid(&format!("control '{}' has invalid geometry", id))),
+    };
+
+    let page = match obj.get("page") {
+        None => None,
+        Some(Value::String(page)) => Some(PageTarget::parse(page)),
+        Some(Value::Number(page)) if page.is_u64() => Some(PageTarget::Index(page.as_u64().unwrap() as usize)),
+        Some(_) => return Err(invalid(&format!("control '{}' has invalid page", id))),
+    };
+
+    // page switches don't send, so need no address
+    let address = match obj.get("address").and_then(Value::as_str) {
+        Some(address) if address.starts_with('/') => address.to_string(),
+        None if page.is_some() => String::new(),
+        _ => return Err(invalid(&format!("control '{}' has no valid OSC address", id))),
+    };
+
+    let args = match obj.get("args") {
+        None => vec![],
+        Some(Value::Array(args)) => args.iter()
+            .map(|arg| parse_arg(&id, arg))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(arg) => vec![parse_arg(&id, arg)?],
+    };
+
+    let min = get_f32(obj, "min").unwrap_or(0.0);
+    let max = get_f32(obj, "max").unwrap_or(1.0);
+
+    let mut filters = FilterConfig::default();
+    match obj.get("filters") {
+        None => (),
+        Some(Value::Object(channels)) => {
+            for (channel, spec) in channels {
+                let spec = spec.as_str()
+                    .ok_or_else(|| invalid(&format!("control '{}' filter '{}' must be a string", id, channel)))?;
+                filters.set(channel, FilterSpec::parse_chain(spec)?)?;
+            }
+        }
+        Some(_) => return Err(invalid(&format!("control '{}' filters must be an object", id))),
+    }
+
+    let midi = match obj.get("midi") {
+        None => None,
+        Some(Value::Object(midi)) => Some(parse_midi(&id, midi)?),
+        Some(_) => return Err(invalid(&format!("control '{}' midi must be an object", id))),
+    };
+
+    Ok(Control { id, control_type, rect, address, args, min, max, filters, midi, page })
+}
+
+fn parse_midi(id: &str, obj: &serde_json::Map<String, Value>) -> Result<MidiMapping, InterfaceError> {
+    let data_byte = |key: &str| -> Result<Option<u8>, InterfaceError> {
+        match obj.get(key) {
+            None => Ok(None),
+            Some(v) => match v.as_u64() {
+                Some(n) if n < 128 => Ok(Some(n as u8)),
+                _ => Err(invalid(&format!("control '{}' midi {} must be 0-127", id, key))),
+            },
+        }
+    };
+
+    // channels are 1-16 in the IR, as musicians count them
+    let channel = match obj.get("channel").map(Value::as_u64) {
+        None => 0,
+        Some(Some(c)) if (1..=16).contains(&c) => c as u8 - 1,
+        Some(_) => return Err(invalid(&format!("control '{}' midi channel must be 1-16", id))),
+    };
+
+    let cc = data_byte("cc")?;
+    let cc14 = obj.get("cc14").and_then(Value::as_bool).unwrap_or(false);
+    if cc14 && cc.is_some_and(|cc| cc >= 32) {
+        return Err(invalid(&format!("control '{}' 14-bit midi cc must be 0-31", id)));
+    }
+
+    let pressure = match obj.get("pressure").and_then(Value::as_str) {
+        None | Some("none") => Pressure::None,
+        Some("channel") => Pressure::Channel,
+        Some("poly") => Pressure::Poly,
+        Some(other) => return Err(invalid(&format!("control '{}' has unknown midi pressure '{}'", id, other))),
+    };
+
+    Ok(MidiMapping {
+        channel,
+        note: data_byte("note")?,
+        cc,
+        cc14,
+        pressure,
+        pitch_bend: obj.get("pitch_bend").and_then(Value::as_bool).unwrap_or(false),
+    })
+}
+
+fn parse_arg(id: &str, value: &Value) -> Result<Arg, InterfaceError> {
+    match value {
+        Value::Number(n) if n.is_i64() => Ok(Arg::Int(n.as_i64().unwrap() as i32)),
+        Value::Number(n) => Ok(Arg::Float(n.as_f64().unwrap_or(0.0) as f32)),
+        Value::String(s) => Ok(Arg::Str(s.clone())),
+        _ => Err(invalid(&format!("control '{}' has unsupported argument {}", id, value))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAD: &str = r#""type": "pad", "address": "/pad", "x": 0, "y": 0, "width": 10, "height": 10"#;
+
+    fn error(json: &str) -> String {
+        match Interface::from_json(json) {
+            Err(InterfaceError::Invalid(msg)) => msg,
+            other => panic!("{}: expected invalid, got {:?}", json, other),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_interfaces() {
+        assert!(matches!(Interface::from_json("[{"), Err(InterfaceError::Json(_))));
+        assert_eq!(error("3"), "expected array of controls");
+        assert_eq!(error(r#"{"pads": []}"#), "expected 'controls' array");
+        assert_eq!(error("[1]"), "control 0 is not an object");
+        assert_eq!(error(r#"[{"x": 0}]"#), "control 0 has no type");
+        assert_eq!(error(r#"[{"type": "knob"}]"#), "control 0 has unknown type 'knob'");
+        assert_eq!(error(r#"[{"type": "keyboard", "keys": 0}]"#), "control 0 has invalid keyboard range");
+        assert_eq!(error(r#"[{"type": "keyboard", "low_note": 120, "keys": 12}]"#),
+                   "control 0 has invalid keyboard range");
+        assert_eq!(error(r#"[{"type": "keyboard", "low_note": 1, "keys": 18446744073709551615}]"#),
+                   "control 0 has invalid keyboard range");
+        assert_eq!(error(r#"[{"id": "a", "type": "pad", "address": "/a", "x": 0, "y": 0, "width": -1, "height": 1}]"#),
+                   "control 'a' has invalid geometry");
+        assert_eq!(error(r#"[{"id": "a", "type": "pad", "x": 0, "y": 0, "width": 1, "height": 1}]"#),
+                   "control 'a' has no valid OSC address");
+        assert_eq!(error(&format!(r#"[{{"id": "a", {}, "page": true}}]"#, PAD)), "control 'a' has invalid page");
+        assert_eq!(error(&format!(r#"[{{"id": "a", {}, "args": [null]}}]"#, PAD)),
+                   "control 'a' has unsupported argument null");
+        assert_eq!(error(&format!(r#"[{{"id": "a", {}, "filters": "ema"}}]"#, PAD)),
+                   "control 'a' filters must be an object");
+        assert_eq!(error(&format!(r#"[{{"id": "a", {}, "filters": {{"position": 1}}}}]"#, PAD)),
+                   "control 'a' filter 'position' must be a string");
+        assert_eq!(error(&format!(r#"[{{"id": "a", {}, "filters": {{"position": "kalman"}}}}]"#, PAD)),
+                   "invalid filter: unknown filter 'kalman'");
+        assert_eq!(error(&format!(r#"[{{"id": "a", {}}}, {{"id": "a", {}}}]"#, PAD, PAD)),
+                   "duplicate control id 'a'");
+    }
+
+    #[test]
+    fn rejects_invalid_midi() {
+        let midi = |midi: &str| error(&format!(r#"[{{"id": "a", {}, "midi": {}}}]"#, PAD, midi));
+        assert_eq!(midi("1"), "control 'a' midi must be an object");
+        assert_eq!(midi(r#"{"channel": 17}"#), "control 'a' midi channel must be 1-16");
+        assert_eq!(midi(r#"{"note": 128}"#), "control 'a' midi note must be 0-127");
+        assert_eq!(midi(r#"{"cc": 40, "cc14": true}"#), "control 'a' 14-bit midi cc must be 0-31");
+        assert_eq!(midi(r#"{"pressure": "key"}"#), "control 'a' has unknown midi pressure 'key'");
+    }
+}
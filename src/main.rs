@@ -1,8 +1,908 @@
 #[macro_use]
 extern crate bitflags;
+#[macro_use]
+extern crate log;
 
 mod sensel;
 use sensel::*;
 
+mod filter;
+mod interface;
+mod driver;
+mod osc;
+mod transport;
+mod control;
+mod oscquery;
+mod midi;
+mod output;
+mod smf;
+mod pages;
+mod watch;
+mod svg;
+mod session;
+mod render;
+mod monitor;
+mod config;
+mod encoder;
+mod stats;
+mod realtime;
+#[cfg(any(test, feature = "bench"))]
+mod allocs;
+#[cfg(any(test, feature = "bench"))]
+mod bench;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use getopts::{Matches, Options};
+use rosc::OscMessage;
+
+#[cfg(feature = "bench")]
+use bench::Bench;
+use config::Config;
+use control::{Command, ControlServer, Status};
+use device::{Device, ScanningDevice};
+use driver::{Driver, Phase};
+use filter::{FilterConfig, FilterSpec};
+use interface::PageTarget;
+use midi::{MidiOutput, MidiSink, RawSink};
+use oscquery::Namespace;
+use monitor::Monitor;
+use output::Outputs;
+use reader::{retry_time, Buffering, FrameReader, MAX_READ_FAILURES};
+use pages::{Pages, Swipe};
+use render::Scene;
+use scan_mode::SCAN_MODE_ASYNC;
+use session::{Session, SessionWriter};
+use settings::DeviceSettings;
+use smf::SmfRecorder;
+use stats::Stats;
+use transport::{Destination, OscSender};
+use watch::Watcher;
+
+const POWER_BUTTON_POLL: Duration = Duration::from_millis(100);
+const WATCH_POLL: Duration = Duration::from_millis(500);
+const STATS_PERIOD: Duration = Duration::from_secs(1);
+// passes over a session with --bench, the first one warming up
+#[cfg(feature = "bench")]
+const BENCH_RUNS: usize = 6;
+
+fn print_usage(program: &str, opts: &Options) {
+    let brief = format!("Usage: {0} [options] <interface.json|interface.svg>...\n       \
+                         {0} [--serial SERIAL|--port PATH] --regs [NAME[=VALUE]...]", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
+}
+
+// parse ID.CHANNEL=SPEC
+fn parse_control_filter(arg: &str, overrides: &mut BTreeMap<String, FilterConfig>) -> Result<(), String> {
+    let mut parts = arg.splitn(2, '=');
+    let target = parts.next().unwrap_or("");
+    let spec = parts.next().ok_or_else(|| format!("expected ID.CHANNEL=SPEC, got '{}'", arg))?;
+    let dot = target.rfind('.').ok_or_else(|| format!("expected ID.CHANNEL=SPEC, got '{}'", arg))?;
+    let chain = FilterSpec::parse_chain(spec).map_err(|e| e.to_string())?;
+    overrides.entry(target[..dot].to_string())
+        .or_default()
+        .set(&target[dot + 1..], chain)
+        .map_err(|e| e.to_string())
+}
+
+// the config file's settings, overridden by the command line
+fn read_config(matches: &Matches) -> Result<Config, String> {
+    let mut config = match matches.opt_str("config") {
+        Some(path) => Config::from_file(&path)?,
+        None => Config::default(),
+    };
+    if !matches.free.is_empty() {
+        config.interfaces = matches.free.clone();
+    }
+    if let Some(serial) = matches.opt_str("serial") {
+        config.device.serial = Some(serial);
+    }
+    if let Some(port) = matches.opt_str("port") {
+        config.device.port = Some(port);
+    }
+    for arg in matches.opt_strs("set") {
+        config.device.set_arg(&arg)?;
+    }
+    if let Some(spec) = matches.opt_str("position-filter") {
+        config.filters.position = FilterSpec::parse_chain(&spec).map_err(|e| e.to_string())?;
+    }
+    if let Some(spec) = matches.opt_str("force-filter") {
+        config.filters.force = FilterSpec::parse_chain(&spec).map_err(|e| e.to_string())?;
+    }
+    for arg in matches.opt_strs("control-filter") {
+        parse_control_filter(&arg, &mut config.control_filters)?;
+    }
+
+    let outputs = &mut config.outputs;
+    if matches.opt_present("output") {
+        outputs.osc = matches.opt_strs("output");
+    }
+    if matches.opt_present("no-bundle") {
+        outputs.bundle = false;
+    }
+    if let Some(ms) = matches.opt_str("latency") {
+        outputs.latency = ms.parse().map_err(|_| format!("Invalid latency '{}'", ms))?;
+    }
+    if let Some(addr) = matches.opt_str("control") {
+        outputs.control = Some(addr);
+    }
+    if let Some(port) = matches.opt_str("oscquery") {
+        outputs.oscquery = Some(port.parse().map_err(|_| format!("Invalid OSCQuery port '{}'", port))?);
+    }
+    if let Some(sink) = matches.opt_str("midi") {
+        outputs.midi = Some(sink);
+    }
+    if let Some(path) = matches.opt_str("record") {
+        outputs.record = Some(path);
+    }
+    if matches.opt_present("mpe") {
+        outputs.mpe = Some(match matches.opt_str("mpe") {
+            Some(m) => m.parse().map_err(|_| format!("Invalid MPE member channel count '{}'", m))?,
+            None => midi::MPE_MAX_MEMBERS,
+        });
+    }
+    if matches.opt_present("page-swipe") {
+        config.page_swipe = Some(match matches.opt_str("page-swipe") {
+            Some(f) => f.parse().map_err(|_| format!("Invalid page swipe finger count '{}'", f))?,
+            None => 3,
+        });
+    }
+
+    if let Some(members) = outputs.mpe {
+        if !(1..=midi::MPE_MAX_MEMBERS).contains(&members) {
+            return Err(format!("Invalid MPE member channel count '{}'", members));
+        }
+    }
+    if let Some(fingers) = config.page_swipe {
+        if fingers < 2 {
+            return Err(format!("Invalid page swipe finger count '{}'", fingers));
+        }
+    }
+    Ok(config)
+}
+
+fn load_pages<P: AsRef<std::path::Path>>(paths: &[P]) -> Result<Pages, String> {
+    Pages::from_files(paths).map_err(|e| {
+        let names: Vec<_> = paths.iter().map(|p| p.as_ref().display().to_string()).collect();
+        format!("Failed to load {}: {}", names.join(", "), e)
+    })
+}
+
+// release every held contact, letting their controls know
+fn release_all(driver: &mut Driver, outputs: &mut Outputs, captured: SystemTime) {
+    let mut events = vec![];
+    driver.release_all(&mut events);
+    outputs.emit(driver.interface(), &events, captured);
+}
+
+// open a MIDI sink: alsa[:CLIENT:PORT] or raw:PATH
+fn open_midi(sink: &str) -> Result<Box<dyn MidiSink>, String> {
+    if let Some(path) = sink.strip_prefix("raw:") {
+        let file = std::fs::OpenOptions::new().write(true).create(true).truncate(false).open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        return Ok(Box::new(RawSink(file)));
+    }
+    if sink == "alsa" || sink.starts_with("alsa:") {
+        return open_alsa(sink.split_once(':').map(|x| x.1));
+    }
+    Err(format!("Unknown MIDI output '{}'", sink))
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+fn open_alsa(dest: Option<&str>) -> Result<Box<dyn MidiSink>, String> {
+    midi::AlsaSink::new("sensel_osc", dest)
+        .map(|sink| Box::new(sink) as Box<dyn MidiSink>)
+        .map_err(|e| format!("Failed to open ALSA sequencer: {}", e))
+}
+
+#[cfg(not(all(target_os = "linux", feature = "alsa")))]
+fn open_alsa(_dest: Option<&str>) -> Result<Box<dyn MidiSink>, String> {
+    Err("ALSA MIDI output needs the 'alsa' feature on Linux".to_string())
+}
+
+// on the serial port if given, otherwise through libsensel
+fn open(config: &config::DeviceConfig) -> device::BaseDevice {
+    match config.port {
+        Some(ref port) => open_port(port),
+        None => open_device(config.serial.as_deref()),
+    }
+}
+
+fn open_device(serial: Option<&str>) -> device::BaseDevice {
+    library::load().unwrap_or_else(|e| fail(&e));
+    let list = device::get_device_list().unwrap_or_else(|_| fail("Failed to list Sensel devices"));
+    let found = match serial {
+        Some(serial) => list.as_slice().iter().find(|id| id.get_serial_num().trim_end_matches('\0') == serial),
+        None => list.as_slice().first(),
+    };
+    let id = match (found, serial) {
+        (Some(id), _) => *id,
+        (None, Some(serial)) => fail(&format!("No Sensel device with serial {}", serial)),
+        (None, None) => fail("No Sensel device found"),
+    };
+    info!("Opening Sensel device {}", id.get_serial_num());
+    id.open().unwrap_or_else(|_| fail("Failed to open Sensel device"))
+}
+
+fn open_port(port: &str) -> device::BaseDevice {
+    info!("Opening Sensel device on {}", port);
+    serial::open(port).unwrap_or_else(|e| fail(&format!("Failed to open {}: {}", port, e)))
+}
+
+// with no arguments lists every register and its value, otherwise reads
+// NAME or writes NAME=VALUE and reads it back
+fn regs(config: &config::DeviceConfig, args: &[String]) {
+    let device = open(config);
+    if args.is_empty() {
+        for reg in register::ALL {
+            let value = match register::read_named(&device, reg.name) {
+                _ if reg.access == "w" => String::new(),
+                Some(Ok(value)) => value,
+                _ => "failed to read".to_string(),
+            };
+            // registers of variable size have none of their own
+            let size = if reg.size == 0 { "vs".to_string() } else { reg.size.to_string() };
+            let line = format!("0x{:02x} {:<28} {:<2} {:<2} {}", reg.addr, reg.name.to_lowercase(), size, reg.access, value);
+            println!("{}", line.trim_end());
+        }
+        return;
+    }
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        let reg = register::ALL.iter().find(|reg| reg.name.eq_ignore_ascii_case(name))
+            .unwrap_or_else(|| fail(&format!("No register {}", name)));
+        if let Some(value) = value {
+            if let Some(Err(e)) = register::write_named(&device, name, value) {
+                fail(&e);
+            }
+        }
+        if reg.access != "w" {
+            match register::read_named(&device, name) {
+                Some(Ok(value)) => println!("{} {}", name.to_lowercase(), value),
+                _ => fail(&format!("Failed to read {}", name.to_lowercase())),
+            }
+        }
+    }
+}
+
+// light the LED for the current page
+fn show_page<D: Device>(device: &D, outputs: &mut Outputs, pages: &Pages) {
+    let info = device.get_info();
+    let leds = pages.leds(info.num_leds, info.max_led_brightness);
+    if device.set_led_array(&leds).is_err() {
+        warn!("Failed to set page LEDs");
+    } else if let Some(ref mut monitor) = outputs.monitor {
+        monitor.set_leds(&leds);
+    }
+}
+
+// release everything held on the current page and make `index` current
+fn switch_page<D: Device>(
+    device: &D,
+    driver: &mut Driver,
+    outputs: &mut Outputs,
+    pages: &mut Pages,
+    index: usize,
+    captured: SystemTime,
+) {
+    release_all(driver, outputs, captured);
+    pages.set_current(index);
+    let page = pages.page();
+    if let Some(ref namespace) = outputs.namespace {
+        namespace.lock().unwrap().set_interface(&page.interface);
+    }
+    driver.set_interface(page.interface.clone());
+    show_page(device, outputs, pages);
+    outputs.send(control::page_message(index, &page.name));
+    info!("Page {} '{}'", index, page.name);
+}
+
+// reload the interface files, keeping contacts held on controls that are
+// still there. If the files are invalid the old interface stays.
+fn reload<D: Device>(device: &D, driver: &mut Driver, outputs: &mut Outputs, pages: &mut Pages, captured: SystemTime) {
+    let mut new = match load_pages(pages.paths()) {
+        Ok(new) => new,
+        Err(e) => {
+            error!("{}, keeping the current interface", e);
+            return;
+        }
+    };
+    new.follow(pages);
+    let interface = new.page().interface.clone();
+
+    let mut events = vec![];
+    driver.release_removed(&interface, &mut events);
+    outputs.emit(driver.interface(), &events, captured);
+    if let Some(ref namespace) = outputs.namespace {
+        namespace.lock().unwrap().set_interface(&interface);
+    }
+    driver.update_interface(interface);
+
+    let moved = new.current() != pages.current() || new.len() != pages.len();
+    *pages = new;
+    if moved {
+        show_page(device, outputs, pages);
+    }
+    info!("Reloaded interface, page {} '{}'", pages.current(), pages.page().name);
+}
+
+// a sensor size in mm, as WIDTHxHEIGHT
+fn parse_size(arg: &str) -> Result<(f32, f32), String> {
+    let invalid = || format!("Invalid size '{}', expected WIDTHxHEIGHT in mm", arg);
+    let mut parts = arg.splitn(2, 'x');
+    let mut dimension = || parts.next()
+        .and_then(|d| d.trim().parse::<f32>().ok())
+        .filter(|d| d.is_finite() && *d > 0.0)
+        .ok_or_else(invalid);
+    Ok((dimension()?, dimension()?))
+}
+
+// draw each page, with contacts from a session, to an SVG or PNG file,
+// on a sensor of `size`, else the session's, else a Morph's
+fn render(out: &str, pages: &Pages, session: Option<&Session>, frame: Option<&str>, size: Option<(f32, f32)>) -> Result<(), String> {
+    let (width, height) = match (size, session) {
+        (Some(size), _) => size,
+        (None, Some(session)) => (session.width, session.height),
+        (None, None) => (session::MORPH_WIDTH, session::MORPH_HEIGHT),
+    };
+    let contacts = match (session, frame) {
+        (Some(session), Some(frame)) => session.frame(frame)
+            .ok_or_else(|| format!("Session has {} frames, no frame {}", session.frames.len(), frame))?
+            .contacts.clone(),
+        // the whole session, as trails
+        (Some(session), None) => session.frames.iter().flat_map(|f| f.contacts.iter().cloned()).collect(),
+        (None, _) => vec![],
+    };
+
+    let out = std::path::Path::new(out);
+    let png = out.extension().is_some_and(|e| e.eq_ignore_ascii_case("png"));
+    for page in pages.iter() {
+        // a file per page, named after it
+        let path = if pages.len() > 1 {
+            let stem = out.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+            let ext = out.extension().map_or_else(String::new, |e| format!(".{}", e.to_string_lossy()));
+            out.with_file_name(format!("{}-{}{}", stem, page.name, ext))
+        } else {
+            out.to_path_buf()
+        };
+        let scene = Scene { width, height, interface: &page.interface, contacts: contacts.clone() };
+        let data = if png { scene.to_png(render::DEFAULT_SCALE)? } else { scene.to_svg().into_bytes() };
+        std::fs::write(&path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        info!("Rendered page '{}' to {}", page.name, path.display());
+    }
+    Ok(())
+}
+
+// replay a session BENCH_RUNS times and report all but the first
+#[cfg(feature = "bench")]
+fn bench(session: &Session, driver: &mut Driver, outputs: &mut Outputs) {
+    let mut bench = Bench::default();
+    for i in 0..BENCH_RUNS {
+        let run = bench.run(session, driver, outputs);
+        let per_frame = run.allocations as f64 / run.frames.max(1) as f64;
+        let name = if i == 0 { "warm-up".to_string() } else { format!("run {}", i) };
+        let us = |d: Duration| d.as_secs_f64() * 1e6;
+        println!("{}: {} frames, per frame mean {:.1}us, max {:.1}us, {} allocations ({:.2} per frame)", name,
+                 run.frames, us(run.time.mean()), us(run.time.max()), run.allocations, per_frame);
+    }
+}
+
+// apply a control command
+// what every page needs of each frame, with `configured` contact data
+fn required_content(pages: &Pages, configured: contact::Mask) -> (frame::Mask, contact::Mask) {
+    pages.iter()
+        .map(|page| driver::required_content(&page.interface))
+        .fold((frame::Mask::empty(), configured), |(f, c), (pf, pc)| (f | pf, c | pc))
+}
+
+// after the pages change, ask the device for what they need if that changed
+fn require_content(device: ScanningDevice, settings: &mut DeviceSettings, pages: &Pages, configured: contact::Mask) -> (ScanningDevice, bool) {
+    let mut wanted = *settings;
+    (wanted.frame_content, wanted.contacts_mask) = required_content(pages, configured);
+    if wanted == *settings {
+        return (device, false);
+    }
+    let unsupported = device.get_info().unsupported_frame_content(wanted.frame_content);
+    if !unsupported.is_empty() {
+        error!("Device can't send {:?} frames, keeping the frame content", unsupported);
+        return (device, false);
+    }
+    let (device, result) = change_settings(device, settings, wanted);
+    if let Err(e) = result {
+        error!("Failed to change the frame content: {}", e);
+    }
+    (device, true)
+}
+
+// settings are written between scans as at start up, the driver can't go on
+// if scanning doesn't start again
+fn change_settings(device: ScanningDevice, settings: &mut DeviceSettings, wanted: DeviceSettings) -> (ScanningDevice, Result<(), String>) {
+    match wanted.apply_scanning(device) {
+        Ok((device, Ok(()))) => {
+            *settings = wanted;
+            (device, Ok(()))
+        }
+        Ok((device, Err(e))) => (device, Err(e.to_string())),
+        Err(_) => fail("Failed to restart scanning"),
+    }
+}
+
+fn handle_command<D: Device>(
+    device: &D,
+    driver: &mut Driver,
+    outputs: &mut Outputs,
+    pages: &mut Pages,
+    paused: &mut bool,
+    command: Command,
+    captured: SystemTime,
+) -> Result<Option<OscMessage>, String> {
+    match command {
+        Command::ShowLeds(leds) => {
+            if let Some(ref mut monitor) = outputs.monitor {
+                for (led, value) in leds {
+                    monitor.set_led(led, value);
+                }
+            }
+            Ok(None)
+        }
+        // the scan loop writes settings, the control server has already
+        // written the LEDs through the device's remote
+        Command::Set(..) | Command::SetLed(..) | Command::SetLedArray(_)
+        | Command::SetLedLevel(..) | Command::SetLedLevels(_) => Ok(None),
+        Command::Get(query) => {
+            let status = Status { paused: *paused, page: pages.current(), page_name: &pages.page().name };
+            control::query(device, query, &status).map(Some)
+        }
+        Command::LoadInterface(path) => {
+            *pages = load_pages(&[&path])?;
+            info!("Loaded interface {}", path.display());
+            switch_page(device, driver, outputs, pages, 0, captured);
+            Ok(None)
+        }
+        Command::Page(target) => {
+            let index = pages.resolve(&target).ok_or_else(|| format!("no page {:?}", target))?;
+            switch_page(device, driver, outputs, pages, index, captured);
+            Ok(None)
+        }
+        Command::Pause(pause) => {
+            *paused = pause.unwrap_or(!*paused);
+            if *paused {
+                release_all(driver, outputs, captured);
+            }
+            info!("Driver {}", if *paused { "paused" } else { "resumed" });
+            Ok(None)
+        }
+    }
+}
+
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("", "config", "read settings from this file, the other options override it", "FILE.toml");
+    opts.optflagopt("", "dump-config", "write the effective config, to stdout or a file, and exit", "FILE.toml");
+    opts.optopt("", "serial", "use the device with this serial number", "SERIAL");
+    opts.optopt("", "port", "talk to the device on this serial port, without libsensel", "PATH");
+    opts.optmulti("", "set", "set a device setting as in the config file, e.g. max_frame_rate=500", "NAME=VALUE");
+    opts.optopt("", "position-filter", "default filter chain for contact position", "SPEC");
+    opts.optopt("", "force-filter", "default filter chain for contact force", "SPEC");
+    opts.optmulti("", "control-filter", "filter chain for one control, overriding the interface file", "ID.CHANNEL=SPEC");
+    opts.optmulti("o", "output", &format!("send OSC to this destination, may be repeated (default {})", transport::DEFAULT_DESTINATION), "SCHEME://HOST:PORT[=PATTERN,...]");
+    opts.optopt("c", "control", "listen for OSC control messages on this port", "[HOST:]PORT");
+    opts.optopt("", "oscquery", "serve an OSCQuery description of the interface on this port", "PORT");
+    opts.optflag("", "no-bundle", "send each OSC message in its own packet instead of one bundle per frame");
+    opts.optopt("", "latency", "schedule bundles this many milliseconds after the frame was captured (default 0)", "MS");
+    opts.optopt("", "midi", "also send MIDI, to the ALSA sequencer or a raw MIDI device or file", "alsa[:CLIENT:PORT]|raw:PATH");
+    opts.optopt("", "record", "record the MIDI performance to a Standard MIDI File", "FILE.mid");
+    opts.optopt("", "render", "draw the interface to an SVG or PNG file and exit, without a device", "FILE.svg|FILE.png");
+    opts.optopt("", "session", "with --render, draw the contacts of a recorded session", "FILE.jsonl");
+    opts.optopt("", "frame", "with --session, draw only this frame of the session, by number or time", "N|SECSs");
+    opts.optopt("", "size", "with --render, the sensor's size in mm (default the session's, or a Morph's 230x130)", "WIDTHxHEIGHT");
+    #[cfg(feature = "bench")]
+    opts.optopt("", "bench", "replay a recorded session through the interface and outputs as fast as possible, report the time and allocations per frame and exit", "FILE.jsonl");
+    opts.optopt("", "record-contacts", "record every frame's contacts to a session file", "FILE.jsonl");
+    opts.optflag("", "regs", "list the device's registers, or read NAME or write NAME=VALUE given in place of the interface files, and exit");
+    opts.optflag("", "no-watch", "don't reload the interface files when they change");
+    opts.optflagopt("", "page-swipe", "switch pages by swiping left or right with this many fingers (default 3)", "FINGERS");
+    opts.optflag("", "monitor", "show the surface, contacts, control values and OSC sent in the terminal");
+    opts.optflag("", "stats", "measure latency and jitter, send /sensel/stats every second and report on exit");
+    opts.optflagopt("", "realtime", "scan at this SCHED_FIFO priority, Linux only (default 50)", "PRIORITY");
+    opts.optopt("", "cpu", "scan on this CPU, Linux only", "N");
+    opts.optflagopt("", "mpe", &format!("send MIDI as an MPE lower zone with this many member channels (default {})", midi::MPE_MAX_MEMBERS), "MEMBERS");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => fail(&f.to_string()),
+    };
+    // the monitor takes over the terminal, so shows the log itself
+    let log = if matches.opt_present("monitor") {
+        Some(monitor::init_logger())
+    } else {
+        env_logger::init();
+        None
+    };
+
+    if matches.opt_present("h") {
+        print_usage(&program, &opts);
+        return;
+    }
+    let config = read_config(&matches).unwrap_or_else(|e| fail(&e));
+
+    if matches.opt_present("dump-config") {
+        let toml = config.to_toml();
+        match matches.opt_str("dump-config") {
+            Some(path) => std::fs::write(&path, toml)
+                .unwrap_or_else(|e| fail(&format!("Failed to write {}: {}", path, e))),
+            None => print!("{}", toml),
+        }
+        return;
+    }
+    if matches.opt_present("regs") {
+        regs(&config.device, &matches.free);
+        return;
+    }
+    if config.interfaces.is_empty() {
+        print_usage(&program, &opts);
+        return;
+    }
+
+    if let Some(out) = matches.opt_str("render") {
+        let pages = load_pages(&config.interfaces).unwrap_or_else(|e| fail(&e));
+        let session = matches.opt_str("session").map(|path| {
+            Session::read(&path).unwrap_or_else(|e| fail(&format!("Failed to read {}: {}", path, e)))
+        });
+        let frame = matches.opt_str("frame");
+        let size = matches.opt_str("size").map(|size| parse_size(&size).unwrap_or_else(|e| fail(&e)));
+        render(&out, &pages, session.as_ref(), frame.as_deref(), size).unwrap_or_else(|e| fail(&e));
+        return;
+    }
+
+    let mut outputs = config.outputs.osc.clone();
+    if outputs.is_empty() {
+        outputs.push(transport::DEFAULT_DESTINATION.to_string());
+    }
+    let destinations: Vec<Destination> = outputs.iter()
+        .map(|o| o.parse().unwrap_or_else(|e: String| fail(&e)))
+        .collect();
+    let sender = OscSender::new(&destinations)
+        .unwrap_or_else(|e| fail(&format!("Failed to open OSC output: {}", e)));
+    for dest in &destinations {
+        info!("Sending OSC to {}", dest);
+    }
+
+    let bundle = config.outputs.bundle;
+    let latency = Duration::from_millis(config.outputs.latency);
+
+    let mpe = config.outputs.mpe;
+    let midi_output = |sink: Box<dyn MidiSink>| match mpe {
+        Some(members) => MidiOutput::new_mpe(sink, members)
+            .unwrap_or_else(|e| fail(&format!("Failed to configure MPE: {}", e))),
+        None => MidiOutput::new(sink),
+    };
+
+    let mut midi = vec![];
+    if let Some(ref sink) = config.outputs.midi {
+        midi.push(midi_output(open_midi(sink).unwrap_or_else(|e| fail(&e))));
+        info!("Sending MIDI to {}", sink);
+    }
+    if let Some(ref path) = config.outputs.record {
+        let recorder = SmfRecorder::new(path)
+            .unwrap_or_else(|e| fail(&format!("Failed to create {}: {}", path, e)));
+        midi.push(midi_output(Box::new(recorder)));
+        info!("Recording MIDI to {}", path);
+    }
+    if midi.is_empty() && mpe.is_some() {
+        warn!("--mpe has no effect without --midi or --record");
+    }
+
+    #[cfg(feature = "bench")]
+    if let Some(path) = matches.opt_str("bench") {
+        let session = Session::read(&path).unwrap_or_else(|e| fail(&format!("Failed to read {}: {}", path, e)));
+        let pages = load_pages(&config.interfaces).unwrap_or_else(|e| fail(&e));
+        let overrides = config.control_filters.clone().into_iter().collect();
+        let mut driver = Driver::new(pages.page().interface.clone(), config.filters.clone(), overrides);
+        let mut outputs = Outputs { osc: sender, namespace: None, midi, bundle, latency, monitor: None, encoder: Default::default() };
+        bench(&session, &mut driver, &mut outputs);
+        outputs.finish();
+        return;
+    }
+
+    let mut device = open(&config.device);
+    if let Some(gamma) = config.device.led_gamma {
+        device.set_led_gamma(gamma);
+    }
+
+    let mut control_port = None;
+    let server = config.outputs.control.as_ref().map(|addr| {
+        let addr = if addr.contains(':') { addr.clone() } else { format!("127.0.0.1:{}", addr) };
+        let server = ControlServer::bind(&addr, device.remote())
+            .unwrap_or_else(|e| fail(&format!("Failed to listen on {}: {}", addr, e)));
+        control_port = server.local_addr().ok().map(|a| a.port());
+        info!("Listening for control messages on {}", addr);
+        server
+    });
+
+    let mut pages = load_pages(&config.interfaces).unwrap_or_else(|e| fail(&e));
+    let interface = pages.page().interface.clone();
+    if pages.len() > 1 {
+        let names: Vec<_> = pages.iter().map(|p| p.name.as_str()).collect();
+        info!("Pages: {}", names.join(", "));
+    }
+    let mut swipe = config.page_swipe.map(Swipe::new);
+
+    let namespace = config.outputs.oscquery.map(|port| {
+        let namespace = Arc::new(Mutex::new(Namespace::new("sensel_osc", &interface, control_port)));
+        let addr = format!("127.0.0.1:{}", port);
+        let local = oscquery::serve(&addr, namespace.clone())
+            .unwrap_or_else(|e| fail(&format!("Failed to serve OSCQuery on {}: {}", addr, e)));
+        info!("Serving OSCQuery on http://{}", local);
+        namespace
+    });
+    for id in config.control_filters.keys() {
+        if pages.iter().all(|p| p.interface.find(id).is_none()) {
+            warn!("Control filter for unknown control id '{}'", id);
+        }
+    }
+    let overrides = config.control_filters.clone().into_iter().collect();
+    let mut driver = Driver::new(interface, config.filters.clone(), overrides);
+    let mut outputs = Outputs { osc: sender, namespace, midi, bundle, latency, monitor: None, encoder: Default::default() };
+
+    let recording_path = matches.opt_str("record-contacts");
+
+    let mut settings = DeviceSettings::read(&device)
+        .unwrap_or_else(|e| fail(&format!("Failed to configure device: {}", e)));
+    // only what the pages use, whatever the device was set to before, and
+    // the contact data configured or recorded
+    let configured_mask = if recording_path.is_some() {
+        // everything the device can tell us about each contact
+        contact::Mask::all()
+    } else {
+        config.device.contacts_mask.unwrap_or_else(contact::Mask::empty)
+    };
+    let (content, contacts_mask) = required_content(&pages, configured_mask);
+    let unsupported = device.get_info().unsupported_frame_content(content);
+    if !unsupported.is_empty() {
+        fail(&format!("Device can't send {:?} frames", unsupported));
+    }
+    settings.frame_content = content;
+    settings.contacts_mask = contacts_mask;
+    if let Some(mode) = config.device.scan_mode {
+        if mode == SCAN_MODE_ASYNC && !device.get_info().async_scanning {
+            fail("Device can't scan async, only sync");
+        }
+        settings.scan_mode = mode;
+    }
+    for &(setting, value) in &config.device.settings {
+        settings.set(setting.value(value).unwrap_or_else(|e| fail(&e)));
+    }
+    settings.apply(&device)
+        .unwrap_or_else(|e| fail(&format!("Failed to configure device: {}", e)));
+
+    let mut recording = recording_path.map(|path| {
+        let info = device.get_info().sensor_info;
+        let writer = SessionWriter::create(&path, info.width, info.height)
+            .unwrap_or_else(|e| fail(&format!("Failed to create {}: {}", path, e)));
+        info!("Recording contacts to {}", path);
+        writer
+    });
+    if let Some(log) = log {
+        let info = device.get_info();
+        let monitor = Monitor::new(log, info.sensor_info.width, info.sensor_info.height,
+                                   info.num_leds, info.max_led_brightness)
+            .unwrap_or_else(|e| fail(&format!("Failed to start the monitor: {}", e)));
+        outputs.monitor = Some(monitor);
+    }
+    let started = Instant::now();
+    let mut device = device.start_scanning().unwrap_or_else(|_| fail("Failed to start scanning"));
+    if pages.len() > 1 {
+        show_page(&device, &mut outputs, &pages);
+    }
+
+    let buffering = config.device.buffering.unwrap_or(Buffering::All);
+    let mut reader = FrameReader::new(&device, buffering);
+    let mut events = Vec::new();
+    let mut paused = false;
+    let mut power_button = false;
+    let mut power_button_polled = Instant::now();
+    let mut watcher = if matches.opt_present("no-watch") { None } else { Some(Watcher::new(pages.paths())) };
+    let mut watch_polled = Instant::now();
+
+    if matches.opt_present("realtime") {
+        let priority = matches.opt_str("realtime").map_or(Ok(50), |p| p.parse())
+            .unwrap_or_else(|_| fail("--realtime: expected a priority from 1 to 99"));
+        match realtime::set_priority(priority) {
+            Ok(()) => info!("Scanning at real-time priority {}", priority),
+            Err(e) => warn!("{}", e),
+        }
+    }
+    if let Some(cpu) = matches.opt_str("cpu") {
+        let cpu = cpu.parse().unwrap_or_else(|_| fail("--cpu: expected a CPU number"));
+        match realtime::set_affinity(cpu) {
+            Ok(()) => info!("Scanning on CPU {}", cpu),
+            Err(e) => warn!("{}", e),
+        }
+    }
+    let mut stats = if matches.opt_present("stats") { Some(Stats::default()) } else { None };
+    let mut stats_sent = Instant::now();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst))
+        .unwrap_or_else(|e| fail(&format!("Failed to set Ctrl-C handler: {}", e)));
+
+    let mut read_failures = 0;
+    let mut settings_written = false;
+    while running.load(Ordering::SeqCst) {
+        if settings_written {
+            reader.refresh(&device);
+            settings_written = false;
+        }
+        let frames = match reader.read(&device) {
+            Ok(frames) => {
+                read_failures = 0;
+                frames
+            }
+            Err(_) => {
+                read_failures += 1;
+                error!("Failed to read sensor");
+                if read_failures == MAX_READ_FAILURES {
+                    error!("Giving up after {} failed reads", read_failures);
+                    break;
+                }
+                thread::sleep(retry_time(read_failures));
+                continue;
+            }
+        };
+        // the newest frame of the read was captured now, those before it a
+        // frame period apart
+        let now = Instant::now();
+        let captured = SystemTime::now();
+
+        let mut pages_changed = false;
+        if let Some(ref server) = server {
+            while let Some(request) = server.try_recv() {
+                pages_changed |= matches!(request.command, Command::LoadInterface(_));
+                let result = match request.command {
+                    Command::Set(setting, value) => match setting.value(value) {
+                        Ok(value) => {
+                            let mut wanted = settings;
+                            wanted.set(value);
+                            let (restarted, result) = change_settings(device, &mut settings, wanted);
+                            device = restarted;
+                            settings_written = true;
+                            result.map(|_| None)
+                        }
+                        Err(e) => Err(e),
+                    },
+                    command => handle_command(&device, &mut driver, &mut outputs, &mut pages, &mut paused, command, captured),
+                };
+                match result {
+                    Ok(Some(reply)) => server.reply(request.from, reply),
+                    Ok(None) => (),
+                    Err(e) => {
+                        warn!("{}", e);
+                        server.reply(request.from, control::error_message(&e));
+                    }
+                }
+            }
+        }
+
+        if let Some(ref mut watcher) = watcher {
+            if now.duration_since(watch_polled) >= WATCH_POLL {
+                watch_polled = now;
+                // a different interface may have been loaded over OSC
+                if !watcher.is_watching(pages.paths()) {
+                    *watcher = Watcher::new(pages.paths());
+                } else if watcher.changed() {
+                    reload(&device, &mut driver, &mut outputs, &mut pages, captured);
+                    pages_changed = true;
+                }
+            }
+        }
+        if pages_changed {
+            let (restarted, changed) = require_content(device, &mut settings, &pages, configured_mask);
+            device = restarted;
+            settings_written |= changed;
+        }
+
+        // the power button steps through the pages, polled as it costs a USB round trip
+        if pages.len() > 1 && now.duration_since(power_button_polled) >= POWER_BUTTON_POLL {
+            power_button_polled = now;
+            let pressed = device.get_power_button_pressed().unwrap_or(false);
+            if pressed && !power_button {
+                let next = pages.resolve(&PageTarget::Next).unwrap_or(0);
+                switch_page(&device, &mut driver, &mut outputs, &mut pages, next, captured);
+            }
+            power_button = pressed;
+        }
+
+        if let Some(ref mut stats) = stats {
+            if !frames.is_empty() {
+                stats.read(now);
+            }
+            if now.duration_since(stats_sent) >= STATS_PERIOD {
+                stats_sent = now;
+                outputs.send(stats.take_window().message());
+            }
+        }
+        for frame in frames {
+            let frame_started = Instant::now();
+            let now = now.checked_sub(frame.age).unwrap_or(now);
+            let captured = captured - frame.age;
+            let contacts = &frame.contacts;
+            if let Some(ref mut stats) = stats {
+                stats.frame(frame.lost_frame_count);
+            }
+            if let Some(ref mut monitor) = outputs.monitor {
+                monitor.frame(contacts, frame.lost_frame_count);
+            }
+            if let Some(ref mut recording) = recording {
+                if let Err(e) = recording.write_frame(now.duration_since(started), contacts) {
+                    error!("Failed to record contacts: {}", e);
+                }
+            }
+            if paused {
+                continue;
+            }
+
+            events.clear();
+            driver.process(contacts, now, &mut events);
+            outputs.emit(driver.interface(), &events, captured);
+            if let Some(ref mut stats) = stats {
+                if !events.is_empty() {
+                    stats.sent(now, frame_started);
+                }
+            }
+
+            let swiped = swipe.as_mut().and_then(|swipe| swipe.process(contacts));
+            let pressed = events.iter()
+                .filter(|event| event.phase == Phase::Press)
+                .filter_map(|event| driver.interface().controls[event.control].page.clone())
+                .next_back();
+            if let Some(index) = swiped.or(pressed).and_then(|target| pages.resolve(&target)) {
+                switch_page(&device, &mut driver, &mut outputs, &mut pages, index, captured);
+            }
+        }
+
+        if let Some(ref mut monitor) = outputs.monitor {
+            monitor.draw(driver.interface(), &pages.page().name);
+            // Ctrl-C is a key press to a terminal in raw mode
+            if monitor.quit_requested() {
+                running.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+    info!("Shutting down");
+    release_all(&mut driver, &mut outputs, SystemTime::now());
+    outputs.finish();
+    if let Some(mut recording) = recording {
+        if let Err(e) = recording.flush() {
+            error!("Failed to record contacts: {}", e);
+        }
+    }
+    if device.stop_scanning().is_err() {
+        error!("Failed to stop scanning");
+    }
+    if let Some(stats) = stats {
+        print!("{}", stats.total.report());
+    }
+    if read_failures == MAX_READ_FAILURES {
+        process::exit(1);
+    }
 }
@@ -0,0 +1,443 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use crate::driver::{ControlEvent, Phase};
+use crate::interface::{Control, ControlType, MidiMapping, Pressure};
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const POLY_PRESSURE: u8 = 0xA0;
+const CONTROL_CHANGE: u8 = 0xB0;
+const CHANNEL_PRESSURE: u8 = 0xD0;
+const PITCH_BEND: u8 = 0xE0;
+
+const PITCH_BEND_CENTRE: u16 = 8192;
+// MPE timbre, sent from a keyboard contact's vertical position
+const CC_TIMBRE: u8 = 74;
+// MPE's default member channel pitch bend range, in semitones
+pub const MPE_BEND_RANGE: f32 = 48.0;
+pub const MPE_MAX_MEMBERS: u8 = 15;
+
+/// Somewhere to write MIDI, one complete message at a time.
+pub trait MidiSink {
+    fn send(&mut self, msg: &[u8]) -> io::Result<()>;
+
+    /// The capture time of the frame the following messages come from.
+    fn set_time(&mut self, _time: SystemTime) {}
+
+    /// Called once on shutdown, after the last message.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes MIDI as a raw byte stream, for example to a file, a raw MIDI
+/// device such as `/dev/snd/midiC1D0`, or a buffer in tests.
+pub struct RawSink<W: Write>(pub W);
+
+impl<W: Write> MidiSink for RawSink<W> {
+    fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+        self.0.write_all(msg)?;
+        self.0.flush()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+pub use self::alsa_sink::AlsaSink;
+
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+mod alsa_sink {
+    use std::ffi::CString;
+    use std::io;
+
+    use alsa::seq::{Addr, MidiEvent, PortCap, PortSubscribe, PortType, Seq};
+    use alsa::Direction;
+
+    use super::MidiSink;
+
+    fn to_io(e: alsa::Error) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, e.to_string())
+    }
+
+    /// An ALSA sequencer output port, optionally connected to `CLIENT:PORT`.
+    pub struct AlsaSink {
+        seq: Seq,
+        port: i32,
+        encoder: MidiEvent,
+    }
+
+    impl AlsaSink {
+        pub fn new(name: &str, dest: Option<&str>) -> io::Result<AlsaSink> {
+            let seq = Seq::open(None, Some(Direction::Playback), false).map_err(to_io)?;
+            let name = CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            seq.set_client_name(&name).map_err(to_io)?;
+            let port = seq.create_simple_port(
+                &name,
+                PortCap::READ | PortCap::SUBS_READ,
+                PortType::MIDI_GENERIC | PortType::APPLICATION,
+            ).map_err(to_io)?;
+
+            if let Some(dest) = dest {
+                let dest: Addr = dest.parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", dest, e)))?;
+                let subscribe = PortSubscribe::empty().map_err(to_io)?;
+                subscribe.set_sender(Addr { client: seq.client_id().map_err(to_io)?, port });
+                subscribe.set_dest(dest);
+                seq.subscribe_port(&subscribe).map_err(to_io)?;
+            }
+
+            let encoder = MidiEvent::new(16).map_err(to_io)?;
+            Ok(AlsaSink { seq, port, encoder })
+        }
+    }
+
+    impl MidiSink for AlsaSink {
+        fn send(&mut self, mut msg: &[u8]) -> io::Result<()> {
+            while !msg.is_empty() {
+                let (used, event) = self.encoder.encode(msg).map_err(to_io)?;
+                if let Some(mut event) = event {
+                    event.set_source(self.port);
+                    event.set_subs();
+                    event.set_direct();
+                    self.seq.event_output_direct(&mut event).map_err(to_io)?;
+                }
+                if used == 0 {
+                    break;
+                }
+                msg = &msg[used..];
+            }
+            Ok(())
+        }
+    }
+}
+
+fn data(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 127.0).round() as u8
+}
+
+fn data14(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * 16383.0).round() as u16
+}
+
+// note on velocity from the force of a press, never 0 as that means note off
+fn velocity(event: &ControlEvent) -> u8 {
+    data(event.pressure()).max(1)
+}
+
+struct Voice {
+    channel: u8,
+    note: u8,
+    pressure: u8,
+}
+
+/// Allocates a member channel of an MPE lower zone to each contact.
+struct Mpe {
+    // free member channels, least recently used first
+    free: VecDeque<u8>,
+    // contacts holding a channel, oldest first
+    held: VecDeque<(u8, u8)>,
+}
+
+impl Mpe {
+    fn new(members: u8) -> Self {
+        Mpe {
+            free: (1..=members).collect(),
+            held: VecDeque::new(),
+        }
+    }
+
+    // a channel for the contact, and a contact whose channel was stolen
+    fn allocate(&mut self, contact: u8) -> (u8, Option<u8>) {
+        match self.free.pop_front() {
+            Some(channel) => {
+                self.held.push_back((contact, channel));
+                (channel, None)
+            }
+            None => {
+                let (stolen, channel) = self.held.pop_front().expect("MPE zone has no member channels");
+                self.held.push_back((contact, channel));
+                (channel, Some(stolen))
+            }
+        }
+    }
+
+    fn release(&mut self, contact: u8) {
+        if let Some(i) = self.held.iter().position(|&(c, _)| c == contact) {
+            let (_, channel) = self.held.remove(i).unwrap();
+            self.free.push_back(channel);
+        }
+    }
+}
+
+/// Turns control events into MIDI. Pads and keyboards play notes, with
+/// velocity from the initial force and pressure while held, sliders send
+/// controllers or pitch bend.
+///
+/// In MPE mode notes are played on a lower zone, each contact getting its
+/// own member channel for pressure, pitch bend and timbre; sliders send on
+/// the master channel.
+pub struct MidiOutput {
+    sink: Box<dyn MidiSink>,
+    mpe: Option<Mpe>,
+    voices: HashMap<u8, Voice>,
+    // last value sent per (status, data1), to skip repeats
+    last: HashMap<(u8, u8), u16>,
+}
+
+impl MidiOutput {
+    pub fn new(sink: Box<dyn MidiSink>) -> Self {
+        MidiOutput {
+            sink,
+            mpe: None,
+            voices: HashMap::new(),
+            last: HashMap::new(),
+        }
+    }
+
+    /// Use an MPE lower zone with `members` channels, announcing it with the
+    /// MPE configuration message.
+    pub fn new_mpe(sink: Box<dyn MidiSink>, members: u8) -> io::Result<Self> {
+        let members = members.clamp(1, MPE_MAX_MEMBERS);
+        let mut output = MidiOutput::new(sink);
+        // RPN 6 on the master channel
+        output.sink.send(&[CONTROL_CHANGE, 101, 0])?;
+        output.sink.send(&[CONTROL_CHANGE, 100, 6])?;
+        output.sink.send(&[CONTROL_CHANGE, 6, members])?;
+        output.mpe = Some(Mpe::new(members));
+        Ok(output)
+    }
+
+    pub fn set_time(&mut self, time: SystemTime) {
+        self.sink.set_time(time);
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.sink.finish()
+    }
+
+    pub fn handle(&mut self, control: &Control, event: &ControlEvent) -> io::Result<()> {
+        match control.control_type {
+            ControlType::Pad => match control.midi {
+                Some(midi) => {
+                    if let Some(note) = midi.note {
+                        self.note(&midi, note, event, None)?;
+                    }
+                    match midi.cc {
+                        Some(cc) if event.phase == Phase::Release => self.cc(midi.channel, cc, midi.cc14, 0.0),
+                        Some(cc) => self.cc(midi.channel, cc, midi.cc14, event.pressure()),
+                        None => Ok(()),
+                    }
+                }
+                None => Ok(()),
+            },
+            ControlType::Keyboard { low_note, keys } => {
+                let midi = control.midi.unwrap_or(MidiMapping {
+                    channel: 0,
+                    note: None,
+                    cc: None,
+                    cc14: false,
+                    pressure: Pressure::Channel,
+                    pitch_bend: true,
+                });
+                // bend by how far the contact has slid from where it was pressed
+                let bend = (event.x - event.start_x) * f32::from(keys) / MPE_BEND_RANGE;
+                self.note(&midi, low_note + event.key(keys), event, Some((bend, 1.0 - event.y)))
+            }
+            control_type => match control.midi {
+                Some(midi) if event.phase != Phase::Release => {
+                    let value = event.slider_position(control_type);
+                    let channel = if self.mpe.is_some() { 0 } else { midi.channel };
+                    match midi.cc {
+                        _ if midi.pitch_bend => self.pitch_bend(channel, value * 2.0 - 1.0),
+                        Some(cc) => self.cc(channel, cc, midi.cc14, value),
+                        None => Ok(()),
+                    }
+                }
+                _ => Ok(()),
+            },
+        }
+    }
+
+    // play a note for a contact. `expression` is pitch bend in [-1, 1] and
+    // timbre in [0, 1], only sent in MPE mode where each note has its own channel
+    fn note(&mut self, midi: &MidiMapping, note: u8, event: &ControlEvent, expression: Option<(f32, f32)>) -> io::Result<()> {
+        match event.phase {
+            Phase::Press => {
+                let channel = match self.mpe.as_mut() {
+                    Some(mpe) => {
+                        let (channel, stolen) = mpe.allocate(event.contact);
+                        if let Some(voice) = stolen.and_then(|c| self.voices.remove(&c)) {
+                            self.sink.send(&[NOTE_OFF | voice.channel, voice.note, 0])?;
+                        }
+                        // reset expression before the note starts
+                        self.pitch_bend(channel, 0.0)?;
+                        channel
+                    }
+                    None => midi.channel,
+                };
+                self.sink.send(&[NOTE_ON | channel, note, velocity(event)])?;
+                self.voices.insert(event.contact, Voice { channel, note, pressure: 0 });
+                Ok(())
+            }
+            Phase::Move => {
+                let pressure = data(event.pressure());
+                let (channel, note, changed) = match self.voices.get_mut(&event.contact) {
+                    Some(voice) => {
+                        let changed = voice.pressure != pressure;
+                        voice.pressure = pressure;
+                        (voice.channel, voice.note, changed)
+                    }
+                    None => return Ok(()),
+                };
+                if self.mpe.is_some() {
+                    if changed {
+                        self.sink.send(&[CHANNEL_PRESSURE | channel, pressure])?;
+                    }
+                    if let Some((bend, timbre)) = expression {
+                        self.pitch_bend(channel, bend)?;
+                        self.cc(channel, CC_TIMBRE, false, timbre)?;
+                    }
+                    Ok(())
+                } else if changed {
+                    match midi.pressure {
+                        Pressure::Poly => self.sink.send(&[POLY_PRESSURE | channel, note, pressure]),
+                        Pressure::Channel => self.sink.send(&[CHANNEL_PRESSURE | channel, pressure]),
+                        Pressure::None => Ok(()),
+                    }
+                } else {
+                    Ok(())
+                }
+            }
+            Phase::Release => {
+                if let Some(voice) = self.voices.remove(&event.contact) {
+                    self.sink.send(&[NOTE_OFF | voice.channel, voice.note, 0])?;
+                }
+                if let Some(mpe) = self.mpe.as_mut() {
+                    mpe.release(event.contact);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn cc(&mut self, channel: u8, cc: u8, cc14: bool, value: f32) -> io::Result<()> {
+        let status = CONTROL_CHANGE | channel;
+        if cc14 {
+            let value = data14(value);
+            if self.last.insert((status, cc), value) != Some(value) {
+                self.sink.send(&[status, cc, (value >> 7) as u8])?;
+                self.sink.send(&[status, cc + 32, (value & 0x7F) as u8])?;
+            }
+        } else {
+            let value = data(value);
+            if self.last.insert((status, cc), u16::from(value)) != Some(u16::from(value)) {
+                self.sink.send(&[status, cc, value])?;
+            }
+        }
+        Ok(())
+    }
+
+    // bend in [-1, 1]
+    fn pitch_bend(&mut self, channel: u8, bend: f32) -> io::Result<()> {
+        let status = PITCH_BEND | channel;
+        let value = (f32::from(PITCH_BEND_CENTRE) + bend.clamp(-1.0, 1.0) * 8191.0).round() as u16;
+        if self.last.insert((status, 0), value) != Some(value) {
+            self.sink.send(&[status, (value & 0x7F) as u8, (value >> 7) as u8])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::interface::Interface;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn take(&self) -> Vec<u8> {
+            self.0.borrow_mut().split_off(0)
+        }
+    }
+
+    const INTERFACE: &str = r#"[
+        { "type": "pad", "id": "pad", "x": 0, "y": 0, "width": 10, "height": 10, "address": "/pad",
+          "midi": { "channel": 2, "note": 36, "pressure": "poly" } },
+        { "type": "vert_slider", "id": "fader", "x": 10, "y": 0, "width": 10, "height": 100, "address": "/fader",
+          "midi": { "channel": 1, "cc": 7, "cc14": true } },
+        { "type": "keyboard", "id": "keys", "x": 20, "y": 0, "width": 120, "height": 20, "address": "/keys",
+          "low_note": 60, "keys": 12 }
+    ]"#;
+
+    fn event(control: usize, contact: u8, phase: Phase, x: f32, y: f32, force: f32) -> ControlEvent {
+        ControlEvent { control, contact, phase, x, y, force, start_x: x, start_y: y }
+    }
+
+    #[test]
+    fn pad_notes_and_poly_pressure() {
+        let interface = Interface::from_json(INTERFACE).unwrap();
+        let buf = Shared::default();
+        let mut midi = MidiOutput::new(Box::new(RawSink(buf.clone())));
+        let pad = &interface.controls[0];
+
+        midi.handle(pad, &event(0, 3, Phase::Press, 0.5, 0.5, 2500.0)).unwrap();
+        assert_eq!(buf.take(), vec![0x91, 36, 64]);
+        midi.handle(pad, &event(0, 3, Phase::Move, 0.5, 0.5, 5000.0)).unwrap();
+        assert_eq!(buf.take(), vec![0xA1, 36, 127]);
+        midi.handle(pad, &event(0, 3, Phase::Move, 0.5, 0.5, 5000.0)).unwrap();
+        assert!(buf.take().is_empty());
+        midi.handle(pad, &event(0, 3, Phase::Release, 0.5, 0.5, 0.0)).unwrap();
+        assert_eq!(buf.take(), vec![0x81, 36, 0]);
+    }
+
+    #[test]
+    fn slider_14_bit_cc() {
+        let interface = Interface::from_json(INTERFACE).unwrap();
+        let buf = Shared::default();
+        let mut midi = MidiOutput::new(Box::new(RawSink(buf.clone())));
+        let fader = &interface.controls[1];
+
+        // top of a vertical slider is full scale
+        midi.handle(fader, &event(1, 0, Phase::Press, 0.5, 0.0, 100.0)).unwrap();
+        assert_eq!(buf.take(), vec![0xB0, 7, 127, 0xB0, 39, 127]);
+        midi.handle(fader, &event(1, 0, Phase::Release, 0.5, 0.0, 0.0)).unwrap();
+        assert!(buf.take().is_empty());
+    }
+
+    #[test]
+    fn mpe_gives_each_contact_a_channel() {
+        let interface = Interface::from_json(INTERFACE).unwrap();
+        let buf = Shared::default();
+        let mut midi = MidiOutput::new_mpe(Box::new(RawSink(buf.clone())), 2).unwrap();
+        assert_eq!(buf.take(), vec![0xB0, 101, 0, 0xB0, 100, 6, 0xB0, 6, 2]);
+        let keys = &interface.controls[2];
+
+        // the fourth key up from middle C
+        midi.handle(keys, &event(2, 1, Phase::Press, 0.3, 0.5, 2500.0)).unwrap();
+        assert_eq!(buf.take(), vec![0xE1, 0, 64, 0x91, 63, 64]);
+        midi.handle(keys, &event(2, 2, Phase::Press, 0.0, 0.5, 2500.0)).unwrap();
+        assert_eq!(buf.take(), vec![0xE2, 0, 64, 0x92, 60, 64]);
+
+        // a third contact steals the oldest channel
+        midi.handle(keys, &event(2, 4, Phase::Press, 0.5, 0.5, 5000.0)).unwrap();
+        assert_eq!(buf.take(), vec![0x81, 63, 0, 0x91, 66, 127]);
+
+        midi.handle(keys, &event(2, 4, Phase::Release, 0.5, 0.5, 0.0)).unwrap();
+        assert_eq!(buf.take(), vec![0x81, 66, 0]);
+    }
+}
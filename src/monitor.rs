@@ -0,0 +1,345 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Stdout, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use rosc::{OscMessage, OscType};
+
+use crate::driver::ControlEvent;
+use crate::interface::{ControlType, Interface};
+use crate::osc;
+use crate::sensel::contact::Contact;
+
+const REDRAW: Duration = Duration::from_millis(66);
+const LOG_LINES: usize = 500;
+
+pub type Log = Arc<Mutex<VecDeque<String>>>;
+
+fn push(log: &Log, line: String) {
+    let mut log = log.lock().unwrap();
+    if log.len() == LOG_LINES {
+        log.pop_front();
+    }
+    log.push_back(line);
+}
+
+/// Sends log records to the monitor's scrolling log, as the terminal is
+/// taken over.
+struct MonitorLogger {
+    log: Log,
+    level: log::LevelFilter,
+}
+
+impl log::Log for MonitorLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            push(&self.log, format!("{:<5} {}", record.level(), record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn format_args(msg: &OscMessage) -> String {
+    let args: Vec<String> = msg.args.iter().flatten()
+        .map(|arg| match arg {
+            OscType::Int(i) => i.to_string(),
+            OscType::Float(f) => format!("{:.3}", f),
+            OscType::String(s) => format!("\"{}\"", s),
+            other => format!("{:?}", other),
+        })
+        .collect();
+    args.join(" ")
+}
+
+type Cell = (char, Color);
+
+// the controls and contacts on a grid of `cells`, of a sensor `size` mm
+fn surface(interface: &Interface, contacts: &[Contact], size: (f32, f32), cells: (usize, usize)) -> Vec<Vec<Cell>> {
+    let ((width, height), (grid_w, grid_h)) = (size, cells);
+    let mut grid = vec![vec![(' ', Color::Reset); grid_w]; grid_h];
+    let to_cell = |x: f32, y: f32| {
+        let cx = ((x / width) * grid_w as f32) as isize;
+        let cy = ((y / height) * grid_h as f32) as isize;
+        (cx.max(0).min(grid_w as isize - 1) as usize, cy.max(0).min(grid_h as isize - 1) as usize)
+    };
+    for control in &interface.controls {
+        let r = control.rect;
+        let (x0, y0) = to_cell(r.x, r.y);
+        let (x1, y1) = to_cell(r.x + r.width, r.y + r.height);
+        let colour = match control.control_type {
+            _ if control.page.is_some() => Color::Grey,
+            ControlType::Pad => Color::Yellow,
+            ControlType::VertSlider | ControlType::HorzSlider => Color::Blue,
+            ControlType::Keyboard { .. } => Color::Green,
+        };
+        for (y, row) in grid.iter_mut().enumerate().take(y1 + 1).skip(y0) {
+            for (x, cell) in row.iter_mut().enumerate().take(x1 + 1).skip(x0) {
+                let edge = x == x0 || x == x1 || y == y0 || y == y1;
+                *cell = (if edge { '.' } else { ' ' }, colour);
+            }
+        }
+        // the id inside the top edge, if there's room
+        if y0 + 1 < y1 {
+            for (i, c) in control.id.chars().take(x1.saturating_sub(x0 + 1)).enumerate() {
+                grid[y0 + 1][x0 + 1 + i] = (c, colour);
+            }
+        }
+    }
+    for contact in contacts {
+        let (x, y) = to_cell(contact.x, contact.y);
+        let label = std::char::from_digit(u32::from(contact.id) % 36, 36).unwrap_or('*');
+        grid[y][x] = (label, Color::Red);
+    }
+    grid
+}
+
+/// Send log records to a log for the monitor rather than stderr, which it
+/// takes over. `RUST_LOG` sets the level, as with env_logger.
+pub fn init_logger() -> Log {
+    let log = Arc::new(Mutex::new(VecDeque::new()));
+    let level = std::env::var("RUST_LOG").ok()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(log::LevelFilter::Info);
+    if log::set_boxed_logger(Box::new(MonitorLogger { log: log.clone(), level })).is_ok() {
+        log::set_max_level(level);
+    }
+    log
+}
+
+/// A terminal UI showing the surface with its controls and contacts, each
+/// control's value, the LEDs, device stats and a log of the OSC sent.
+pub struct Monitor {
+    out: Stdout,
+    width: f32,
+    height: f32,
+    contacts: Vec<Contact>,
+    // by control id, so they survive page switches and reloads
+    values: HashMap<String, String>,
+    leds: Vec<u16>,
+    max_brightness: u16,
+    log: Log,
+    frames: u32,
+    fps: f32,
+    lost: i64,
+    counted: Instant,
+    drawn: Instant,
+    quit: bool,
+}
+
+impl Monitor {
+    /// Take over the terminal, `width` and `height` are the sensor's size in mm.
+    pub fn new(log: Log, width: f32, height: f32, num_leds: usize, max_brightness: u16) -> io::Result<Monitor> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, cursor::Hide)?;
+        let now = Instant::now();
+        Ok(Monitor {
+            out,
+            width,
+            height,
+            contacts: vec![],
+            values: HashMap::new(),
+            leds: vec![0; num_leds],
+            max_brightness,
+            log,
+            frames: 0,
+            fps: 0.0,
+            lost: 0,
+            counted: now,
+            drawn: now,
+            quit: false,
+        })
+    }
+
+    /// The contacts of a frame as it's read.
+    pub fn frame(&mut self, contacts: &[Contact], lost_frame_count: i32) {
+        self.contacts.clear();
+        self.contacts.extend_from_slice(contacts);
+        self.frames += 1;
+        self.lost += i64::from(lost_frame_count.max(0));
+    }
+
+    /// Record what was sent for a frame's events.
+    pub fn sent<'a, I: Iterator<Item = &'a ControlEvent>>(&mut self, interface: &Interface, events: I) {
+        for event in events {
+            let control = &interface.controls[event.control];
+            if let Some(value) = osc::value(control, event) {
+                let value = match osc::note(control, event) {
+                    Some(note) => format!("{} {:.3}", note, value),
+                    None => format!("{:.3}", value),
+                };
+                self.values.insert(control.id.clone(), value);
+            }
+            if let Some(shown) = osc::show(control, event) {
+                push(&self.log, shown.to_string());
+            }
+        }
+    }
+
+    pub fn log_message(&mut self, msg: &OscMessage) {
+        push(&self.log, format!("{} {}", msg.addr, format_args(msg)));
+    }
+
+    pub fn set_leds(&mut self, leds: &[u16]) {
+        self.leds = leds.to_vec();
+    }
+
+    pub fn set_led(&mut self, led: u8, brightness: u16) {
+        if let Some(l) = self.leds.get_mut(led as usize) {
+            *l = brightness;
+        }
+    }
+
+    /// Whether q, Esc or Ctrl-C was pressed.
+    pub fn quit_requested(&mut self) -> bool {
+        while let Ok(true) = event::poll(Duration::from_secs(0)) {
+            if let Ok(Event::Key(KeyEvent { code, modifiers, .. })) = event::read() {
+                match code {
+                    KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+                    _ => (),
+                }
+            }
+        }
+        self.quit
+    }
+
+    /// Redraw, at most every `REDRAW`.
+    pub fn draw(&mut self, interface: &Interface, page: &str) {
+        let now = Instant::now();
+        if now.duration_since(self.drawn) < REDRAW {
+            return;
+        }
+        self.drawn = now;
+        let elapsed = now.duration_since(self.counted);
+        if elapsed >= Duration::from_secs(1) {
+            self.fps = self.frames as f32 / (elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9);
+            self.frames = 0;
+            self.counted = now;
+        }
+        if let Err(e) = self.render(interface, page) {
+            push(&self.log, format!("ERROR monitor: {}", e));
+        }
+    }
+
+    fn render(&mut self, interface: &Interface, page: &str) -> io::Result<()> {
+        let (cols, rows) = terminal::size()?;
+        let (cols, rows) = (cols as usize, rows as usize);
+        if cols < 20 || rows < 10 {
+            return Ok(());
+        }
+
+        // the surface, characters being about twice as tall as wide
+        let grid_w = cols - 2;
+        let grid_h = (((grid_w as f32) * self.height / self.width / 2.0) as usize).max(3).min(rows / 2);
+        let grid = surface(interface, &self.contacts, (self.width, self.height), (grid_w, grid_h));
+
+        queue!(self.out, cursor::MoveTo(0, 0), terminal::Clear(ClearType::All))?;
+        let border = format!("+{}+", "-".repeat(grid_w));
+        queue!(self.out, Print(&border))?;
+        for (y, row) in grid.iter().enumerate() {
+            queue!(self.out, cursor::MoveTo(0, y as u16 + 1), Print('|'))?;
+            for &(c, colour) in row {
+                queue!(self.out, SetForegroundColor(colour), Print(c))?;
+            }
+            queue!(self.out, ResetColor, Print('|'))?;
+        }
+        queue!(self.out, cursor::MoveTo(0, grid_h as u16 + 1), Print(&border))?;
+
+        let mut lines = vec![];
+        let contacts: Vec<String> = self.contacts.iter()
+            .map(|c| format!("{}:{:.0}g", c.id, c.total_force))
+            .collect();
+        lines.push(format!("page {}  {:.0} fps  lost {}  contacts {}",
+                           page, self.fps, self.lost, contacts.join(" ")));
+
+        let levels = [' ', '.', ':', '-', '=', '+', '*', '#', '@'];
+        let max = f32::from(self.max_brightness.max(1));
+        let leds: String = self.leds.iter()
+            .map(|&l| levels[((f32::from(l) / max).min(1.0) * (levels.len() - 1) as f32).round() as usize])
+            .collect();
+        lines.push(format!("leds [{}]", leds));
+
+        let mut current = String::from("values");
+        for control in interface.controls.iter().filter(|c| c.page.is_none()) {
+            let value = format!("{}={}", control.id, self.values.get(&control.id).map_or("-", String::as_str));
+            if current.len() + value.len() + 1 > cols {
+                lines.push(current);
+                current = String::from("      ");
+            }
+            current.push(' ');
+            current.push_str(&value);
+        }
+        lines.push(current);
+        lines.push("-".repeat(cols));
+
+        // then the most recent log lines that fit
+        let top = grid_h + 2;
+        let free = rows.saturating_sub(top + lines.len());
+        {
+            let log = self.log.lock().unwrap();
+            lines.extend(log.iter().skip(log.len().saturating_sub(free)).cloned());
+        }
+        for (i, line) in lines.into_iter().enumerate().take(rows.saturating_sub(top)) {
+            let line: String = line.chars().take(cols).collect();
+            queue!(self.out, cursor::MoveTo(0, (top + i) as u16), Print(line))?;
+        }
+        self.out.flush()
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensel::contact::State;
+
+    #[test]
+    fn surface_grid() {
+        let interface = Interface::from_json(r#"[
+            { "type": "pad", "id": "pad", "x": 0, "y": 0, "width": 100, "height": 50, "address": "/pad" }
+        ]"#).unwrap();
+        let contact = Contact {
+            id: 11,
+            state: State::CONTACT_MOVE,
+            x: 150.0,
+            y: 75.0,
+            total_force: 100.0,
+            area: 10.0,
+            ellipse: None,
+            delta: None,
+            bounding_box: None,
+            peak: None,
+        };
+        let grid = surface(&interface, &[contact], (200.0, 100.0), (20, 10));
+        let row = |y: usize| grid[y].iter().map(|&(c, _)| c).collect::<String>();
+        assert_eq!(row(0), "...........         ");
+        assert_eq!(row(1), ".pad      .         ");
+        assert_eq!(row(5), "...........         ");
+        assert_eq!(grid[7][15], ('b', Color::Red));
+    }
+
+    #[test]
+    fn message_args() {
+        let msg = OscMessage {
+            addr: "/keys".to_string(),
+            args: Some(vec![OscType::Int(60), OscType::Float(0.5), OscType::String("on".to_string())]),
+        };
+        assert_eq!(format_args(&msg), "60 0.500 \"on\"");
+    }
+}
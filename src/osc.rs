@@ -0,0 +1,75 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rosc::OscType;
+
+use crate::driver::{ControlEvent, Phase};
+use crate::interface::{Arg, Control, ControlType};
+
+// seconds between the NTP epoch (1900) and the Unix epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+impl Arg {
+    pub fn to_osc(&self) -> OscType {
+        match self {
+            Arg::Int(i) => OscType::Int(*i),
+            Arg::Float(f) => OscType::Float(*f),
+            Arg::Str(s) => OscType::String(s.clone()),
+        }
+    }
+}
+
+/// The value a control reports for an event, in the control's range.
+pub fn value(control: &Control, event: &ControlEvent) -> Option<f32> {
+    match (control.control_type, event.phase) {
+        (ControlType::Pad, Phase::Release) | (ControlType::Keyboard { .. }, Phase::Release) => Some(control.min),
+        (ControlType::Pad, _) | (ControlType::Keyboard { .. }, _) => Some(control.scale(event.pressure())),
+        // sliders hold their value when released
+        (_, Phase::Release) => None,
+        (control_type, _) => Some(control.scale(event.slider_position(control_type))),
+    }
+}
+
+/// The note a keyboard event plays.
+pub fn note(control: &Control, event: &ControlEvent) -> Option<i32> {
+    match control.control_type {
+        ControlType::Keyboard { low_note, keys } => Some(i32::from(low_note + event.key(keys))),
+        _ => None,
+    }
+}
+
+/// The message for an event as text, address then args, for logs.
+pub struct Shown<'a> {
+    control: &'a Control,
+    note: Option<i32>,
+    value: f32,
+}
+
+pub fn show<'a>(control: &'a Control, event: &ControlEvent) -> Option<Shown<'a>> {
+    value(control, event).map(|value| Shown { control, note: note(control, event), value })
+}
+
+impl<'a> fmt::Display for Shown<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.control.address)?;
+        for arg in &self.control.args {
+            match arg {
+                Arg::Int(i) => write!(f, " {}", i)?,
+                Arg::Float(x) => write!(f, " {:.3}", x)?,
+                Arg::Str(s) => write!(f, " \"{}\"", s)?,
+            }
+        }
+        if let Some(note) = self.note {
+            write!(f, " {}", note)?;
+        }
+        write!(f, " {:.3}", self.value)
+    }
+}
+
+/// NTP seconds and fraction of a second, for bundle timetags.
+pub fn ntp_time(time: SystemTime) -> (u32, u32) {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_else(|_| Duration::from_secs(0));
+    let secs = since.as_secs() + NTP_UNIX_OFFSET;
+    let frac = (u64::from(since.subsec_nanos()) << 32) / 1_000_000_000;
+    (secs as u32, frac as u32)
+}
@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rosc::OscType;
+use serde_json::{json, Map, Value};
+
+use crate::driver::ControlEvent;
+use crate::interface::{Arg, Control, ControlType, Interface};
+use crate::osc;
+
+// OSCQuery access values
+const ACCESS_READ: u8 = 1;
+
+struct Node {
+    types: String,
+    // allowed values for each argument, the controls' args then their value
+    ranges: Vec<Value>,
+    value: Vec<OscType>,
+    description: String,
+}
+
+/// The OSC address space of an interface, as described by OSCQuery.
+pub struct Namespace {
+    name: String,
+    osc_port: Option<u16>,
+    nodes: BTreeMap<String, Node>,
+}
+
+fn type_tag(arg: &Arg) -> char {
+    match arg {
+        Arg::Int(_) => 'i',
+        Arg::Float(_) => 'f',
+        Arg::Str(_) => 's',
+    }
+}
+
+fn arg_json(arg: &Arg) -> Value {
+    match arg {
+        Arg::Int(i) => json!(i),
+        Arg::Float(f) => json!(f),
+        Arg::Str(s) => json!(s),
+    }
+}
+
+fn osc_json(arg: &OscType) -> Value {
+    match arg {
+        OscType::Int(i) => json!(i),
+        OscType::Float(f) => json!(f),
+        OscType::String(s) => json!(s),
+        OscType::Long(l) => json!(l),
+        OscType::Double(d) => json!(d),
+        OscType::Bool(b) => json!(b),
+        _ => Value::Null,
+    }
+}
+
+impl Namespace {
+    /// `osc_port` is where the driver accepts OSC, if the control server is running.
+    pub fn new(name: &str, interface: &Interface, osc_port: Option<u16>) -> Self {
+        let mut namespace = Namespace {
+            name: name.to_string(),
+            osc_port,
+            nodes: BTreeMap::new(),
+        };
+        namespace.set_interface(interface);
+        namespace
+    }
+
+    /// Describe a new interface, keeping the values of addresses that are
+    /// unchanged.
+    pub fn set_interface(&mut self, interface: &Interface) {
+        let mut old = std::mem::take(&mut self.nodes);
+        for control in interface.controls.iter().filter(|c| c.page.is_none()) {
+            let mut types: String = control.args.iter().map(type_tag).collect();
+            let mut value_ranges = vec![];
+            if let ControlType::Keyboard { low_note, keys } = control.control_type {
+                types.push('i');
+                value_ranges.push(json!({ "MIN": low_note, "MAX": low_note + keys - 1 }));
+            }
+            types.push('f');
+            value_ranges.push(json!({ "MIN": control.min.min(control.max), "MAX": control.min.max(control.max) }));
+
+            match self.nodes.get_mut(&control.address) {
+                Some(node) if node.types == types => {
+                    // several controls sharing an address, told apart by their args
+                    for (range, arg) in node.ranges.iter_mut().zip(&control.args) {
+                        if let Some(Value::Array(vals)) = range.get_mut("VALS") {
+                            let val = arg_json(arg);
+                            if !vals.contains(&val) {
+                                vals.push(val);
+                            }
+                        }
+                    }
+                    node.description.push_str(", ");
+                    node.description.push_str(&control.id);
+                }
+                Some(_) => warn!("OSCQuery: control '{}' has different arguments to others at {}, skipped",
+                                 control.id, control.address),
+                None => {
+                    let value = match old.remove(&control.address) {
+                        Some(node) if node.types == types => node.value,
+                        _ => vec![],
+                    };
+                    let ranges = control.args.iter()
+                        .map(|arg| json!({ "VALS": [arg_json(arg)] }))
+                        .chain(value_ranges)
+                        .collect();
+                    self.nodes.insert(control.address.clone(), Node {
+                        types,
+                        ranges,
+                        value,
+                        description: control.id.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Record the message sent for an event as the current value of its
+    /// address. The args are only copied when they change.
+    pub fn update(&mut self, control: &Control, event: &ControlEvent) {
+        let value = match osc::value(control, event) {
+            Some(value) => value,
+            None => return,
+        };
+        if let Some(node) = self.nodes.get_mut(control.address.as_str()) {
+            let args = control.args.len();
+            let same = node.value.len() > args && node.value.iter().zip(&control.args).all(|(v, arg)| match (v, arg) {
+                (OscType::Int(a), Arg::Int(b)) => a == b,
+                (OscType::Float(a), Arg::Float(b)) => a == b,
+                (OscType::String(a), Arg::Str(b)) => a == b,
+                _ => false,
+            });
+            if same {
+                node.value.truncate(args);
+            } else {
+                node.value.clear();
+                node.value.extend(control.args.iter().map(Arg::to_osc));
+            }
+            if let Some(note) = osc::note(control, event) {
+                node.value.push(OscType::Int(note));
+            }
+            node.value.push(OscType::Float(value));
+        }
+    }
+
+    pub fn host_info(&self) -> Value {
+        let mut info = json!({
+            "NAME": self.name,
+            "OSC_TRANSPORT": "UDP",
+            "EXTENSIONS": {
+                "ACCESS": true,
+                "VALUE": true,
+                "RANGE": true,
+                "DESCRIPTION": true,
+                "TAGS": false,
+                "CLIPMODE": false,
+                "UNIT": false,
+                "LISTEN": false,
+            }
+        });
+        if let Some(port) = self.osc_port {
+            info["OSC_PORT"] = json!(port);
+        }
+        info
+    }
+
+    fn node_json(&self, path: &str) -> Option<Value> {
+        let mut obj = Map::new();
+        obj.insert("FULL_PATH".to_string(), json!(path));
+
+        if let Some(node) = self.nodes.get(path) {
+            obj.insert("TYPE".to_string(), json!(node.types));
+            obj.insert("ACCESS".to_string(), json!(ACCESS_READ));
+            obj.insert("RANGE".to_string(), Value::Array(node.ranges.clone()));
+            obj.insert("DESCRIPTION".to_string(), json!(node.description));
+            if !node.value.is_empty() {
+                obj.insert("VALUE".to_string(), Value::Array(node.value.iter().map(osc_json).collect()));
+            }
+        }
+
+        let prefix = if path == "/" { "/".to_string() } else { format!("{}/", path) };
+        let mut contents = Map::new();
+        for addr in self.nodes.keys().filter(|a| a.starts_with(&prefix)) {
+            let child = addr[prefix.len()..].split('/').next().unwrap_or("");
+            if !child.is_empty() && !contents.contains_key(child) {
+                let child_path = format!("{}{}", prefix, child);
+                if let Some(json) = self.node_json(&child_path) {
+                    contents.insert(child.to_string(), json);
+                }
+            }
+        }
+
+        if !contents.is_empty() {
+            obj.insert("CONTENTS".to_string(), Value::Object(contents));
+        } else if !self.nodes.contains_key(path) && path != "/" {
+            return None;
+        }
+        Some(Value::Object(obj))
+    }
+
+    /// Answer an OSCQuery request for `path`, optionally `?ATTRIBUTE`.
+    pub fn query(&self, path: &str, attribute: Option<&str>) -> Option<Value> {
+        if attribute == Some("HOST_INFO") {
+            return Some(self.host_info());
+        }
+        let path = if path.len() > 1 { path.trim_end_matches('/') } else { path };
+        let node = self.node_json(path)?;
+        match attribute {
+            None => Some(node),
+            Some(attribute) => node.get(attribute).map(|v| {
+                let mut obj = Map::new();
+                obj.insert(attribute.to_string(), v.clone());
+                Value::Object(obj)
+            }),
+        }
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    write!(stream,
+           "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, body.len(), body)?;
+    stream.flush()
+}
+
+fn handle(mut stream: TcpStream, namespace: &Mutex<Namespace>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // skip the headers
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("/");
+    if method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", "");
+    }
+
+    let mut target = target.splitn(2, '?');
+    let path = target.next().unwrap_or("/");
+    let attribute = target.next().filter(|a| !a.is_empty());
+
+    let result = namespace.lock().unwrap().query(path, attribute);
+    match result {
+        Some(json) => respond(&mut stream, "200 OK", &json.to_string()),
+        None => respond(&mut stream, "404 Not Found", ""),
+    }
+}
+
+/// Serve the namespace over HTTP, returning the address actually bound.
+pub fn serve<A: ToSocketAddrs>(addr: A, namespace: Arc<Mutex<Namespace>>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| handle(stream, &namespace));
+            if let Err(e) = result {
+                debug!("OSCQuery: {}", e);
+            }
+        }
+    });
+    Ok(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use crate::driver::Phase;
+
+    const INTERFACE: &str = r#"[
+        { "type": "pad", "id": "a", "x": 0, "y": 0, "width": 10, "height": 10,
+          "address": "/pads/hit", "args": [1], "min": 0, "max": 127 },
+        { "type": "pad", "id": "b", "x": 10, "y": 0, "width": 10, "height": 10,
+          "address": "/pads/hit", "args": [2], "min": 0, "max": 127 },
+        { "type": "vert_slider", "id": "volume", "x": 20, "y": 0, "width": 10, "height": 50,
+          "address": "/volume" }
+    ]"#;
+
+    fn event(control: usize, phase: Phase, y: f32) -> ControlEvent {
+        ControlEvent { control, contact: 1, phase, x: 0.5, y, force: 0.0, start_x: 0.5, start_y: y }
+    }
+
+    fn namespace() -> Namespace {
+        Namespace::new("test", &Interface::from_json(INTERFACE).unwrap(), Some(9000))
+    }
+
+    fn get(addr: SocketAddr, target: &str) -> (String, Option<Value>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let mut parts = response.splitn(2, "\r\n\r\n");
+        let status = parts.next().unwrap().lines().next().unwrap().to_string();
+        let body = parts.next().unwrap_or("");
+        (status, serde_json::from_str(body).ok())
+    }
+
+    #[test]
+    fn root_lists_all_addresses() {
+        let root = namespace().query("/", None).unwrap();
+        assert_eq!(root["FULL_PATH"], "/");
+        assert_eq!(root["CONTENTS"]["pads"]["FULL_PATH"], "/pads");
+        assert_eq!(root["CONTENTS"]["pads"]["CONTENTS"]["hit"]["TYPE"], "if");
+        assert_eq!(root["CONTENTS"]["volume"]["TYPE"], "f");
+    }
+
+    #[test]
+    fn shared_address_merges_ranges() {
+        let hit = namespace().query("/pads/hit", None).unwrap();
+        assert_eq!(hit["RANGE"], json!([{ "VALS": [1, 2] }, { "MIN": 0.0, "MAX": 127.0 }]));
+        assert_eq!(hit["DESCRIPTION"], "a, b");
+        assert_eq!(hit["ACCESS"], 1);
+    }
+
+    #[test]
+    fn value_tracks_last_message() {
+        let interface = Interface::from_json(INTERFACE).unwrap();
+        let mut ns = namespace();
+        assert_eq!(ns.query("/volume", Some("VALUE")), None);
+        ns.update(&interface.controls[2], &event(2, Phase::Move, 0.5));
+        assert_eq!(ns.query("/volume", Some("VALUE")), Some(json!({ "VALUE": [0.5] })));
+        // pads sharing an address send their own args
+        ns.update(&interface.controls[0], &event(0, Phase::Release, 0.5));
+        assert_eq!(ns.query("/pads/hit", Some("VALUE")), Some(json!({ "VALUE": [1, 0.0] })));
+        ns.update(&interface.controls[1], &event(1, Phase::Release, 0.5));
+        assert_eq!(ns.query("/pads/hit", Some("VALUE")), Some(json!({ "VALUE": [2, 0.0] })));
+    }
+
+    #[test]
+    fn new_interface_keeps_values() {
+        let old = Interface::from_json(INTERFACE).unwrap();
+        let mut ns = namespace();
+        ns.update(&old.controls[2], &event(2, Phase::Move, 0.5));
+        ns.update(&old.controls[0], &event(0, Phase::Release, 0.5));
+
+        let interface = r#"[
+            { "type": "horz_slider", "id": "volume", "x": 0, "y": 0, "width": 50, "height": 10,
+              "address": "/volume" },
+            { "type": "pad", "id": "a", "x": 0, "y": 10, "width": 10, "height": 10,
+              "address": "/pads/hit" }
+        ]"#;
+        ns.set_interface(&Interface::from_json(interface).unwrap());
+        assert_eq!(ns.query("/volume", Some("VALUE")), Some(json!({ "VALUE": [0.5] })));
+        // the args changed, so the old value no longer fits
+        assert_eq!(ns.query("/pads/hit", Some("VALUE")), None);
+    }
+
+    #[test]
+    fn unknown_path() {
+        assert_eq!(namespace().query("/nope", None), None);
+        assert_eq!(namespace().query("/pad", None), None);
+    }
+
+    #[test]
+    fn http() {
+        let addr = serve("127.0.0.1:0", Arc::new(Mutex::new(namespace()))).unwrap();
+
+        let (status, body) = get(addr, "/volume");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body.unwrap()["FULL_PATH"], "/volume");
+
+        let (status, body) = get(addr, "/?HOST_INFO");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let info = body.unwrap();
+        assert_eq!(info["NAME"], "test");
+        assert_eq!(info["OSC_PORT"], 9000);
+
+        let (status, body) = get(addr, "/pads/hit?TYPE");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body.unwrap(), json!({ "TYPE": "if" }));
+
+        let (status, _) = get(addr, "/missing");
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use log::Level;
+use rosc::{OscMessage, OscPacket};
+
+use crate::driver::ControlEvent;
+use crate::encoder::FrameEncoder;
+use crate::interface::Interface;
+use crate::midi::MidiOutput;
+use crate::monitor::Monitor;
+use crate::osc;
+use crate::oscquery::Namespace;
+use crate::transport::OscSender;
+
+/// Everywhere control events go: OSC destinations, the OSCQuery namespace
+/// and MIDI.
+pub struct Outputs {
+    pub osc: OscSender,
+    pub namespace: Option<Arc<Mutex<Namespace>>>,
+    // live MIDI and recordings
+    pub midi: Vec<MidiOutput>,
+    // send each frame's messages as one bundle
+    pub bundle: bool,
+    // added to a frame's capture time for the bundle's timetag
+    pub latency: Duration,
+    pub monitor: Option<Monitor>,
+    // the OSC of each frame, reused
+    pub encoder: FrameEncoder,
+}
+
+impl Outputs {
+    /// Send the events from a frame captured at `captured`. Page switches
+    /// are left to the caller.
+    pub fn emit(&mut self, interface: &Interface, events: &[ControlEvent], captured: SystemTime) {
+        let events = || events.iter().filter(|event| interface.controls[event.control].page.is_none());
+
+        for midi in &mut self.midi {
+            midi.set_time(captured);
+            for event in events() {
+                if let Err(e) = midi.handle(&interface.controls[event.control], event) {
+                    warn!("MIDI: {}", e);
+                }
+            }
+        }
+
+        self.encoder.clear();
+        for event in events() {
+            self.encoder.push(&interface.controls[event.control], event);
+        }
+        if let Some(ref mut monitor) = self.monitor {
+            monitor.sent(interface, events());
+        }
+        if log_enabled!(Level::Debug) {
+            for event in events() {
+                if let Some(shown) = osc::show(&interface.controls[event.control], event) {
+                    debug!("{}", shown);
+                }
+            }
+        }
+        if let Some(ref namespace) = self.namespace {
+            let mut namespace = namespace.lock().unwrap();
+            for event in events() {
+                namespace.update(&interface.controls[event.control], event);
+            }
+        }
+        if self.encoder.is_empty() {
+            return;
+        }
+        let time = if self.bundle { Some(captured + self.latency) } else { None };
+        self.osc.send_frame(&mut self.encoder, time);
+    }
+
+    /// Send a message that isn't for a control, such as a page change.
+    pub fn send(&mut self, msg: OscMessage) {
+        if let Some(ref mut monitor) = self.monitor {
+            monitor.log_message(&msg);
+        }
+        self.osc.send(&OscPacket::Message(msg));
+    }
+
+    /// Finish writing any recordings.
+    pub fn finish(&mut self) {
+        for midi in &mut self.midi {
+            if let Err(e) = midi.finish() {
+                error!("MIDI: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::interface::{Interface, InterfaceError, PageTarget};
+use crate::sensel::contact::{Contact, State};
+use crate::svg;
+
+// how far, in mm, the fingers of a swipe must travel together
+const SWIPE_DISTANCE: f32 = 30.0;
+
+pub struct Page {
+    pub name: String,
+    pub interface: Interface,
+}
+
+/// The pages of a layout, one of which is current. A page comes from each
+/// interface file (JSON IR or SVG), or several from one IR file written as
+/// `{"pages": [{"name": ..., "controls": [...]}, ...]}`.
+pub struct Pages {
+    pages: Vec<Page>,
+    current: usize,
+    // the files the pages were loaded from
+    paths: Vec<PathBuf>,
+}
+
+impl Pages {
+    pub fn new(pages: Vec<Page>) -> Result<Pages, InterfaceError> {
+        if pages.is_empty() {
+            return Err(InterfaceError::Invalid("no pages".to_string()));
+        }
+        Ok(Pages { pages, current: 0, paths: vec![] })
+    }
+
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> Result<Pages, InterfaceError> {
+        let mut pages = vec![];
+        for path in paths {
+            let path = path.as_ref();
+            let name = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+            let text = fs::read_to_string(path)?;
+            let value = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("svg")) {
+                svg::to_ir(&text)?
+            } else {
+                serde_json::from_str(&text)?
+            };
+            pages.extend(parse_pages(&value, &name)?);
+        }
+        let mut pages = Pages::new(pages)?;
+        pages.paths = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        Ok(pages)
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn page(&self) -> &Page {
+        &self.pages[self.current]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Page> {
+        self.pages.iter()
+    }
+
+    /// The page a target refers to, next and prev wrap around.
+    pub fn resolve(&self, target: &PageTarget) -> Option<usize> {
+        let len = self.pages.len();
+        match target {
+            PageTarget::Next => Some((self.current + 1) % len),
+            PageTarget::Prev => Some((self.current + len - 1) % len),
+            PageTarget::Index(i) if *i < len => Some(*i),
+            PageTarget::Index(_) => None,
+            PageTarget::Name(name) => self.pages.iter().position(|p| p.name == *name),
+        }
+    }
+
+    pub fn set_current(&mut self, index: usize) {
+        self.current = index.min(self.pages.len() - 1);
+    }
+
+    /// Take the place of `old`, staying on the page of the same name if
+    /// there is one.
+    pub fn follow(&mut self, old: &Pages) {
+        let name = &old.page().name;
+        let index = self.pages.iter().position(|p| p.name == *name).unwrap_or(old.current);
+        self.set_current(index);
+    }
+
+    /// LED brightnesses showing the current page, one LED lit per page.
+    pub fn leds(&self, num_leds: usize, max_brightness: u16) -> Vec<u16> {
+        let mut leds = vec![0; num_leds];
+        if num_leds > 0 {
+            leds[self.current % num_leds] = max_brightness;
+        }
+        leds
+    }
+}
+
+fn parse_pages(value: &Value, name: &str) -> Result<Vec<Page>, InterfaceError> {
+    let pages = match value.get("pages") {
+        None => return Ok(vec![Page { name: name.to_string(), interface: Interface::from_value(value)? }]),
+        Some(Value::Array(pages)) => pages,
+        Some(_) => return Err(InterfaceError::Invalid("'pages' must be an array".to_string())),
+    };
+    pages.iter()
+        .enumerate()
+        .map(|(i, page)| {
+            let name = match page.get("name").and_then(Value::as_str) {
+                Some(name) => name.to_string(),
+                None => format!("{}{}", name, i),
+            };
+            let interface = Interface::from_value(page)
+                .map_err(|e| InterfaceError::Invalid(format!("page '{}': {}", name, e)))?;
+            Ok(Page { name, interface })
+        })
+        .collect()
+}
+
+/// Spots a horizontal swipe of several fingers together anywhere on the
+/// surface: to the left for the next page, to the right for the previous.
+pub struct Swipe {
+    fingers: usize,
+    // contacts down, with where they started
+    down: HashMap<u8, (f32, f32)>,
+    // fired, waiting for all fingers to lift
+    done: bool,
+}
+
+impl Swipe {
+    pub fn new(fingers: usize) -> Self {
+        Swipe { fingers, down: HashMap::new(), done: false }
+    }
+
+    pub fn process(&mut self, contacts: &[Contact]) -> Option<PageTarget> {
+        for contact in contacts {
+            match contact.state {
+                State::CONTACT_START => {
+                    self.down.insert(contact.id, (contact.x, contact.x));
+                }
+                State::CONTACT_MOVE => {
+                    if let Some(d) = self.down.get_mut(&contact.id) {
+                        d.1 = contact.x;
+                    }
+                }
+                State::CONTACT_END => {
+                    self.down.remove(&contact.id);
+                }
+                State::CONTACT_INVALID => (),
+            }
+        }
+        if self.down.is_empty() {
+            self.done = false;
+        }
+        if self.done || self.down.len() != self.fingers {
+            return None;
+        }
+
+        // every finger must have moved the same way
+        let mut moved = self.down.values().map(|&(start, x)| x - start);
+        let target = if moved.clone().all(|dx| dx <= -SWIPE_DISTANCE) {
+            PageTarget::Next
+        } else if moved.all(|dx| dx >= SWIPE_DISTANCE) {
+            PageTarget::Prev
+        } else {
+            return None;
+        };
+        self.done = true;
+        Some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pages() -> Pages {
+        let layout = serde_json::json!({"pages": [
+            {"name": "keys", "controls": []},
+            {"controls": []},
+            {"name": "drums", "controls": []},
+        ]});
+        Pages::new(parse_pages(&layout, "live").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn next_and_prev_wrap_around() {
+        let mut pages = pages();
+        assert_eq!(pages.resolve(&PageTarget::Prev), Some(2));
+        assert_eq!(pages.resolve(&PageTarget::Next), Some(1));
+        pages.set_current(2);
+        assert_eq!(pages.resolve(&PageTarget::Next), Some(0));
+        assert_eq!(pages.resolve(&PageTarget::Prev), Some(1));
+    }
+
+    #[test]
+    fn pages_are_found_by_name_or_index() {
+        let mut pages = pages();
+        assert_eq!(pages.resolve(&PageTarget::Name("drums".to_string())), Some(2));
+        // unnamed pages are named after their file
+        assert_eq!(pages.resolve(&PageTarget::Name("live1".to_string())), Some(1));
+        assert_eq!(pages.resolve(&PageTarget::Name("bass".to_string())), None);
+        assert_eq!(pages.resolve(&PageTarget::Index(2)), Some(2));
+        assert_eq!(pages.resolve(&PageTarget::Index(3)), None);
+        pages.set_current(5);
+        assert_eq!(pages.current(), 2);
+    }
+}
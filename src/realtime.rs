@@ -0,0 +1,39 @@
+// Real-time scheduling for the scan loop, so other processes can't delay frames.
+
+/// Run the calling thread under SCHED_FIFO at `priority` (1-99), which needs
+/// CAP_SYS_NICE or an rtprio limit.
+#[cfg(target_os = "linux")]
+pub fn set_priority(priority: i32) -> Result<(), String> {
+    let param = libc::sched_param { sched_priority: priority };
+    match unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } {
+        0 => Ok(()),
+        _ => Err(format!("failed to set real-time priority {}: {}", priority, std::io::Error::last_os_error())),
+    }
+}
+
+/// Keep the calling thread on CPU `cpu`.
+#[cfg(target_os = "linux")]
+pub fn set_affinity(cpu: usize) -> Result<(), String> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(format!("no CPU {}", cpu));
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(cpu, &mut set);
+        match libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) {
+            0 => Ok(()),
+            _ => Err(format!("failed to run on CPU {}: {}", cpu, std::io::Error::last_os_error())),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_priority(_priority: i32) -> Result<(), String> {
+    Err("real-time priority is only supported on Linux".to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_affinity(_cpu: usize) -> Result<(), String> {
+    Err("CPU affinity is only supported on Linux".to_string())
+}
@@ -0,0 +1,225 @@
+use std::fmt::Write;
+
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, StrokeDash, Transform};
+
+use crate::interface::{ControlType, Interface, MAX_FORCE};
+use crate::sensel::contact::Contact;
+
+// PNG pixels per mm
+pub const DEFAULT_SCALE: f32 = 4.0;
+// radius of a contact drawn without its ellipse, at no force and at full force
+const MIN_RADIUS: f32 = 2.0;
+const MAX_RADIUS: f32 = 8.0;
+
+type Rgb = (u8, u8, u8);
+
+const BACKGROUND: Rgb = (32, 32, 32);
+const PAD: Rgb = (243, 183, 59);
+const SLIDER: Rgb = (80, 160, 220);
+const KEYBOARD: Rgb = (140, 200, 120);
+const PAGE: Rgb = (180, 180, 180);
+const CONTACT: Rgb = (230, 60, 60);
+const OUTLINE: Rgb = (255, 255, 255);
+
+fn control_colour(interface: &Interface, index: usize) -> Rgb {
+    let control = &interface.controls[index];
+    if control.page.is_some() {
+        return PAGE;
+    }
+    match control.control_type {
+        ControlType::Pad => PAD,
+        ControlType::VertSlider | ControlType::HorzSlider => SLIDER,
+        ControlType::Keyboard { .. } => KEYBOARD,
+    }
+}
+
+// contacts are drawn more opaque the harder they press
+fn force_alpha(force: f32) -> f32 {
+    0.25 + 0.75 * (force / MAX_FORCE).clamp(0.0, 1.0)
+}
+
+// (rx, ry, orientation in degrees) of a contact
+fn contact_shape(contact: &Contact) -> (f32, f32, f32) {
+    match contact.ellipse {
+        Some(e) if e.major_axis > 0.0 && e.minor_axis > 0.0 => (e.major_axis / 2.0, e.minor_axis / 2.0, e.orientation),
+        _ => {
+            let r = MIN_RADIUS + (MAX_RADIUS - MIN_RADIUS) * (contact.total_force / MAX_FORCE).clamp(0.0, 1.0);
+            (r, r, 0.0)
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// An interface on a Morph sized canvas, in mm, with contacts over it.
+pub struct Scene<'a> {
+    pub width: f32,
+    pub height: f32,
+    pub interface: &'a Interface,
+    pub contacts: Vec<Contact>,
+}
+
+impl<'a> Scene<'a> {
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        let rgb = |(r, g, b): Rgb| format!("rgb({},{},{})", r, g, b);
+        // writing to a String can't fail
+        let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}mm" height="{h}mm" viewBox="0 0 {w} {h}">"#,
+                         w = self.width, h = self.height);
+        let _ = writeln!(svg, r#"<rect width="{}" height="{}" fill="{}"/>"#, self.width, self.height, rgb(BACKGROUND));
+
+        for (i, control) in self.interface.controls.iter().enumerate() {
+            let r = control.rect;
+            let _ = writeln!(svg, r#"<rect id="{}" x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="0.35" stroke="{}" stroke-width="0.3"/>"#,
+                             escape(&control.id), r.x, r.y, r.width, r.height,
+                             rgb(control_colour(self.interface, i)), rgb(control_colour(self.interface, i)));
+            if let ControlType::Keyboard { keys, .. } = control.control_type {
+                for k in 1..keys {
+                    let x = r.x + r.width * f32::from(k) / f32::from(keys);
+                    let _ = writeln!(svg, r#"<line x1="{x}" y1="{}" x2="{x}" y2="{}" stroke="{}" stroke-width="0.2"/>"#,
+                                     r.y, r.y + r.height, rgb(KEYBOARD), x = x);
+                }
+            }
+            let label = if control.address.is_empty() { &control.id } else { &control.address };
+            let _ = writeln!(svg, r#"<text x="{}" y="{}" font-family="sans-serif" font-size="3" fill="{}">{}</text>"#,
+                             r.x + 1.0, r.y + 4.0, rgb(OUTLINE), escape(label));
+        }
+
+        for contact in &self.contacts {
+            if let Some(b) = contact.bounding_box {
+                let _ = writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="{}" stroke-width="0.2" stroke-dasharray="1 1"/>"#,
+                                 b.min_x, b.min_y, b.max_x - b.min_x, b.max_y - b.min_y, rgb(OUTLINE));
+            }
+            let (rx, ry, angle) = contact_shape(contact);
+            let _ = writeln!(svg, r#"<ellipse cx="0" cy="0" rx="{}" ry="{}" transform="translate({} {}) rotate({})" fill="{}" fill-opacity="{}" stroke="{}" stroke-width="0.2"/>"#,
+                             rx, ry, contact.x, contact.y, angle, rgb(CONTACT), force_alpha(contact.total_force), rgb(OUTLINE));
+            if let Some(p) = contact.peak {
+                let _ = writeln!(svg, r#"<circle cx="{}" cy="{}" r="0.5" fill="{}"/>"#, p.x, p.y, rgb(OUTLINE));
+            }
+            let _ = writeln!(svg, r#"<text x="{}" y="{}" font-family="sans-serif" font-size="2.5" fill="{}">{} {:.0}g</text>"#,
+                             contact.x + rx.max(ry) + 1.0, contact.y, rgb(OUTLINE), contact.id, contact.total_force);
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Rasterise at `scale` pixels per mm. Text is left out, PNGs have no
+    /// fonts to draw it with.
+    pub fn to_png(&self, scale: f32) -> Result<Vec<u8>, String> {
+        let (w, h) = ((self.width * scale).ceil() as u32, (self.height * scale).ceil() as u32);
+        let mut pixmap = Pixmap::new(w, h).ok_or_else(|| format!("can't render a {}x{} image", w, h))?;
+        let (r, g, b) = BACKGROUND;
+        pixmap.fill(tiny_skia::Color::from_rgba8(r, g, b, 255));
+
+        let mm = Transform::from_scale(scale, scale);
+        let paint = |(r, g, b): Rgb, alpha: f32| {
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(r, g, b, (alpha * 255.0) as u8);
+            paint.anti_alias = true;
+            paint
+        };
+        let stroke = |width: f32, dashed: bool| Stroke {
+            width,
+            dash: if dashed { StrokeDash::new(vec![1.0, 1.0], 0.0) } else { None },
+            ..Stroke::default()
+        };
+
+        for (i, control) in self.interface.controls.iter().enumerate() {
+            let r = control.rect;
+            let colour = control_colour(self.interface, i);
+            if let Some(rect) = Rect::from_xywh(r.x, r.y, r.width, r.height) {
+                let path = PathBuilder::from_rect(rect);
+                pixmap.fill_path(&path, &paint(colour, 0.35), FillRule::Winding, mm, None);
+                pixmap.stroke_path(&path, &paint(colour, 1.0), &stroke(0.3, false), mm, None);
+            }
+            if let ControlType::Keyboard { keys, .. } = control.control_type {
+                let mut pb = PathBuilder::new();
+                for k in 1..keys {
+                    let x = r.x + r.width * f32::from(k) / f32::from(keys);
+                    pb.move_to(x, r.y);
+                    pb.line_to(x, r.y + r.height);
+                }
+                if let Some(path) = pb.finish() {
+                    pixmap.stroke_path(&path, &paint(KEYBOARD, 1.0), &stroke(0.2, false), mm, None);
+                }
+            }
+        }
+
+        for contact in &self.contacts {
+            if let Some(b) = contact.bounding_box {
+                if let Some(rect) = Rect::from_ltrb(b.min_x, b.min_y, b.max_x, b.max_y) {
+                    let path = PathBuilder::from_rect(rect);
+                    pixmap.stroke_path(&path, &paint(OUTLINE, 1.0), &stroke(0.2, true), mm, None);
+                }
+            }
+            let (rx, ry, angle) = contact_shape(contact);
+            let at = mm.pre_translate(contact.x, contact.y).pre_concat(Transform::from_rotate(angle));
+            if let Some(path) = Rect::from_ltrb(-rx, -ry, rx, ry).and_then(PathBuilder::from_oval) {
+                pixmap.fill_path(&path, &paint(CONTACT, force_alpha(contact.total_force)), FillRule::Winding, at, None);
+                pixmap.stroke_path(&path, &paint(OUTLINE, 1.0), &stroke(0.2, false), at, None);
+            }
+            if let Some(p) = contact.peak {
+                if let Some(path) = PathBuilder::from_circle(p.x, p.y, 0.5) {
+                    pixmap.fill_path(&path, &paint(OUTLINE, 1.0), FillRule::Winding, mm, None);
+                }
+            }
+        }
+
+        pixmap.encode_png().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensel::contact::{Ellipse, State};
+
+    fn scene(interface: &Interface) -> Scene<'_> {
+        Scene {
+            width: 230.0,
+            height: 130.0,
+            interface,
+            contacts: vec![Contact {
+                id: 1,
+                state: State::CONTACT_MOVE,
+                x: 15.0,
+                y: 15.0,
+                total_force: 2500.0,
+                area: 20.0,
+                ellipse: Some(Ellipse { orientation: 30.0, major_axis: 10.0, minor_axis: 6.0 }),
+                delta: None,
+                bounding_box: None,
+                peak: None,
+            }],
+        }
+    }
+
+    const INTERFACE: &str = r#"[
+        { "type": "pad", "id": "a<b", "x": 10, "y": 10, "width": 20, "height": 20, "address": "/pad" },
+        { "type": "keyboard", "id": "keys", "x": 0, "y": 100, "width": 230, "height": 30, "address": "/keys" }
+    ]"#;
+
+    #[test]
+    fn svg() {
+        let interface = Interface::from_json(INTERFACE).unwrap();
+        let svg = scene(&interface).to_svg();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="230mm" height="130mm""#));
+        assert!(svg.contains(r#"<rect id="a&lt;b" x="10" y="10" width="20" height="20""#));
+        assert!(svg.contains(r#"rx="5" ry="3" transform="translate(15 15) rotate(30)""#));
+        assert_eq!(svg.matches("<line").count(), 11);
+        // what we write, we can read back
+        assert!(roxmltree::Document::parse(&svg).is_ok());
+    }
+
+    #[test]
+    fn png() {
+        let interface = Interface::from_json(INTERFACE).unwrap();
+        let png = scene(&interface).to_png(2.0).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // IHDR width and height
+        assert_eq!(&png[16..24], &[0, 0, 1, 204, 0, 0, 1, 4]);
+    }
+}
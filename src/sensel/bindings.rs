@@ -3,4 +3,9 @@
 #![allow(non_snake_case)]
 #![allow(dead_code)]
 
+// regenerated from the sensel-api submodule with --features bindgen,
+// otherwise the checked in copy for the API version we support
+#[cfg(feature = "bindgen")]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+#[cfg(not(feature = "bindgen"))]
+include!("sensel_h.rs");
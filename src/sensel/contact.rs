@@ -7,9 +7,9 @@ const CONTACT_START: u32 = State::CONTACT_START as u32;
 const CONTACT_MOVE: u32 = State::CONTACT_MOVE as u32;
 const CONTACT_END: u32 = State::CONTACT_END as u32;
 
-impl Into<State> for u32 {
-    fn into(self) -> State {
-        match self {
+impl From<u32> for State {
+    fn from(state: u32) -> State {
+        match state {
             CONTACT_INVALID => State::CONTACT_INVALID,
             CONTACT_START => State::CONTACT_START,
             CONTACT_MOVE => State::CONTACT_MOVE,
@@ -1,19 +1,21 @@
 use std::mem;
-use std::cell::RefCell;
 
 use super::bindings::*;
+use super::library::*;
 use super::result::*;
 use super::frame;
 use super::contact;
+use super::led;
+use super::register::{self, Readable, Reg, Value, Writable};
+use super::remote::{Queue, Remote};
 
-enum LEDArray {
-    Char(Vec<u8>),
-    Short(Vec<u16>),
-}
+// the most a variable size register holds, its size is sent as 16 bits
+const MAX_REG_VS_SIZE: usize = 65535;
 
 pub struct DeviceList(SenselDeviceList);
 
 impl SenselSensorInfo {
+    #[allow(dead_code)]
     pub fn get_num_sensors(&self) -> usize {
         self.num_rows as usize * self.num_cols as usize
     }
@@ -22,19 +24,92 @@ impl SenselSensorInfo {
 #[derive(Copy, Clone, Debug)]
 pub struct DeviceInfo {
     pub sensor_info: SenselSensorInfo,
+    #[allow(dead_code)]
     pub fw_info: SenselFirmwareInfo,
     pub supported_frame_content: frame::Mask,
     pub num_leds: usize,
     pub max_led_brightness: u16,
+    pub led_format: led::Format,
+    // scanning in async mode, which the serial protocol can't
+    pub async_scanning: bool,
+}
+
+impl DeviceInfo {
+    /// The parts of `mask` the device can't send, e.g. pressure on firmware
+    /// without force output.
+    pub fn unsupported_frame_content(&self, mask: frame::Mask) -> frame::Mask {
+        mask - self.supported_frame_content
+    }
+
+    pub(crate) fn new(
+        sensor_info: SenselSensorInfo,
+        fw_info: SenselFirmwareInfo,
+        supported_frame_content: u8,
+        num_leds: u8,
+        led_format: led::Format,
+        async_scanning: bool,
+    ) -> DeviceInfo {
+        DeviceInfo {
+            sensor_info,
+            fw_info,
+            supported_frame_content: frame::Mask::from_bits_truncate(supported_frame_content),
+            num_leds: led_format.num_leds(usize::from(num_leds)),
+            max_led_brightness: led_format.max(),
+            led_format,
+            async_scanning,
+        }
+    }
+}
+
+/// What talks to the device, libsensel or the serial protocol. Only ever used
+/// by one thread at a time, but it may be a different one.
+pub(crate) trait Backend: Send {
+    fn start_scanning(&self) -> Result<(), SenselError>;
+    fn stop_scanning(&self) -> Result<(), SenselError>;
+    fn read_sensor(&self) -> Result<(), SenselError>;
+    fn get_num_available_frames(&self) -> Result<usize, SenselError>;
+    /// The next frame, pointing into buffers kept until the one after.
+    fn get_frame(&self) -> Result<SenselFrameData, SenselError>;
+
+    fn get_power_button_pressed(&self) -> Result<bool, SenselError>;
+    fn soft_reset(&self) -> Result<(), SenselError>;
+
+    fn set_led_brightness(&self, led_id: u8, brightness: u16) -> Result<(), SenselError>;
+    fn get_led_brightness(&self, led_id: u8) -> Result<u16, SenselError>;
+
+    fn set_scan_mode(&self, mode: SenselScanMode) -> Result<(), SenselError>;
+    fn get_scan_mode(&self) -> Result<SenselScanMode, SenselError>;
+    fn set_scan_detail(&self, detail: SenselScanDetail) -> Result<(), SenselError>;
+    fn get_scan_detail(&self) -> Result<SenselScanDetail, SenselError>;
+    fn set_buffer_control(&self, num: u8) -> Result<(), SenselError>;
+    fn get_buffer_control(&self) -> Result<u8, SenselError>;
+    fn set_max_frame_rate(&self, val: u16) -> Result<(), SenselError>;
+    fn get_max_frame_rate(&self) -> Result<u16, SenselError>;
+    fn set_frame_content(&self, mask: u8) -> Result<(), SenselError>;
+    fn get_frame_content(&self) -> Result<u8, SenselError>;
+    fn set_contacts_mask(&self, mask: u8) -> Result<(), SenselError>;
+    fn get_contacts_mask(&self) -> Result<u8, SenselError>;
+    fn set_contacts_min_force(&self, val: u16) -> Result<(), SenselError>;
+    fn get_contacts_min_force(&self) -> Result<u16, SenselError>;
+    fn set_contacts_enable_blob_merge(&self, val: bool) -> Result<(), SenselError>;
+    fn get_contacts_enable_blob_merge(&self) -> Result<bool, SenselError>;
+    fn set_dynamic_baseline_enabled(&self, val: bool) -> Result<(), SenselError>;
+    fn get_dynamic_baseline_enabled(&self) -> Result<bool, SenselError>;
+
+    fn read_reg(&self, addr: u8, size: usize) -> Result<Vec<u8>, SenselError>;
+    fn write_reg(&self, addr: u8, data: &[u8]) -> Result<(), SenselError>;
+    /// Read a register of variable size, e.g. the LED array.
+    fn read_reg_vs(&self, addr: u8) -> Result<Vec<u8>, SenselError>;
+    fn write_reg_vs(&self, addr: u8, data: &[u8]) -> Result<(), SenselError>;
 }
 
 pub struct ScanningDevice(BaseDevice);
 
 pub struct BaseDevice {
-    handle: *mut ::std::os::raw::c_void,
-    frame_data: *mut SenselFrameData,
-    led_array_buf: RefCell<LEDArray>,
+    backend: Box<dyn Backend>,
     info: DeviceInfo,
+    led_gamma: f32,
+    queue: Queue,
 }
 
 pub fn get_device_list() -> Result<DeviceList, SenselError> {
@@ -51,9 +126,9 @@ impl DeviceList {
     }
 }
 
-impl Into<DeviceList> for SenselDeviceList {
-    fn into(self) -> DeviceList {
-        DeviceList(self)
+impl From<SenselDeviceList> for DeviceList {
+    fn from(val: SenselDeviceList) -> Self {
+        DeviceList(val)
     }
 }
 
@@ -61,16 +136,227 @@ impl SenselDeviceID {
     pub fn get_serial_num(&self) -> &str {
         ::std::str::from_utf8(&self.serial_num).unwrap()
     }
+    #[allow(dead_code)]
     pub fn get_com_port(&self) -> &str {
         ::std::str::from_utf8(&self.com_port).unwrap()
     }
     pub fn open(self) -> Result<BaseDevice, SenselError> {
-        BaseDevice::new(self)
+        Lib::open(self).map(|(lib, info)| BaseDevice::new(Box::new(lib), info))
     }
 }
 
 impl BaseDevice {
-    fn new(id: SenselDeviceID) -> Result<Self, SenselError> {
+    pub(crate) fn new(backend: Box<dyn Backend>, info: DeviceInfo) -> Self {
+        BaseDevice { backend, info, led_gamma: led::DEFAULT_GAMMA, queue: Queue::new() }
+    }
+
+    /// A handle for other threads to change the device once it's scanning.
+    pub fn remote(&self) -> Remote {
+        self.queue.remote()
+    }
+
+    /// The gamma of normalised LED brightness, `led::DEFAULT_GAMMA` until set.
+    pub fn set_led_gamma(&mut self, gamma: f32) {
+        self.led_gamma = gamma;
+    }
+
+    pub fn start_scanning(mut self) -> Result<ScanningDevice, SenselError> {
+        self.queue.open();
+        self.backend.start_scanning()
+            .and(Ok(ScanningDevice(self)))
+    }
+
+    #[allow(dead_code)]
+    pub fn soft_reset(&self) -> Result<(), SenselError> {
+        self.backend.soft_reset()
+    }
+}
+
+impl Device for BaseDevice {
+    fn _get_device(&self) -> &BaseDevice {
+        self
+    }
+}
+
+impl ScanningDevice {
+    /// Stop scanning, failing calls through a `Remote` until started again.
+    pub fn stop_scanning(self) -> Result<BaseDevice, SenselError> {
+        self.0.queue.close();
+        self.0.backend.stop_scanning()
+            .and(Ok(self.0))
+    }
+
+    /// Read from the sensor, first carrying out what was asked through a `Remote`.
+    pub fn read_sensor(&self) -> Result<(), SenselError> {
+        self.0.queue.run(self);
+        self.0.backend.read_sensor()
+    }
+    pub fn get_num_available_frames(&self) -> Result<usize, SenselError> {
+        self.0.backend.get_num_available_frames()
+    }
+    pub fn get_frame(&self) -> Result<frame::Frame<'_>, SenselError> {
+        self.0.backend.get_frame()
+            .map(|data| frame::from_frame_data(data, self.get_info().sensor_info))
+    }
+}
+
+impl Device for ScanningDevice {
+    fn _get_device(&self) -> &BaseDevice {
+        &self.0
+    }
+}
+
+pub trait Device where Self: ::std::marker::Sized {
+    // this should be private but not sure if possible
+    fn _get_device(&self) -> &BaseDevice;
+
+    fn get_info(&self) -> DeviceInfo {
+        self._get_device().info
+    }
+
+    fn get_power_button_pressed(&self) -> Result<bool, SenselError> {
+        self._get_device().backend.get_power_button_pressed()
+    }
+
+    // the LED array is encoded here, whatever its size, so neither backend
+    // has to know it
+    fn set_led_array(&self, led_array: &[u16]) -> Result<(), SenselError> {
+        let info = self.get_info();
+        if led_array.len() != info.num_leds
+            || led_array.iter().any(|&brightness| brightness > info.max_led_brightness) {
+            Err(SenselError)
+        } else {
+            self._get_device().backend.write_reg_vs(register::LED_BRIGHTNESS.addr, &info.led_format.encode_all(led_array))
+        }
+    }
+
+    fn set_led_brightness(&self, led_id: u8, brightness: u16) -> Result<(), SenselError> {
+        let info = self.get_info();
+        if usize::from(led_id) >= info.num_leds || brightness > info.max_led_brightness {
+            return Err(SenselError);
+        }
+        self._get_device().backend.set_led_brightness(led_id, brightness)
+    }
+    fn get_led_brightness(&self, led_id: u8) -> Result<u16, SenselError> {
+        let info = self.get_info();
+        if usize::from(led_id) >= info.num_leds {
+            return Err(SenselError);
+        }
+        self._get_device().backend.get_led_brightness(led_id)
+    }
+
+    /// Set every LED from 0 to 1, gamma corrected, returning the brightness
+    /// each was set to.
+    fn set_led_levels(&self, levels: &[f32]) -> Result<Vec<u16>, SenselError> {
+        let format = self.get_info().led_format;
+        let gamma = self._get_device().led_gamma;
+        let led_array: Vec<u16> = levels.iter().map(|&level| format.scale(level, gamma)).collect();
+        self.set_led_array(&led_array).and(Ok(led_array))
+    }
+    fn set_led_level(&self, led_id: u8, level: f32) -> Result<u16, SenselError> {
+        let brightness = self.get_info().led_format.scale(level, self._get_device().led_gamma);
+        self.set_led_brightness(led_id, brightness).and(Ok(brightness))
+    }
+
+    fn set_scan_mode(&self, mode: SenselScanMode) -> Result<(), SenselError> {
+        self._get_device().backend.set_scan_mode(mode)
+    }
+    fn get_scan_mode(&self) -> Result<SenselScanMode, SenselError> {
+        self._get_device().backend.get_scan_mode()
+    }
+
+    fn set_scan_detail(&self, detail: SenselScanDetail) -> Result<(), SenselError> {
+        self._get_device().backend.set_scan_detail(detail)
+    }
+    fn get_scan_detail(&self) -> Result<SenselScanDetail, SenselError> {
+        self._get_device().backend.get_scan_detail()
+    }
+
+    fn set_buffer_control(&self, num: u8) -> Result<(), SenselError> {
+        self._get_device().backend.set_buffer_control(num)
+    }
+    fn get_buffer_control(&self) -> Result<u8, SenselError> {
+        self._get_device().backend.get_buffer_control()
+    }
+
+    fn set_max_frame_rate(&self, val: u16) -> Result<(), SenselError> {
+        self._get_device().backend.set_max_frame_rate(val)
+    }
+    fn get_max_frame_rate(&self) -> Result<u16, SenselError> {
+        self._get_device().backend.get_max_frame_rate()
+    }
+
+    fn set_frame_content(&self, mask: frame::Mask) -> Result<(), SenselError> {
+        if !self.get_info().unsupported_frame_content(mask).is_empty() {
+            Err(SenselError)
+        } else {
+            self._get_device().backend.set_frame_content(mask.bits())
+        }
+    }
+    fn get_frame_content(&self) -> Result<frame::Mask, SenselError> {
+        self._get_device().backend.get_frame_content()
+            .map(frame::Mask::from_bits_truncate)
+    }
+
+    fn set_contacts_mask(&self, mask: contact::Mask) -> Result<(), SenselError> {
+        self._get_device().backend.set_contacts_mask(mask.bits())
+    }
+    fn get_contacts_mask(&self) -> Result<contact::Mask, SenselError> {
+        self._get_device().backend.get_contacts_mask()
+            .map(contact::Mask::from_bits_truncate)
+    }
+
+    fn set_contacts_min_force(&self, val: u16) -> Result<(), SenselError> {
+        self._get_device().backend.set_contacts_min_force(val)
+    }
+    fn get_contacts_min_force(&self) -> Result<u16, SenselError> {
+        self._get_device().backend.get_contacts_min_force()
+    }
+
+    fn set_contacts_enable_blob_merge(&self, val: bool) -> Result<(), SenselError> {
+        self._get_device().backend.set_contacts_enable_blob_merge(val)
+    }
+    fn get_contacts_enable_blob_merge(&self) -> Result<bool, SenselError> {
+        self._get_device().backend.get_contacts_enable_blob_merge()
+    }
+
+    fn set_dynamic_baseline_enabled(&self, val: bool) -> Result<(), SenselError> {
+        self._get_device().backend.set_dynamic_baseline_enabled(val)
+    }
+    fn get_dynamic_baseline_enabled(&self) -> Result<bool, SenselError> {
+        self._get_device().backend.get_dynamic_baseline_enabled()
+    }
+
+    // registers without a size of their own are read and written whole
+    fn read_reg<T: Value, A: Readable>(&self, reg: Reg<T, A>) -> Result<T, SenselError> {
+        let backend = &self._get_device().backend;
+        match T::SIZE {
+            0 => backend.read_reg_vs(reg.addr),
+            size => backend.read_reg(reg.addr, size),
+        }.map(|bytes| T::from_bytes(&bytes))
+    }
+    fn write_reg<T: Value, A: Writable>(&self, reg: Reg<T, A>, value: T) -> Result<(), SenselError> {
+        let backend = &self._get_device().backend;
+        match T::SIZE {
+            0 => backend.write_reg_vs(reg.addr, &value.to_bytes()),
+            _ => backend.write_reg(reg.addr, &value.to_bytes()),
+        }
+    }
+
+    #[allow(dead_code)]
+    fn close(self) {
+        // move and drop the device
+    }
+}
+
+// the device through libsensel
+struct Lib {
+    handle: SENSEL_HANDLE,
+    frame_data: *mut SenselFrameData,
+}
+
+impl Lib {
+    fn open(id: SenselDeviceID) -> Result<(Lib, DeviceInfo), SenselError> {
         unsafe {
             let mut handle = mem::zeroed();
             let mut fw_info = mem::zeroed();
@@ -89,40 +375,23 @@ impl BaseDevice {
                 .and_then(|_| sensel_result(senselGetMaxLEDBrightness(handle, &mut max_led_brightness)))
                 .and_then(|_| sensel_result(senselReadReg(handle, SENSEL_REG_LED_BRIGHTNESS_SIZE as u8, SENSEL_REG_SIZE_LED_BRIGHTNESS_SIZE as u8, &mut led_reg_size)))
                 .and_then(|_| sensel_result(senselAllocateFrameData(handle, &mut frame_data)))
-                .and(Ok(BaseDevice {
-                    handle,
-                    frame_data,
-                    led_array_buf: RefCell::new(match led_reg_size {
-                        1 => LEDArray::Char(vec![0; num_leds as usize]),
-                        2 => LEDArray::Short(vec![0; num_leds as usize]),
-                        _ => unimplemented!()
-                    }),
-                    info: DeviceInfo {
-                        sensor_info,
-                        fw_info,
-                        supported_frame_content: frame::Mask::from_bits_truncate(supported_frame_content),
-                        num_leds: num_leds as usize,
-                        max_led_brightness,
-                    }
-                }))
-        }
-    }
-
-    pub fn start_scanning(self) -> Result<ScanningDevice, SenselError> {
-        unsafe {
-            sensel_result(senselStartScanning(self.handle))
-                .and(Ok(ScanningDevice(self)))
-        }
-    }
-
-    pub fn soft_reset(&self) -> Result<(), SenselError> {
-        unsafe {
-            sensel_result(senselSoftReset(self.handle))
+                .and(Ok((Lib { handle, frame_data }, DeviceInfo::new(
+                    sensor_info,
+                    fw_info,
+                    supported_frame_content,
+                    num_leds,
+                    led::Format { size: usize::from(led_reg_size), max_brightness: max_led_brightness },
+                    true,
+                ))))
         }
     }
 }
 
-impl Drop for BaseDevice {
+// the handle and frame data belong to this device alone, so they can move to
+// another thread as long as one uses them at a time
+unsafe impl Send for Lib {}
+
+impl Drop for Lib {
     fn drop(&mut self) {
         unsafe {
             // always close on drop to prevent memory leaks
@@ -132,243 +401,227 @@ impl Drop for BaseDevice {
     }
 }
 
-impl Device for BaseDevice {
-    fn _get_device(&self) -> &BaseDevice {
-        self
+impl Backend for Lib {
+    fn start_scanning(&self) -> Result<(), SenselError> {
+        unsafe {
+            sensel_result(senselStartScanning(self.handle))
+        }
     }
-}
-
-impl ScanningDevice {
-    pub fn stop_scanning(self) -> Result<BaseDevice, SenselError> {
+    fn stop_scanning(&self) -> Result<(), SenselError> {
         unsafe {
-            sensel_result(senselStopScanning(self.0.handle))
-                .and(Ok(self.0))
+            sensel_result(senselStopScanning(self.handle))
         }
     }
-
-    pub fn read_sensor(&self) -> Result<(), SenselError> {
+    fn read_sensor(&self) -> Result<(), SenselError> {
         unsafe {
-            sensel_result(senselReadSensor(self.0.handle))
-                .and(Ok(()))
+            sensel_result(senselReadSensor(self.handle))
         }
     }
-    pub fn get_num_available_frames(&self) -> Result<usize, SenselError> {
+    fn get_num_available_frames(&self) -> Result<usize, SenselError> {
         let mut num_frames = 0;
         unsafe {
-            sensel_result(senselGetNumAvailableFrames(self.0.handle, &mut num_frames))
+            sensel_result(senselGetNumAvailableFrames(self.handle, &mut num_frames))
                 .and(Ok(num_frames as usize))
         }
     }
-    pub fn get_frame(&self) -> Result<frame::Frame, SenselError> {
+    fn get_frame(&self) -> Result<SenselFrameData, SenselError> {
         unsafe {
-            sensel_result(senselGetFrame(self.0.handle, self.0.frame_data))
-                .and(Ok(frame::from_frame_data(*self.0.frame_data, self.get_info().sensor_info)))
+            sensel_result(senselGetFrame(self.handle, self.frame_data))
+                .and(Ok(*self.frame_data))
         }
     }
-}
-
-impl Device for ScanningDevice {
-    fn _get_device(&self) -> &BaseDevice {
-        &self.0
-    }
-}
-
-pub trait Device where Self: ::std::marker::Sized {
-    // this should be private but not sure if possible
-    fn _get_device(&self) -> &BaseDevice;
-
-    fn get_info(&self) -> DeviceInfo {
-        self._get_device().info
-    }
 
     fn get_power_button_pressed(&self) -> Result<bool, SenselError> {
-        let dev = self._get_device();
         let mut pressed = 0;
         unsafe {
-            sensel_result(senselGetPowerButtonPressed(dev.handle, &mut pressed))
+            sensel_result(senselGetPowerButtonPressed(self.handle, &mut pressed))
                 .and(Ok(pressed != 0))
         }
     }
-
-    fn set_led_array(&self, led_array: &[u16]) -> Result<(), SenselError> {
-        let dev = self._get_device();
-        let info = self.get_info();
-        if led_array.len() != info.num_leds {
-            Err(SenselError)
-        } else if led_array.iter().any(|&brightness| brightness > info.max_led_brightness) {
-            Err(SenselError)
-        } else {
-            let (buf_ptr, buf_size) = match *dev.led_array_buf.borrow_mut() {
-                LEDArray::Char(ref mut buf) => {
-                    for (buf, led) in buf.iter_mut().zip(led_array) {
-                        *buf = *led as u8;
-                    }
-                    (buf.as_mut_ptr(), buf.len())
-                },
-                LEDArray::Short(ref mut buf) => {
-                    buf.copy_from_slice(led_array);
-                    (buf.as_mut_ptr() as *mut u8, buf.len())
-                }
-            };
-            unsafe {
-                let write_size = mem::zeroed();
-                sensel_result(senselWriteRegVS(dev.handle, SENSEL_REG_LED_BRIGHTNESS as u8, buf_size as u32, buf_ptr, write_size))
-                    .and(Ok(()))
-            }
+    fn soft_reset(&self) -> Result<(), SenselError> {
+        unsafe {
+            sensel_result(senselSoftReset(self.handle))
         }
     }
 
     fn set_led_brightness(&self, led_id: u8, brightness: u16) -> Result<(), SenselError> {
-        let dev = self._get_device();
         unsafe {
-            sensel_result(senselSetLEDBrightness(dev.handle, led_id, brightness))
+            sensel_result(senselSetLEDBrightness(self.handle, led_id, brightness))
         }
     }
     fn get_led_brightness(&self, led_id: u8) -> Result<u16, SenselError> {
-        let dev = self._get_device();
         let mut brightness = 0;
         unsafe {
-            sensel_result(senselGetLEDBrightness(dev.handle, led_id, &mut brightness))
+            sensel_result(senselGetLEDBrightness(self.handle, led_id, &mut brightness))
                 .and(Ok(brightness))
         }
     }
 
     fn set_scan_mode(&self, mode: SenselScanMode) -> Result<(), SenselError> {
-        let dev = self._get_device();
         unsafe {
-            sensel_result(senselSetScanMode(dev.handle, mode))
+            sensel_result(senselSetScanMode(self.handle, mode))
         }
     }
     fn get_scan_mode(&self) -> Result<SenselScanMode, SenselError> {
-        let dev = self._get_device();
         let mut mode = SenselScanMode::SCAN_MODE_DISABLE;
         unsafe {
-            sensel_result(senselGetScanMode(dev.handle, &mut mode))
+            sensel_result(senselGetScanMode(self.handle, &mut mode))
                 .and(Ok(mode))
         }
     }
 
     fn set_scan_detail(&self, detail: SenselScanDetail) -> Result<(), SenselError> {
-        let dev = self._get_device();
         unsafe {
-            sensel_result(senselSetScanDetail(dev.handle, detail))
+            sensel_result(senselSetScanDetail(self.handle, detail))
         }
     }
     fn get_scan_detail(&self) -> Result<SenselScanDetail, SenselError> {
-        let dev = self._get_device();
         let mut detail = SenselScanDetail::SCAN_DETAIL_UNKNOWN;
         unsafe {
-            sensel_result(senselGetScanDetail(dev.handle, &mut detail))
+            sensel_result(senselGetScanDetail(self.handle, &mut detail))
                 .and(Ok(detail))
         }
     }
 
     fn set_buffer_control(&self, num: u8) -> Result<(), SenselError> {
-        let dev = self._get_device();
         unsafe {
-            sensel_result(senselSetBufferControl(dev.handle, num))
+            sensel_result(senselSetBufferControl(self.handle, num))
         }
     }
     fn get_buffer_control(&self) -> Result<u8, SenselError> {
-        let dev = self._get_device();
         let mut num = 0;
         unsafe {
-            sensel_result(senselGetBufferControl(dev.handle, &mut num))
+            sensel_result(senselGetBufferControl(self.handle, &mut num))
                 .and(Ok(num))
         }
     }
 
     fn set_max_frame_rate(&self, val: u16) -> Result<(), SenselError> {
-        let dev = self._get_device();
         unsafe {
-            sensel_result(senselSetMaxFrameRate(dev.handle, val))
+            sensel_result(senselSetMaxFrameRate(self.handle, val))
         }
     }
     fn get_max_frame_rate(&self) -> Result<u16, SenselError> {
-        let dev = self._get_device();
         let mut val = 0;
         unsafe {
-            sensel_result(senselGetMaxFrameRate(dev.handle, &mut val))
+            sensel_result(senselGetMaxFrameRate(self.handle, &mut val))
                 .and(Ok(val))
         }
     }
 
-    fn set_frame_content(&self, mask: frame::Mask) -> Result<(), SenselError> {
-        let dev = self._get_device();
+    fn set_frame_content(&self, mask: u8) -> Result<(), SenselError> {
         unsafe {
-            sensel_result(senselSetFrameContent(dev.handle, mask.bits()))
+            sensel_result(senselSetFrameContent(self.handle, mask))
         }
     }
-    fn get_frame_content(&self) -> Result<frame::Mask, SenselError> {
-        let dev = self._get_device();
+    fn get_frame_content(&self) -> Result<u8, SenselError> {
         let mut mask = 0;
         unsafe {
-            sensel_result(senselGetFrameContent(dev.handle, &mut mask))
-                .and(Ok(frame::Mask::from_bits_truncate(mask)))
+            sensel_result(senselGetFrameContent(self.handle, &mut mask))
+                .and(Ok(mask))
         }
     }
 
-    fn set_contacts_mask(&self, mask: contact::Mask) -> Result<(), SenselError> {
-        let dev = self._get_device();
+    fn set_contacts_mask(&self, mask: u8) -> Result<(), SenselError> {
         unsafe {
-            sensel_result(senselSetContactsMask(dev.handle, mask.bits()))
+            sensel_result(senselSetContactsMask(self.handle, mask))
         }
     }
-    fn get_contacts_mask(&self) -> Result<contact::Mask, SenselError> {
-        let dev = self._get_device();
+    fn get_contacts_mask(&self) -> Result<u8, SenselError> {
         let mut mask = 0;
         unsafe {
-            sensel_result(senselGetContactsMask(dev.handle, &mut mask))
-                .and(Ok(contact::Mask::from_bits_truncate(mask)))
+            sensel_result(senselGetContactsMask(self.handle, &mut mask))
+                .and(Ok(mask))
         }
     }
 
     fn set_contacts_min_force(&self, val: u16) -> Result<(), SenselError> {
-        let dev = self._get_device();
         unsafe {
-            sensel_result(senselSetContactsMinForce(dev.handle, val))
+            sensel_result(senselSetContactsMinForce(self.handle, val))
         }
     }
     fn get_contacts_min_force(&self) -> Result<u16, SenselError> {
-        let dev = self._get_device();
         let mut val = 0;
         unsafe {
-            sensel_result(senselGetContactsMinForce(dev.handle, &mut val))
+            sensel_result(senselGetContactsMinForce(self.handle, &mut val))
                 .and(Ok(val))
         }
     }
 
     fn set_contacts_enable_blob_merge(&self, val: bool) -> Result<(), SenselError> {
-        let dev = self._get_device();
         unsafe {
-            sensel_result(senselSetContactsEnableBlobMerge(dev.handle, val as u8))
+            sensel_result(senselSetContactsEnableBlobMerge(self.handle, val as u8))
         }
     }
     fn get_contacts_enable_blob_merge(&self) -> Result<bool, SenselError> {
-        let dev = self._get_device();
         let mut val = 0;
         unsafe {
-            sensel_result(senselGetContactsEnableBlobMerge(dev.handle, &mut val))
+            sensel_result(senselGetContactsEnableBlobMerge(self.handle, &mut val))
                 .and(Ok(val != 0))
         }
     }
 
     fn set_dynamic_baseline_enabled(&self, val: bool) -> Result<(), SenselError> {
-        let dev = self._get_device();
         unsafe {
-            sensel_result(senselSetDynamicBaselineEnabled(dev.handle, val as u8))
+            sensel_result(senselSetDynamicBaselineEnabled(self.handle, val as u8))
         }
     }
     fn get_dynamic_baseline_enabled(&self) -> Result<bool, SenselError> {
-        let dev = self._get_device();
         let mut val = 0;
         unsafe {
-            sensel_result(senselGetDynamicBaselineEnabled(dev.handle, &mut val))
+            sensel_result(senselGetDynamicBaselineEnabled(self.handle, &mut val))
                 .and(Ok(val != 0))
         }
     }
 
-    fn close(self) -> () {
-        // move and drop the device
+    fn read_reg(&self, addr: u8, size: usize) -> Result<Vec<u8>, SenselError> {
+        let mut buf = vec![0; size];
+        unsafe {
+            sensel_result(senselReadReg(self.handle, addr, size as u8, buf.as_mut_ptr()))
+                .and(Ok(buf))
+        }
+    }
+    fn write_reg(&self, addr: u8, data: &[u8]) -> Result<(), SenselError> {
+        let mut buf = data.to_vec();
+        unsafe {
+            sensel_result(senselWriteReg(self.handle, addr, buf.len() as u8, buf.as_mut_ptr()))
+        }
+    }
+    fn read_reg_vs(&self, addr: u8) -> Result<Vec<u8>, SenselError> {
+        let mut buf = vec![0; MAX_REG_VS_SIZE];
+        let mut read = 0;
+        unsafe {
+            sensel_result(senselReadRegVS(self.handle, addr, buf.len() as u32, buf.as_mut_ptr(), &mut read))?;
+        }
+        buf.truncate(read as usize);
+        Ok(buf)
+    }
+    fn write_reg_vs(&self, addr: u8, data: &[u8]) -> Result<(), SenselError> {
+        let mut buf = data.to_vec();
+        let mut written = 0;
+        unsafe {
+            sensel_result(senselWriteRegVS(self.handle, addr, buf.len() as u32, buf.as_mut_ptr(), &mut written))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensel::frame::Mask;
+    use crate::sensel::serial::fake;
+
+    #[test]
+    fn frame_content_the_device_cant_send_is_refused() {
+        let (device, regs) = fake::open(&[]);
+        let content = || regs.lock().unwrap()[usize::from(register::FRAME_CONTENT_CONTROL.addr)];
+        let info = device.get_info();
+        assert_eq!(info.unsupported_frame_content(Mask::CONTACTS), Mask::empty());
+        assert_eq!(info.unsupported_frame_content(Mask::CONTACTS | Mask::ACCEL), Mask::ACCEL);
+
+        assert!(device.set_frame_content(Mask::CONTACTS | Mask::ACCEL).is_err());
+        assert_eq!(content(), 0);
+        device.set_frame_content(Mask::CONTACTS).unwrap();
+        assert_eq!(content(), Mask::CONTACTS.bits());
     }
 }
@@ -13,6 +13,8 @@ bitflags! {
     }
 }
 
+// accel and the force images aren't requested by the driver yet
+#[allow(dead_code)]
 #[derive(Clone, Copy, Debug)]
 pub struct Frame<'a> {
     pub lost_frame_count: i32,
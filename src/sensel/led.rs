@@ -0,0 +1,96 @@
+use std::ops::Range;
+
+/// Gamma applied to normalised brightness unless configured otherwise.
+pub const DEFAULT_GAMMA: f32 = 2.2;
+
+/// How the device stores LED brightness: an array of `size` little endian
+/// bytes per LED, read and written whole through the LED_BRIGHTNESS
+/// register, up to `max_brightness`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Format {
+    pub size: usize,
+    pub max_brightness: u16,
+}
+
+impl Format {
+    /// The brightest value both the device and the register allow.
+    pub fn max(&self) -> u16 {
+        match self.size {
+            0 => 0,
+            1 => self.max_brightness.min(u16::from(u8::MAX)),
+            _ => self.max_brightness,
+        }
+    }
+
+    /// How many of `count` LEDs can be addressed, none if the array has no
+    /// size.
+    pub fn num_leds(&self, count: usize) -> usize {
+        if self.size == 0 { 0 } else { count }
+    }
+
+    /// The bytes of LED `led_id` in the array.
+    pub fn entry(&self, led_id: u8) -> Range<usize> {
+        let start = usize::from(led_id) * self.size;
+        start..start + self.size
+    }
+
+    /// Brightness as register bytes, saturated to `max` and zero extended
+    /// for registers wider than 16 bits.
+    pub fn encode(&self, brightness: u16, bytes: &mut Vec<u8>) {
+        let le = brightness.min(self.max()).to_le_bytes();
+        bytes.extend((0..self.size).map(|i| le.get(i).copied().unwrap_or(0)));
+    }
+
+    pub fn encode_all(&self, brightness: &[u16]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(brightness.len() * self.size);
+        for &b in brightness {
+            self.encode(b, &mut bytes);
+        }
+        bytes
+    }
+
+    /// Register bytes as brightness, saturating what doesn't fit in 16 bits.
+    pub fn decode(&self, bytes: &[u8]) -> u16 {
+        if bytes.iter().skip(2).any(|&b| b != 0) {
+            return u16::MAX;
+        }
+        bytes.iter().take(2).rev().fold(0, |v, &b| v << 8 | u16::from(b))
+    }
+
+    /// Native brightness for `level` between 0 and 1, gamma corrected.
+    pub fn scale(&self, level: f32, gamma: f32) -> u16 {
+        let level = if level.is_nan() { 0.0 } else { level.clamp(0.0, 1.0) };
+        (level.powf(gamma) * f32::from(self.max())).round() as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_any_register_size() {
+        let narrow = Format { size: 1, max_brightness: 1000 };
+        assert_eq!(narrow.max(), 255);
+        assert_eq!(narrow.encode_all(&[10, 1000]), vec![10, 255]);
+
+        let wide = Format { size: 4, max_brightness: 1000 };
+        assert_eq!(wide.encode_all(&[0x0102]), vec![2, 1, 0, 0]);
+        assert_eq!(wide.decode(&[2, 1, 0, 0]), 0x0102);
+        assert_eq!(wide.decode(&[0, 0, 1, 0]), u16::MAX);
+
+        let none = Format { size: 0, max_brightness: 100 };
+        assert_eq!(none.num_leds(16), 0);
+        assert!(none.encode_all(&[50]).is_empty());
+    }
+
+    #[test]
+    fn scales_normalised_brightness() {
+        let format = Format { size: 2, max_brightness: 1000 };
+        assert_eq!(format.scale(1.0, DEFAULT_GAMMA), 1000);
+        assert_eq!(format.scale(0.5, 1.0), 500);
+        assert_eq!(format.scale(0.5, 2.0), 250);
+        assert_eq!(format.scale(2.0, 1.0), 1000);
+        assert_eq!(format.scale(f32::NAN, 1.0), 0);
+    }
+}
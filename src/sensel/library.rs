@@ -0,0 +1,144 @@
+// libsensel is loaded when first needed rather than linked, so the driver
+// builds anywhere and runs without it for rendering and replaying sessions.
+
+use std::env;
+use std::os::raw::{c_uchar, c_uint, c_ushort};
+use std::sync::OnceLock;
+
+use libloading::Library;
+
+use super::bindings::*;
+
+// where the Sensel installers put it, tried after $SENSEL_LIB
+#[cfg(target_os = "windows")]
+const NAMES: &[&str] = &[
+    "LibSensel.dll",
+    r"C:\Program Files\Sensel\SenselLib\x64\LibSensel.dll",
+    r"C:\Program Files\Sensel\SenselLib\x86\LibSensel.dll",
+];
+#[cfg(target_os = "macos")]
+const NAMES: &[&str] = &["libsensel.dylib", "/usr/local/lib/libsensel.dylib"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const NAMES: &[&str] = &["libsensel.so", "/usr/lib/libsensel.so", "/usr/local/lib/libsensel.so"];
+
+// the functions used, each with a wrapper of the same name that calls it
+// through the loaded library, failing with SENSEL_ERROR without one
+macro_rules! sensel_api {
+    ($($name:ident($($arg:ident: $ty:ty),*);)*) => {
+        #[allow(non_snake_case)]
+        struct Api {
+            $($name: unsafe extern "C" fn($($ty),*) -> SenselStatus,)*
+            // keeps the functions loaded
+            _library: Library,
+        }
+
+        impl Api {
+            #[allow(non_snake_case)]
+            unsafe fn load(library: Library) -> Result<Api, String> {
+                $(
+                    let $name = *library.get::<unsafe extern "C" fn($($ty),*) -> SenselStatus>(
+                        concat!(stringify!($name), "\0").as_bytes())
+                        .map_err(|e| format!("libsensel is missing {}: {}", stringify!($name), e))?;
+                )*
+                Ok(Api { $($name,)* _library: library })
+            }
+        }
+
+        $(
+            #[allow(non_snake_case)]
+            pub unsafe fn $name($($arg: $ty),*) -> SenselStatus {
+                match api() {
+                    Ok(api) => (api.$name)($($arg),*),
+                    Err(_) => SenselStatus::SENSEL_ERROR,
+                }
+            }
+        )*
+    };
+}
+
+sensel_api! {
+    senselGetDeviceList(list: *mut SenselDeviceList);
+    senselOpenDeviceByID(handle: *mut SENSEL_HANDLE, idx: c_uchar);
+    senselClose(handle: SENSEL_HANDLE);
+    senselSoftReset(handle: SENSEL_HANDLE);
+    senselGetSensorInfo(handle: SENSEL_HANDLE, info: *mut SenselSensorInfo);
+    senselGetFirmwareInfo(handle: SENSEL_HANDLE, info: *mut SenselFirmwareInfo);
+    senselAllocateFrameData(handle: SENSEL_HANDLE, data: *mut *mut SenselFrameData);
+    senselStartScanning(handle: SENSEL_HANDLE);
+    senselStopScanning(handle: SENSEL_HANDLE);
+    senselReadSensor(handle: SENSEL_HANDLE);
+    senselGetNumAvailableFrames(handle: SENSEL_HANDLE, num_frames: *mut c_uint);
+    senselGetFrame(handle: SENSEL_HANDLE, data: *mut SenselFrameData);
+    senselGetPowerButtonPressed(handle: SENSEL_HANDLE, pressed: *mut c_uchar);
+    senselGetNumAvailableLEDs(handle: SENSEL_HANDLE, num_leds: *mut c_uchar);
+    senselGetMaxLEDBrightness(handle: SENSEL_HANDLE, max_brightness: *mut c_ushort);
+    senselSetLEDBrightness(handle: SENSEL_HANDLE, led_id: c_uchar, brightness: c_ushort);
+    senselGetLEDBrightness(handle: SENSEL_HANDLE, led_id: c_uchar, brightness: *mut c_ushort);
+    senselGetSupportedFrameContent(handle: SENSEL_HANDLE, content: *mut c_uchar);
+    senselSetFrameContent(handle: SENSEL_HANDLE, content: c_uchar);
+    senselGetFrameContent(handle: SENSEL_HANDLE, content: *mut c_uchar);
+    senselSetScanMode(handle: SENSEL_HANDLE, mode: SenselScanMode);
+    senselGetScanMode(handle: SENSEL_HANDLE, mode: *mut SenselScanMode);
+    senselSetScanDetail(handle: SENSEL_HANDLE, detail: SenselScanDetail);
+    senselGetScanDetail(handle: SENSEL_HANDLE, detail: *mut SenselScanDetail);
+    senselSetBufferControl(handle: SENSEL_HANDLE, num: c_uchar);
+    senselGetBufferControl(handle: SENSEL_HANDLE, num: *mut c_uchar);
+    senselSetMaxFrameRate(handle: SENSEL_HANDLE, val: c_ushort);
+    senselGetMaxFrameRate(handle: SENSEL_HANDLE, val: *mut c_ushort);
+    senselSetContactsMask(handle: SENSEL_HANDLE, mask: c_uchar);
+    senselGetContactsMask(handle: SENSEL_HANDLE, mask: *mut c_uchar);
+    senselSetContactsMinForce(handle: SENSEL_HANDLE, val: c_ushort);
+    senselGetContactsMinForce(handle: SENSEL_HANDLE, val: *mut c_ushort);
+    senselSetContactsEnableBlobMerge(handle: SENSEL_HANDLE, val: c_uchar);
+    senselGetContactsEnableBlobMerge(handle: SENSEL_HANDLE, val: *mut c_uchar);
+    senselSetDynamicBaselineEnabled(handle: SENSEL_HANDLE, val: c_uchar);
+    senselGetDynamicBaselineEnabled(handle: SENSEL_HANDLE, val: *mut c_uchar);
+    senselReadReg(handle: SENSEL_HANDLE, reg: c_uchar, size: c_uchar, buf: *mut c_uchar);
+    senselWriteReg(handle: SENSEL_HANDLE, reg: c_uchar, size: c_uchar, buf: *mut c_uchar);
+    senselReadRegVS(handle: SENSEL_HANDLE, reg: c_uchar, buf_size: c_uint, buf: *mut c_uchar, read_size: *mut c_uint);
+    senselWriteRegVS(handle: SENSEL_HANDLE, reg: c_uchar, buf_size: c_uint, buf: *mut c_uchar, write_size: *mut c_uint);
+}
+
+static API: OnceLock<Result<Api, String>> = OnceLock::new();
+
+fn open() -> Result<Api, String> {
+    let names: Vec<String> = env::var("SENSEL_LIB").ok().into_iter()
+        .chain(NAMES.iter().map(|name| name.to_string()))
+        .collect();
+    open_from(&names)
+}
+
+// the first of `names` that loads
+fn open_from(names: &[String]) -> Result<Api, String> {
+    for name in names {
+        // loading runs the library's initialisers, nothing more
+        if let Ok(library) = unsafe { Library::new(name) } {
+            return unsafe { Api::load(library) };
+        }
+    }
+    Err(format!("libsensel not found (tried {}), install it from \
+                 https://github.com/sensel/sensel-api or set SENSEL_LIB to its path",
+                names.join(", ")))
+}
+
+fn api() -> Result<&'static Api, &'static str> {
+    API.get_or_init(open).as_ref().map_err(String::as_str)
+}
+
+/// Load libsensel, if it isn't already, saying why if it can't be.
+pub fn load() -> Result<(), String> {
+    api().map(|_| ()).map_err(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_missing_library_is_an_error() {
+        let path = "/nonexistent/libsensel.so";
+        let err = open_from(&[path.to_string()]).err().unwrap();
+        assert!(err.starts_with("libsensel not found"), "{}", err);
+        assert!(err.contains(path), "{}", err);
+    }
+}
@@ -1,15 +1,27 @@
 extern crate bitflags;
 
 pub mod bindings;
+pub mod library;
+pub mod serial;
+pub mod register;
+pub mod led;
+pub mod remote;
+// for embedding in async services, the driver itself doesn't use it
+#[cfg(feature = "async")]
+#[allow(dead_code)]
+pub mod stream;
 pub mod scan_mode;
+pub mod reader;
 pub mod scan_detail;
 mod result;
 pub mod device;
 pub mod frame;
 pub mod contact;
+pub mod settings;
 
-use bindings::*;
-
+// the device API as a whole, not all of it used by the driver
+#[allow(unused_imports)]
 pub use result::SenselError;
 
+#[allow(dead_code)]
 pub const MAX_DEVICES: usize = bindings::SENSEL_MAX_DEVICES as usize;
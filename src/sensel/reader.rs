@@ -0,0 +1,236 @@
+// The scan loop's side of the scan modes. In sync mode each read asks the
+// device for a frame and waits for it, so reading paces itself to the device.
+// In async mode the device streams frames into a buffer that reads take
+// whatever has arrived from, often nothing, so a read that finds nothing
+// sleeps for part of a frame rather than spinning.
+
+use std::thread;
+use std::time::Duration;
+
+use super::bindings::SenselScanMode;
+use super::contact::{Contact, State};
+use super::device::{Device, ScanningDevice};
+use super::result::*;
+
+/// Reads failing in a row before giving up on the device, about 20s with
+/// `retry_time` between them.
+pub const MAX_READ_FAILURES: u32 = 60;
+
+/// How long to wait after `failures` reads have failed in a row, longer
+/// after each one up to a second.
+pub fn retry_time(failures: u32) -> Duration {
+    (Duration::from_millis(10) * failures).min(Duration::from_secs(1))
+}
+
+/// What to do with several frames arriving in one read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Buffering {
+    // handle every frame in turn, nothing is skipped
+    All,
+    // merge them into the newest, for the least latency when the scan loop
+    // falls behind, at the cost of the positions in between
+    Newest,
+}
+
+impl Buffering {
+    pub fn from_name(name: &str) -> Option<Buffering> {
+        match name {
+            "all" => Some(Buffering::All),
+            "newest" => Some(Buffering::Newest),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Buffering::All => "all",
+            Buffering::Newest => "newest",
+        }
+    }
+}
+
+/// A frame's contacts, or those of several merged.
+#[derive(Default)]
+pub struct ReadFrame {
+    pub lost_frame_count: i32,
+    // frames from the device it stands for
+    pub frames: usize,
+    pub contacts: Vec<Contact>,
+    // how long before the newest frame of the read it was captured, a frame
+    // period for each frame after it
+    pub age: Duration,
+}
+
+/// Reads frames as the device's scan mode calls for, into buffers reused
+/// from read to read.
+pub struct FrameReader {
+    buffering: Buffering,
+    period: Duration,
+    // after an async read that found no frames
+    idle: Option<Duration>,
+    frames: Vec<ReadFrame>,
+    len: usize,
+    // frames that failed to read, counted as lost in the next one
+    failed: i32,
+}
+
+// a frame at the device's frame rate, a rate of 0 is unlimited, which the
+// Morph takes to be about 1000
+fn frame_period(max_frame_rate: u16) -> Duration {
+    let rate = if max_frame_rate == 0 { 1000 } else { u32::from(max_frame_rate) };
+    Duration::from_secs(1) / rate
+}
+
+// half a frame
+pub(crate) fn idle_time(mode: SenselScanMode, max_frame_rate: u16) -> Option<Duration> {
+    match mode {
+        SenselScanMode::SCAN_MODE_ASYNC => Some(frame_period(max_frame_rate) / 2),
+        _ => None,
+    }
+}
+
+/// Add a contact of a frame to those of the frames before it in the same
+/// read. A contact that was moving takes its newest state, but presses and
+/// releases are all kept, so a tap between two reads still sends both.
+fn merge(merged: &mut Vec<Contact>, contact: Contact) {
+    match merged.iter().rposition(|c| c.id == contact.id) {
+        Some(i) if merged[i].state == State::CONTACT_MOVE => merged[i] = contact,
+        _ => merged.push(contact),
+    }
+}
+
+// each frame of a read is as old as the frames after it take
+fn set_ages(frames: &mut [ReadFrame], period: Duration) {
+    let mut age = Duration::ZERO;
+    for read in frames.iter_mut().rev() {
+        read.age = age;
+        age += period * read.frames as u32;
+    }
+}
+
+impl FrameReader {
+    /// For `device` in the scan mode and at the frame rate it has now.
+    pub fn new(device: &ScanningDevice, buffering: Buffering) -> FrameReader {
+        let mut reader = FrameReader {
+            buffering, period: Duration::ZERO, idle: None, frames: vec![], len: 0, failed: 0,
+        };
+        reader.refresh(device);
+        reader
+    }
+
+    /// Catch up with the device's scan mode and frame rate after either may
+    /// have been written.
+    pub fn refresh(&mut self, device: &ScanningDevice) {
+        let mode = device.get_scan_mode().unwrap_or(SenselScanMode::SCAN_MODE_SYNC);
+        let rate = device.get_max_frame_rate().unwrap_or(0);
+        self.period = frame_period(rate);
+        self.idle = idle_time(mode, rate);
+    }
+
+    /// Read the sensor and take every frame it had, in order, or merged into
+    /// one.
+    pub fn read(&mut self, device: &ScanningDevice) -> Result<&[ReadFrame], SenselError> {
+        self.len = 0;
+        device.read_sensor()?;
+        let num_frames = device.get_num_available_frames()?;
+        for _ in 0..num_frames {
+            let frame = match device.get_frame() {
+                Ok(frame) => frame,
+                Err(_) => {
+                    self.failed += 1;
+                    continue;
+                }
+            };
+            if self.len == 0 || self.buffering == Buffering::All {
+                if self.len == self.frames.len() {
+                    self.frames.push(ReadFrame::default());
+                }
+                let read = &mut self.frames[self.len];
+                read.lost_frame_count = 0;
+                read.frames = 0;
+                read.contacts.clear();
+                self.len += 1;
+            }
+            let read = &mut self.frames[self.len - 1];
+            read.lost_frame_count += frame.lost_frame_count + self.failed;
+            self.failed = 0;
+            read.frames += 1;
+            let contacts = frame.contacts.unwrap_or(&[]).iter().map(|&c| Contact::from(c));
+            if read.frames == 1 {
+                read.contacts.extend(contacts);
+            } else {
+                contacts.for_each(|contact| merge(&mut read.contacts, contact));
+            }
+        }
+        if self.len == 0 {
+            if let Some(idle) = self.idle {
+                thread::sleep(idle);
+            }
+        }
+        set_ages(&mut self.frames[..self.len], self.period);
+        Ok(&self.frames[..self.len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensel::bindings::FRAME_CONTENT_PRESSURE_MASK;
+    use crate::sensel::serial::fake;
+
+    fn contact(id: u8, state: State, x: f32) -> Contact {
+        Contact {
+            id, state, x, y: 0.0, total_force: 100.0, area: 1.0,
+            ellipse: None, delta: None, bounding_box: None, peak: None,
+        }
+    }
+
+    #[test]
+    fn merging_keeps_presses_and_releases() {
+        let mut merged = vec![contact(1, State::CONTACT_MOVE, 1.0), contact(2, State::CONTACT_MOVE, 5.0)];
+        let frames = [
+            contact(1, State::CONTACT_MOVE, 2.0), contact(3, State::CONTACT_START, 7.0),
+            contact(1, State::CONTACT_END, 3.0), contact(3, State::CONTACT_MOVE, 8.0),
+            contact(3, State::CONTACT_MOVE, 9.0),
+        ];
+        for &c in &frames {
+            merge(&mut merged, c);
+        }
+        let states: Vec<_> = merged.iter().map(|c| (c.id, c.state, c.x)).collect();
+        assert_eq!(states, vec![
+            (1, State::CONTACT_END, 3.0),
+            (2, State::CONTACT_MOVE, 5.0),
+            (3, State::CONTACT_START, 7.0),
+            (3, State::CONTACT_MOVE, 9.0),
+        ]);
+    }
+
+    #[test]
+    fn frames_that_fail_to_read_count_as_lost() {
+        // a pressure image, which isn't decoded over serial, then an empty frame
+        let (device, _) = fake::open(&[&[FRAME_CONTENT_PRESSURE_MASK as u8, 0], &[0, 2]]);
+        let device = device.start_scanning().unwrap();
+        let mut reader = FrameReader::new(&device, Buffering::All);
+        assert!(reader.read(&device).unwrap().is_empty());
+        let frames = reader.read(&device).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].lost_frame_count, 3);
+    }
+
+    #[test]
+    fn frames_read_together_are_a_frame_period_apart() {
+        let mut frames: Vec<ReadFrame> = [1, 3, 1].iter()
+            .map(|&n| ReadFrame { frames: n, ..Default::default() })
+            .collect();
+        set_ages(&mut frames, frame_period(500));
+        let ages: Vec<_> = frames.iter().map(|f| f.age).collect();
+        assert_eq!(ages, vec![Duration::from_millis(8), Duration::from_millis(2), Duration::ZERO]);
+    }
+
+    #[test]
+    fn only_async_reads_wait() {
+        assert_eq!(idle_time(SenselScanMode::SCAN_MODE_SYNC, 125), None);
+        assert_eq!(idle_time(SenselScanMode::SCAN_MODE_ASYNC, 2000), Some(Duration::from_micros(250)));
+        assert_eq!(idle_time(SenselScanMode::SCAN_MODE_ASYNC, 0), Some(Duration::from_micros(500)));
+    }
+}
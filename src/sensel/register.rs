@@ -0,0 +1,334 @@
+// The registers of sensel_register_map.h, each with its value type and
+// whether it can be read or written, checked when compiling.
+
+use std::marker::PhantomData;
+
+use super::bindings::*;
+use super::device::Device;
+use super::result::SenselError;
+
+pub struct ReadOnly;
+pub struct ReadWrite;
+pub struct WriteOnly;
+
+pub trait Access {
+    const NAME: &'static str;
+}
+pub trait Readable: Access {}
+pub trait Writable: Access {}
+
+impl Access for ReadOnly {
+    const NAME: &'static str = "r";
+}
+impl Access for ReadWrite {
+    const NAME: &'static str = "rw";
+}
+impl Access for WriteOnly {
+    const NAME: &'static str = "w";
+}
+impl Readable for ReadOnly {}
+impl Readable for ReadWrite {}
+impl Writable for ReadWrite {}
+impl Writable for WriteOnly {}
+
+/// What a register holds, little endian on the wire.
+pub trait Value: Sized {
+    const SIZE: usize;
+    fn from_bytes(bytes: &[u8]) -> Self;
+    fn to_bytes(&self) -> Vec<u8>;
+    /// From the command line, decimal or 0x hex.
+    fn parse(s: &str) -> Result<Self, String>;
+    fn show(&self) -> String;
+}
+
+macro_rules! int_value {
+    ($($ty:ty),*) => {
+        $(
+            impl Value for $ty {
+                const SIZE: usize = std::mem::size_of::<$ty>();
+                fn from_bytes(bytes: &[u8]) -> Self {
+                    let mut le = [0; std::mem::size_of::<$ty>()];
+                    le.copy_from_slice(&bytes[..Self::SIZE]);
+                    <$ty>::from_le_bytes(le)
+                }
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+                fn parse(s: &str) -> Result<Self, String> {
+                    match s.strip_prefix("0x") {
+                        Some(hex) => <$ty>::from_str_radix(hex, 16),
+                        None => s.parse(),
+                    }.map_err(|e| format!("'{}': {}", s, e))
+                }
+                fn show(&self) -> String {
+                    format!("{} (0x{:0width$x})", self, self, width = Self::SIZE * 2)
+                }
+            }
+        )*
+    };
+}
+
+int_value!(u8, u16, u32, i16);
+
+// the magic, text
+impl Value for [u8; 6] {
+    const SIZE: usize = 6;
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut value = [0; 6];
+        value.copy_from_slice(&bytes[..6]);
+        value
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+    fn parse(s: &str) -> Result<Self, String> {
+        if s.len() != 6 {
+            return Err(format!("'{}': expected 6 characters", s));
+        }
+        Ok(Self::from_bytes(s.as_bytes()))
+    }
+    fn show(&self) -> String {
+        String::from_utf8_lossy(self).into_owned()
+    }
+}
+
+// a register whose size the device decides, e.g. the LED array, as comma
+// separated bytes
+impl Value for Vec<u8> {
+    // none of its own, it's read and written whole
+    const SIZE: usize = 0;
+    fn from_bytes(bytes: &[u8]) -> Self {
+        bytes.to_vec()
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+    fn parse(s: &str) -> Result<Self, String> {
+        if s.is_empty() {
+            return Ok(vec![]);
+        }
+        s.split(',').map(|b| u8::parse(b.trim())).collect()
+    }
+    fn show(&self) -> String {
+        self.iter().map(u8::to_string).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// A register holding a `T`, readable and writable as `A` allows.
+pub struct Reg<T, A> {
+    pub name: &'static str,
+    pub addr: u8,
+    _value: PhantomData<(T, A)>,
+}
+
+impl<T, A> Clone for Reg<T, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T, A> Copy for Reg<T, A> {}
+
+impl<T: Value, A: Access> Reg<T, A> {
+    const fn new(name: &'static str, addr: u32) -> Self {
+        Reg { name, addr: addr as u8, _value: PhantomData }
+    }
+
+    const fn info(self) -> Register {
+        Register { name: self.name, addr: self.addr, size: T::SIZE, access: A::NAME }
+    }
+}
+
+/// A register's description, for listing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Register {
+    pub name: &'static str,
+    pub addr: u8,
+    pub size: usize,
+    // "r", "rw" or "w"
+    pub access: &'static str,
+}
+
+macro_rules! registers {
+    ($($name:ident($access:ident): $ty:ty = $addr:expr;)*) => {
+        $(
+            pub const $name: Reg<$ty, $access> = Reg::new(stringify!($name), $addr);
+        )*
+
+        /// Every register, by address.
+        pub const ALL: &[Register] = &[$($name.info(),)*];
+
+        /// Read a register by name, its value shown, None if it isn't one.
+        pub fn read_named<D: Device>(device: &D, name: &str) -> Option<Result<String, SenselError>> {
+            $(
+                if name.eq_ignore_ascii_case(stringify!($name)) {
+                    return Some(registers!(@read $access, device, $name));
+                }
+            )*
+            None
+        }
+
+        /// Write a register by name, the value parsed for its type.
+        pub fn write_named<D: Device>(device: &D, name: &str, value: &str) -> Option<Result<(), String>> {
+            $(
+                if name.eq_ignore_ascii_case(stringify!($name)) {
+                    return Some(registers!(@write $access, device, $name, value));
+                }
+            )*
+            None
+        }
+    };
+    (@read WriteOnly, $device:ident, $name:ident) => {
+        Err(SenselError)
+    };
+    (@read $access:ident, $device:ident, $name:ident) => {
+        $device.read_reg($name).map(|v| v.show())
+    };
+    (@write ReadOnly, $device:ident, $name:ident, $value:ident) => {
+        Err(format!("{} is read only", stringify!($name).to_lowercase()))
+    };
+    (@write $access:ident, $device:ident, $name:ident, $value:ident) => {
+        Value::parse($value).and_then(|v| $device.write_reg($name, v)
+            .map_err(|_| format!("device rejected {} {}", stringify!($name).to_lowercase(), $value)))
+    };
+}
+
+registers! {
+    MAGIC(ReadOnly): [u8; 6] = SENSEL_REG_MAGIC;
+    FW_PROTOCOL_VERSION(ReadOnly): u8 = SENSEL_REG_FW_PROTOCOL_VERSION;
+    FW_VERSION_MAJOR(ReadOnly): u8 = SENSEL_REG_FW_VERSION_MAJOR;
+    FW_VERSION_MINOR(ReadOnly): u8 = SENSEL_REG_FW_VERSION_MINOR;
+    FW_VERSION_BUILD(ReadOnly): u16 = SENSEL_REG_FW_VERSION_BUILD;
+    FW_VERSION_RELEASE(ReadOnly): u8 = SENSEL_REG_FW_VERSION_RELEASE;
+    DEVICE_ID(ReadOnly): u16 = SENSEL_REG_DEVICE_ID;
+    DEVICE_REVISION(ReadOnly): u8 = SENSEL_REG_DEVICE_REVISION;
+    DEVICE_SERIAL_NUMBER(ReadOnly): Vec<u8> = SENSEL_REG_DEVICE_SERIAL_NUMBER;
+    SENSOR_NUM_COLS(ReadOnly): u16 = SENSEL_REG_SENSOR_NUM_COLS;
+    SENSOR_NUM_ROWS(ReadOnly): u16 = SENSEL_REG_SENSOR_NUM_ROWS;
+    SENSOR_ACTIVE_AREA_WIDTH_UM(ReadOnly): u32 = SENSEL_REG_SENSOR_ACTIVE_AREA_WIDTH_UM;
+    SENSOR_ACTIVE_AREA_HEIGHT_UM(ReadOnly): u32 = SENSEL_REG_SENSOR_ACTIVE_AREA_HEIGHT_UM;
+    COMPRESSION_METADATA(ReadOnly): u8 = SENSEL_REG_COMPRESSION_METADATA;
+    SCAN_FRAME_RATE(ReadWrite): u16 = SENSEL_REG_SCAN_FRAME_RATE;
+    SCAN_BUFFER_CONTROL(ReadWrite): u8 = SENSEL_REG_SCAN_BUFFER_CONTROL;
+    SCAN_DETAIL_CONTROL(ReadWrite): u8 = SENSEL_REG_SCAN_DETAIL_CONTROL;
+    FRAME_CONTENT_CONTROL(ReadWrite): u8 = SENSEL_REG_FRAME_CONTENT_CONTROL;
+    SCAN_ENABLED(ReadWrite): u8 = SENSEL_REG_SCAN_ENABLED;
+    SCAN_READ_FRAME(ReadOnly): Vec<u8> = SENSEL_REG_SCAN_READ_FRAME;
+    FRAME_CONTENT_SUPPORTED(ReadOnly): u8 = SENSEL_REG_FRAME_CONTENT_SUPPORTED;
+    CONTACTS_MAX_COUNT(ReadOnly): u8 = SENSEL_REG_CONTACTS_MAX_COUNT;
+    CONTACTS_ENABLE_BLOB_MERGE(ReadWrite): u8 = SENSEL_REG_CONTACTS_ENABLE_BLOB_MERGE;
+    CONTACTS_MIN_FORCE(ReadWrite): u16 = SENSEL_REG_CONTACTS_MIN_FORCE;
+    CONTACTS_MASK(ReadWrite): u8 = SENSEL_REG_CONTACTS_MASK;
+    BASELINE_ENABLED(ReadWrite): u8 = SENSEL_REG_BASELINE_ENABLED;
+    BASELINE_INCREASE_RATE(ReadWrite): u16 = SENSEL_REG_BASELINE_INCREASE_RATE;
+    BASELINE_DECREASE_RATE(ReadWrite): u16 = SENSEL_REG_BASELINE_DECREASE_RATE;
+    BASELINE_DYNAMIC_ENABLED(ReadWrite): u8 = SENSEL_REG_BASELINE_DYNAMIC_ENABLED;
+    ACCEL_X(ReadOnly): i16 = SENSEL_REG_ACCEL_X;
+    ACCEL_Y(ReadOnly): i16 = SENSEL_REG_ACCEL_Y;
+    ACCEL_Z(ReadOnly): i16 = SENSEL_REG_ACCEL_Z;
+    BATTERY_STATUS(ReadOnly): u8 = SENSEL_REG_BATTERY_STATUS;
+    BATTERY_PERCENTAGE(ReadOnly): u8 = SENSEL_REG_BATTERY_PERCENTAGE;
+    POWER_BUTTON_PRESSED(ReadOnly): u8 = SENSEL_REG_POWER_BUTTON_PRESSED;
+    // the whole LED array, LED_COUNT entries of LED_BRIGHTNESS_SIZE bytes
+    LED_BRIGHTNESS(ReadWrite): Vec<u8> = SENSEL_REG_LED_BRIGHTNESS;
+    LED_BRIGHTNESS_SIZE(ReadOnly): u8 = SENSEL_REG_LED_BRIGHTNESS_SIZE;
+    LED_BRIGHTNESS_MAX(ReadOnly): u16 = SENSEL_REG_LED_BRIGHTNESS_MAX;
+    LED_COUNT(ReadOnly): u8 = SENSEL_REG_LED_COUNT;
+    UNIT_SHIFT_DIMS(ReadOnly): u8 = SENSEL_REG_UNIT_SHIFT_DIMS;
+    UNIT_SHIFT_FORCE(ReadOnly): u8 = SENSEL_REG_UNIT_SHIFT_FORCE;
+    UNIT_SHIFT_AREA(ReadOnly): u8 = SENSEL_REG_UNIT_SHIFT_AREA;
+    UNIT_SHIFT_ANGLE(ReadOnly): u8 = SENSEL_REG_UNIT_SHIFT_ANGLE;
+    UNIT_SHIFT_TIME(ReadOnly): u8 = SENSEL_REG_UNIT_SHIFT_TIME;
+    DEVICE_OPEN(ReadWrite): u8 = SENSEL_REG_DEVICE_OPEN;
+    SOFT_RESET(WriteOnly): u8 = SENSEL_REG_SOFT_RESET;
+    ERROR_CODE(ReadOnly): u8 = SENSEL_REG_ERROR_CODE;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensel::serial::fake;
+
+    #[test]
+    fn sizes_match_the_register_map() {
+        let map = [
+            ("MAGIC", SENSEL_REG_SIZE_MAGIC),
+            ("FW_PROTOCOL_VERSION", SENSEL_REG_SIZE_FW_PROTOCOL_VERSION),
+            ("FW_VERSION_MAJOR", SENSEL_REG_SIZE_FW_VERSION_MAJOR),
+            ("FW_VERSION_MINOR", SENSEL_REG_SIZE_FW_VERSION_MINOR),
+            ("FW_VERSION_BUILD", SENSEL_REG_SIZE_FW_VERSION_BUILD),
+            ("FW_VERSION_RELEASE", SENSEL_REG_SIZE_FW_VERSION_RELEASE),
+            ("DEVICE_ID", SENSEL_REG_SIZE_DEVICE_ID),
+            ("DEVICE_REVISION", SENSEL_REG_SIZE_DEVICE_REVISION),
+            ("DEVICE_SERIAL_NUMBER", SENSEL_REG_SIZE_DEVICE_SERIAL_NUMBER),
+            ("SENSOR_NUM_COLS", SENSEL_REG_SIZE_SENSOR_NUM_COLS),
+            ("SENSOR_NUM_ROWS", SENSEL_REG_SIZE_SENSOR_NUM_ROWS),
+            ("SENSOR_ACTIVE_AREA_WIDTH_UM", SENSEL_REG_SIZE_SENSOR_ACTIVE_AREA_WIDTH_UM),
+            ("SENSOR_ACTIVE_AREA_HEIGHT_UM", SENSEL_REG_SIZE_SENSOR_ACTIVE_AREA_HEIGHT_UM),
+            ("COMPRESSION_METADATA", SENSEL_REG_SIZE_COMPRESSION_METADATA),
+            ("SCAN_FRAME_RATE", SENSEL_REG_SIZE_SCAN_FRAME_RATE),
+            ("SCAN_BUFFER_CONTROL", SENSEL_REG_SIZE_SCAN_BUFFER_CONTROL),
+            ("SCAN_DETAIL_CONTROL", SENSEL_REG_SIZE_SCAN_DETAIL_CONTROL),
+            ("FRAME_CONTENT_CONTROL", SENSEL_REG_SIZE_FRAME_CONTENT_CONTROL),
+            ("SCAN_ENABLED", SENSEL_REG_SIZE_SCAN_ENABLED),
+            ("SCAN_READ_FRAME", SENSEL_REG_SIZE_SCAN_READ_FRAME),
+            ("FRAME_CONTENT_SUPPORTED", SENSEL_REG_SIZE_FRAME_CONTENT_SUPPORTED),
+            ("CONTACTS_MAX_COUNT", SENSEL_REG_SIZE_CONTACTS_MAX_COUNT),
+            ("CONTACTS_ENABLE_BLOB_MERGE", SENSEL_REG_SIZE_CONTACTS_ENABLE_BLOB_MERGE),
+            ("CONTACTS_MIN_FORCE", SENSEL_REG_SIZE_CONTACTS_MIN_FORCE),
+            ("CONTACTS_MASK", SENSEL_REG_SIZE_CONTACTS_MASK),
+            ("BASELINE_ENABLED", SENSEL_REG_SIZE_BASELINE_ENABLED),
+            ("BASELINE_INCREASE_RATE", SENSEL_REG_SIZE_BASELINE_INCREASE_RATE),
+            ("BASELINE_DECREASE_RATE", SENSEL_REG_SIZE_BASELINE_DECREASE_RATE),
+            ("BASELINE_DYNAMIC_ENABLED", SENSEL_REG_SIZE_BASELINE_DYNAMIC_ENABLED),
+            ("ACCEL_X", SENSEL_REG_SIZE_ACCEL_X),
+            ("ACCEL_Y", SENSEL_REG_SIZE_ACCEL_Y),
+            ("ACCEL_Z", SENSEL_REG_SIZE_ACCEL_Z),
+            ("BATTERY_STATUS", SENSEL_REG_SIZE_BATTERY_STATUS),
+            ("BATTERY_PERCENTAGE", SENSEL_REG_SIZE_BATTERY_PERCENTAGE),
+            ("POWER_BUTTON_PRESSED", SENSEL_REG_SIZE_POWER_BUTTON_PRESSED),
+            ("LED_BRIGHTNESS", SENSEL_REG_SIZE_LED_BRIGHTNESS),
+            ("LED_BRIGHTNESS_SIZE", SENSEL_REG_SIZE_LED_BRIGHTNESS_SIZE),
+            ("LED_BRIGHTNESS_MAX", SENSEL_REG_SIZE_LED_BRIGHTNESS_MAX),
+            ("LED_COUNT", SENSEL_REG_SIZE_LED_COUNT),
+            ("UNIT_SHIFT_DIMS", SENSEL_REG_SIZE_UNIT_SHIFT_DIMS),
+            ("UNIT_SHIFT_FORCE", SENSEL_REG_SIZE_UNIT_SHIFT_FORCE),
+            ("UNIT_SHIFT_AREA", SENSEL_REG_SIZE_UNIT_SHIFT_AREA),
+            ("UNIT_SHIFT_ANGLE", SENSEL_REG_SIZE_UNIT_SHIFT_ANGLE),
+            ("UNIT_SHIFT_TIME", SENSEL_REG_SIZE_UNIT_SHIFT_TIME),
+            ("DEVICE_OPEN", SENSEL_REG_SIZE_DEVICE_OPEN),
+            ("SOFT_RESET", SENSEL_REG_SIZE_SOFT_RESET),
+            ("ERROR_CODE", SENSEL_REG_SIZE_ERROR_CODE),
+        ];
+        // the map gives these a nominal size of 1
+        let variable = ["DEVICE_SERIAL_NUMBER", "SCAN_READ_FRAME", "LED_BRIGHTNESS"];
+        assert_eq!(ALL.len(), map.len());
+        for (reg, (name, size)) in ALL.iter().zip(map.iter()) {
+            let size = if variable.contains(name) { 0 } else { *size as usize };
+            assert_eq!((reg.name, reg.size), (*name, size));
+        }
+        // and none overlap
+        assert!(ALL.windows(2).all(|w| w[0].addr as usize + w[0].size <= w[1].addr as usize));
+    }
+
+    #[test]
+    fn values_round_trip() {
+        assert_eq!(u16::parse("0x01f4"), Ok(500));
+        assert_eq!(u16::from_bytes(&500u16.to_bytes()), 500);
+        assert_eq!(i16::from_bytes(&[0x30, 0xfd]), -720);
+        assert_eq!(500u16.show(), "500 (0x01f4)");
+        assert!(u8::parse("256").is_err());
+        assert_eq!(<[u8; 6]>::parse("S3NS31").map(|m| m.show()), Ok("S3NS31".to_string()));
+        assert_eq!(Vec::<u8>::parse("0, 255,0x10"), Ok(vec![0, 255, 16]));
+        assert_eq!(vec![0u8, 255, 16].show(), "0,255,16");
+        assert!(Vec::<u8>::parse("1,256").is_err());
+    }
+
+    #[test]
+    fn variable_size_registers_are_read_and_written_whole() {
+        let (device, _) = fake::open(&[]);
+        assert_eq!(read_named(&device, "led_brightness").map(|r| r.ok()), Some(Some(String::new())));
+        assert_eq!(write_named(&device, "led_brightness", "1,2,0x10"), Some(Ok(())));
+        assert_eq!(read_named(&device, "led_brightness").map(|r| r.ok()), Some(Some("1,2,16".to_string())));
+        assert!(write_named(&device, "device_serial_number", "1").unwrap().is_err());
+    }
+}
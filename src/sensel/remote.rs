@@ -0,0 +1,137 @@
+use std::sync::{mpsc, Arc, Mutex};
+
+use super::result::*;
+use super::device::{Device, ScanningDevice};
+
+pub(crate) type Job = Box<dyn FnOnce(&ScanningDevice) + Send>;
+
+// the one sender of a queue, shared by its remotes, None once closed
+type Sender = Arc<Mutex<Option<mpsc::Sender<Job>>>>;
+
+/// Requests waiting for the thread that reads the device.
+pub(crate) struct Queue {
+    tx: Sender,
+    rx: mpsc::Receiver<Job>,
+}
+
+impl Queue {
+    pub(crate) fn new() -> Queue {
+        let (tx, rx) = mpsc::channel();
+        Queue { tx: Arc::new(Mutex::new(Some(tx))), rx }
+    }
+
+    pub(crate) fn remote(&self) -> Remote {
+        Remote { tx: self.tx.clone() }
+    }
+
+    pub(crate) fn run(&self, device: &ScanningDevice) {
+        while let Ok(job) = self.rx.try_recv() {
+            job(device);
+        }
+    }
+
+    /// Take requests again after `close`.
+    pub(crate) fn open(&mut self) {
+        if let Ok(mut sender) = self.tx.lock() {
+            if sender.is_none() {
+                let (tx, rx) = mpsc::channel();
+                *sender = Some(tx);
+                self.rx = rx;
+            }
+        }
+    }
+
+    /// Fail the requests waiting and any made until opened again.
+    pub(crate) fn close(&self) {
+        if let Ok(mut sender) = self.tx.lock() {
+            *sender = None;
+        }
+        // dropping a job drops its reply, failing the call
+        self.rx.try_iter().for_each(drop);
+    }
+}
+
+/// Changes the device from other threads. Each call is queued and carried out
+/// by the thread scanning the device in its next `read_sensor`, so it never
+/// comes between frame reads, and waits for the result.
+///
+/// Calls fail once the device stops scanning or is dropped, and must not be
+/// made from the scanning thread itself, which would wait for itself.
+#[derive(Clone)]
+pub struct Remote {
+    tx: Sender,
+}
+
+impl Remote {
+    pub fn call<T, F>(&self, f: F) -> Result<T, SenselError>
+        where T: Send + 'static, F: FnOnce(&ScanningDevice) -> Result<T, SenselError> + Send + 'static
+    {
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.send(Box::new(move |device| {
+            let _ = tx.send(f(device));
+        }))?;
+        rx.recv().unwrap_or(Err(SenselError))
+    }
+
+    pub(crate) fn send(&self, job: Job) -> Result<(), SenselError> {
+        match *self.tx.lock().or(Err(SenselError))? {
+            Some(ref tx) => tx.send(job).or(Err(SenselError)),
+            None => Err(SenselError),
+        }
+    }
+
+    pub fn set_led_array(&self, led_array: Vec<u16>) -> Result<(), SenselError> {
+        self.call(move |device| device.set_led_array(&led_array))
+    }
+    pub fn set_led_brightness(&self, led_id: u8, brightness: u16) -> Result<(), SenselError> {
+        self.call(move |device| device.set_led_brightness(led_id, brightness))
+    }
+    pub fn set_led_levels(&self, levels: Vec<f32>) -> Result<Vec<u16>, SenselError> {
+        self.call(move |device| device.set_led_levels(&levels))
+    }
+    pub fn set_led_level(&self, led_id: u8, level: f32) -> Result<u16, SenselError> {
+        self.call(move |device| device.set_led_level(led_id, level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn devices_and_remotes_can_move_between_threads() {
+        assert_send::<ScanningDevice>();
+        assert_send::<Remote>();
+    }
+
+    #[test]
+    fn calls_fail_once_the_device_is_gone() {
+        let queue = Queue::new();
+        let remote = queue.remote();
+        drop(queue);
+        assert!(remote.set_led_brightness(0, 1).is_err());
+    }
+
+    #[test]
+    fn calls_fail_once_scanning_stops() {
+        let mut queue = Queue::new();
+        let remote = queue.remote();
+        // waiting for a scan that never comes, until it's called off
+        let pending = {
+            let remote = remote.clone();
+            thread::spawn(move || remote.set_led_brightness(0, 1))
+        };
+        thread::sleep(Duration::from_millis(50));
+        queue.close();
+        assert!(pending.join().unwrap().is_err());
+        assert!(remote.set_led_brightness(0, 1).is_err());
+
+        queue.open();
+        assert!(remote.send(Box::new(|_| ())).is_ok());
+    }
+}
@@ -0,0 +1,227 @@
+// The parts of sensel-api's sensel.h and sensel_register_map.h that are used
+// here, as bindgen generates them. `--features bindgen` regenerates the full
+// set from the external/sensel-api submodule instead.
+
+pub const SENSEL_MAX_DEVICES: u32 = 16;
+pub const FRAME_CONTENT_PRESSURE_MASK: u32 = 1;
+pub const FRAME_CONTENT_LABELS_MASK: u32 = 2;
+pub const FRAME_CONTENT_CONTACTS_MASK: u32 = 4;
+pub const FRAME_CONTENT_ACCEL_MASK: u32 = 8;
+pub const CONTACT_MASK_ELLIPSE: u32 = 1;
+pub const CONTACT_MASK_DELTAS: u32 = 2;
+pub const CONTACT_MASK_BOUNDING_BOX: u32 = 4;
+pub const CONTACT_MASK_PEAK: u32 = 8;
+
+#[repr(i32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SenselStatus {
+    SENSEL_OK = 0,
+    SENSEL_ERROR = -1,
+}
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SenselScanDetail {
+    SCAN_DETAIL_HIGH = 0,
+    SCAN_DETAIL_MEDIUM = 1,
+    SCAN_DETAIL_LOW = 2,
+    SCAN_DETAIL_UNKNOWN = 3,
+}
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SenselContactState {
+    CONTACT_INVALID = 0,
+    CONTACT_START = 1,
+    CONTACT_MOVE = 2,
+    CONTACT_END = 3,
+}
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SenselScanMode {
+    SCAN_MODE_DISABLE = 0,
+    SCAN_MODE_SYNC = 1,
+    SCAN_MODE_ASYNC = 2,
+}
+
+pub type SENSEL_HANDLE = *mut ::std::os::raw::c_void;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SenselDeviceID {
+    pub idx: ::std::os::raw::c_uchar,
+    pub serial_num: [::std::os::raw::c_uchar; 64usize],
+    pub com_port: [::std::os::raw::c_uchar; 64usize],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SenselDeviceList {
+    pub num_devices: ::std::os::raw::c_uchar,
+    pub devices: [SenselDeviceID; 16usize],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SenselSensorInfo {
+    pub max_contacts: ::std::os::raw::c_uchar,
+    pub num_rows: ::std::os::raw::c_ushort,
+    pub num_cols: ::std::os::raw::c_ushort,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SenselFirmwareInfo {
+    pub fw_protocol_version: ::std::os::raw::c_uchar,
+    pub fw_version_major: ::std::os::raw::c_uchar,
+    pub fw_version_minor: ::std::os::raw::c_uchar,
+    pub fw_version_build: ::std::os::raw::c_ushort,
+    pub fw_version_release: ::std::os::raw::c_uchar,
+    pub device_id: ::std::os::raw::c_ushort,
+    pub device_revision: ::std::os::raw::c_uchar,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SenselContact {
+    pub content_bit_mask: ::std::os::raw::c_uchar,
+    pub id: ::std::os::raw::c_uchar,
+    pub state: ::std::os::raw::c_uint,
+    pub x_pos: f32,
+    pub y_pos: f32,
+    pub total_force: f32,
+    pub area: f32,
+    pub orientation: f32,
+    pub major_axis: f32,
+    pub minor_axis: f32,
+    pub delta_x: f32,
+    pub delta_y: f32,
+    pub delta_force: f32,
+    pub delta_area: f32,
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+    pub peak_x: f32,
+    pub peak_y: f32,
+    pub peak_force: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SenselAccelData {
+    pub x: ::std::os::raw::c_int,
+    pub y: ::std::os::raw::c_int,
+    pub z: ::std::os::raw::c_int,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SenselFrameData {
+    pub content_bit_mask: ::std::os::raw::c_uchar,
+    pub lost_frame_count: ::std::os::raw::c_int,
+    pub n_contacts: ::std::os::raw::c_uchar,
+    pub contacts: *mut SenselContact,
+    pub force_array: *mut f32,
+    pub labels_array: *mut ::std::os::raw::c_uchar,
+    pub accel_data: *mut SenselAccelData,
+}
+
+pub const SENSEL_REG_MAGIC: u32 = 0;
+pub const SENSEL_REG_FW_PROTOCOL_VERSION: u32 = 6;
+pub const SENSEL_REG_FW_VERSION_MAJOR: u32 = 7;
+pub const SENSEL_REG_FW_VERSION_MINOR: u32 = 8;
+pub const SENSEL_REG_FW_VERSION_BUILD: u32 = 9;
+pub const SENSEL_REG_FW_VERSION_RELEASE: u32 = 11;
+pub const SENSEL_REG_DEVICE_ID: u32 = 12;
+pub const SENSEL_REG_DEVICE_REVISION: u32 = 14;
+pub const SENSEL_REG_DEVICE_SERIAL_NUMBER: u32 = 15;
+pub const SENSEL_REG_SENSOR_NUM_COLS: u32 = 16;
+pub const SENSEL_REG_SENSOR_NUM_ROWS: u32 = 18;
+pub const SENSEL_REG_SENSOR_ACTIVE_AREA_WIDTH_UM: u32 = 20;
+pub const SENSEL_REG_SENSOR_ACTIVE_AREA_HEIGHT_UM: u32 = 24;
+pub const SENSEL_REG_COMPRESSION_METADATA: u32 = 28;
+pub const SENSEL_REG_SCAN_FRAME_RATE: u32 = 32;
+pub const SENSEL_REG_SCAN_BUFFER_CONTROL: u32 = 34;
+pub const SENSEL_REG_SCAN_DETAIL_CONTROL: u32 = 35;
+pub const SENSEL_REG_FRAME_CONTENT_CONTROL: u32 = 36;
+pub const SENSEL_REG_SCAN_ENABLED: u32 = 37;
+pub const SENSEL_REG_SCAN_READ_FRAME: u32 = 38;
+pub const SENSEL_REG_FRAME_CONTENT_SUPPORTED: u32 = 40;
+pub const SENSEL_REG_CONTACTS_MAX_COUNT: u32 = 64;
+pub const SENSEL_REG_CONTACTS_ENABLE_BLOB_MERGE: u32 = 65;
+pub const SENSEL_REG_CONTACTS_MIN_FORCE: u32 = 71;
+pub const SENSEL_REG_CONTACTS_MASK: u32 = 75;
+pub const SENSEL_REG_BASELINE_ENABLED: u32 = 80;
+pub const SENSEL_REG_BASELINE_INCREASE_RATE: u32 = 81;
+pub const SENSEL_REG_BASELINE_DECREASE_RATE: u32 = 83;
+pub const SENSEL_REG_BASELINE_DYNAMIC_ENABLED: u32 = 87;
+pub const SENSEL_REG_ACCEL_X: u32 = 96;
+pub const SENSEL_REG_ACCEL_Y: u32 = 98;
+pub const SENSEL_REG_ACCEL_Z: u32 = 100;
+pub const SENSEL_REG_BATTERY_STATUS: u32 = 112;
+pub const SENSEL_REG_BATTERY_PERCENTAGE: u32 = 113;
+pub const SENSEL_REG_POWER_BUTTON_PRESSED: u32 = 114;
+pub const SENSEL_REG_LED_BRIGHTNESS: u32 = 128;
+pub const SENSEL_REG_LED_BRIGHTNESS_SIZE: u32 = 129;
+pub const SENSEL_REG_LED_BRIGHTNESS_MAX: u32 = 130;
+pub const SENSEL_REG_LED_COUNT: u32 = 132;
+pub const SENSEL_REG_UNIT_SHIFT_DIMS: u32 = 160;
+pub const SENSEL_REG_UNIT_SHIFT_FORCE: u32 = 161;
+pub const SENSEL_REG_UNIT_SHIFT_AREA: u32 = 162;
+pub const SENSEL_REG_UNIT_SHIFT_ANGLE: u32 = 163;
+pub const SENSEL_REG_UNIT_SHIFT_TIME: u32 = 164;
+pub const SENSEL_REG_DEVICE_OPEN: u32 = 208;
+pub const SENSEL_REG_SOFT_RESET: u32 = 224;
+pub const SENSEL_REG_ERROR_CODE: u32 = 236;
+
+pub const SENSEL_REG_SIZE_MAGIC: u32 = 6;
+pub const SENSEL_REG_SIZE_FW_PROTOCOL_VERSION: u32 = 1;
+pub const SENSEL_REG_SIZE_FW_VERSION_MAJOR: u32 = 1;
+pub const SENSEL_REG_SIZE_FW_VERSION_MINOR: u32 = 1;
+pub const SENSEL_REG_SIZE_FW_VERSION_BUILD: u32 = 2;
+pub const SENSEL_REG_SIZE_FW_VERSION_RELEASE: u32 = 1;
+pub const SENSEL_REG_SIZE_DEVICE_ID: u32 = 2;
+pub const SENSEL_REG_SIZE_DEVICE_REVISION: u32 = 1;
+pub const SENSEL_REG_SIZE_DEVICE_SERIAL_NUMBER: u32 = 1;
+pub const SENSEL_REG_SIZE_SENSOR_NUM_COLS: u32 = 2;
+pub const SENSEL_REG_SIZE_SENSOR_NUM_ROWS: u32 = 2;
+pub const SENSEL_REG_SIZE_SENSOR_ACTIVE_AREA_WIDTH_UM: u32 = 4;
+pub const SENSEL_REG_SIZE_SENSOR_ACTIVE_AREA_HEIGHT_UM: u32 = 4;
+pub const SENSEL_REG_SIZE_COMPRESSION_METADATA: u32 = 1;
+pub const SENSEL_REG_SIZE_SCAN_FRAME_RATE: u32 = 2;
+pub const SENSEL_REG_SIZE_SCAN_BUFFER_CONTROL: u32 = 1;
+pub const SENSEL_REG_SIZE_SCAN_DETAIL_CONTROL: u32 = 1;
+pub const SENSEL_REG_SIZE_FRAME_CONTENT_CONTROL: u32 = 1;
+pub const SENSEL_REG_SIZE_SCAN_ENABLED: u32 = 1;
+pub const SENSEL_REG_SIZE_SCAN_READ_FRAME: u32 = 1;
+pub const SENSEL_REG_SIZE_FRAME_CONTENT_SUPPORTED: u32 = 1;
+pub const SENSEL_REG_SIZE_CONTACTS_MAX_COUNT: u32 = 1;
+pub const SENSEL_REG_SIZE_CONTACTS_ENABLE_BLOB_MERGE: u32 = 1;
+pub const SENSEL_REG_SIZE_CONTACTS_MIN_FORCE: u32 = 2;
+pub const SENSEL_REG_SIZE_CONTACTS_MASK: u32 = 1;
+pub const SENSEL_REG_SIZE_BASELINE_ENABLED: u32 = 1;
+pub const SENSEL_REG_SIZE_BASELINE_INCREASE_RATE: u32 = 2;
+pub const SENSEL_REG_SIZE_BASELINE_DECREASE_RATE: u32 = 2;
+pub const SENSEL_REG_SIZE_BASELINE_DYNAMIC_ENABLED: u32 = 1;
+pub const SENSEL_REG_SIZE_ACCEL_X: u32 = 2;
+pub const SENSEL_REG_SIZE_ACCEL_Y: u32 = 2;
+pub const SENSEL_REG_SIZE_ACCEL_Z: u32 = 2;
+pub const SENSEL_REG_SIZE_BATTERY_STATUS: u32 = 1;
+pub const SENSEL_REG_SIZE_BATTERY_PERCENTAGE: u32 = 1;
+pub const SENSEL_REG_SIZE_POWER_BUTTON_PRESSED: u32 = 1;
+pub const SENSEL_REG_SIZE_LED_BRIGHTNESS: u32 = 1;
+pub const SENSEL_REG_SIZE_LED_BRIGHTNESS_SIZE: u32 = 1;
+pub const SENSEL_REG_SIZE_LED_BRIGHTNESS_MAX: u32 = 2;
+pub const SENSEL_REG_SIZE_LED_COUNT: u32 = 1;
+pub const SENSEL_REG_SIZE_UNIT_SHIFT_DIMS: u32 = 1;
+pub const SENSEL_REG_SIZE_UNIT_SHIFT_FORCE: u32 = 1;
+pub const SENSEL_REG_SIZE_UNIT_SHIFT_AREA: u32 = 1;
+pub const SENSEL_REG_SIZE_UNIT_SHIFT_ANGLE: u32 = 1;
+pub const SENSEL_REG_SIZE_UNIT_SHIFT_TIME: u32 = 1;
+pub const SENSEL_REG_SIZE_DEVICE_OPEN: u32 = 1;
+pub const SENSEL_REG_SIZE_SOFT_RESET: u32 = 1;
+pub const SENSEL_REG_SIZE_ERROR_CODE: u32 = 1;
@@ -0,0 +1,765 @@
+// The Morph's register protocol over its USB serial port, spoken directly
+// instead of through libsensel. Pressure and label images aren't decoded,
+// frames carry contacts and acceleration only, and scanning is sync only:
+// the device reports both through its DeviceInfo, so neither is asked for.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::ptr;
+use std::time::Duration;
+
+use super::bindings::*;
+use super::device::{Backend, BaseDevice, DeviceInfo};
+use super::led;
+use super::register::{self, Readable, Reg, Value, Writable};
+use super::result::SenselError;
+
+const MAGIC: [u8; 6] = *b"S3NS31";
+
+// packet headers and the acknowledgements to them
+const READ_HEADER: u8 = 0x80;
+const READ_VS_HEADER: u8 = 0x81;
+const WRITE_HEADER: u8 = 0x00;
+const WRITE_VS_HEADER: u8 = 0x01;
+const READ_ACK: u8 = 6;
+const READ_VS_ACK: u8 = 10;
+const WRITE_ACK: u8 = 8;
+const WRITE_VS_ACK: u8 = 12;
+
+// a USB CDC port ignores the rate, but it has to be set
+const BAUD_RATE: u32 = 115_200;
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+// a frame has at most this many contacts
+const MAX_CONTACTS: usize = 256;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// Register reads and writes over anything byte oriented, a serial port or
+/// a test double.
+pub struct Protocol<P> {
+    port: P,
+}
+
+impl<P: Read + Write> Protocol<P> {
+    pub fn new(port: P) -> Self {
+        Protocol { port }
+    }
+
+    pub fn read_reg(&mut self, addr: u8, size: usize) -> io::Result<Vec<u8>> {
+        self.port.write_all(&[READ_HEADER, addr, size as u8])?;
+        self.port.flush()?;
+        let mut data = Vec::with_capacity(size + 1);
+        self.response(READ_ACK, &mut data)?;
+        if data.len() != size {
+            return Err(invalid("register read returned the wrong size"));
+        }
+        Ok(data)
+    }
+
+    /// Read a register whose size the device decides, e.g. a frame, into
+    /// `data`, replacing what it held.
+    pub fn read_reg_vs(&mut self, addr: u8, data: &mut Vec<u8>) -> io::Result<()> {
+        self.port.write_all(&[READ_VS_HEADER, addr, 0])?;
+        self.port.flush()?;
+        self.response(READ_VS_ACK, data)
+    }
+
+    pub fn write_reg(&mut self, addr: u8, data: &[u8]) -> io::Result<()> {
+        let mut packet = vec![WRITE_HEADER, addr, data.len() as u8];
+        packet.extend_from_slice(data);
+        packet.push(checksum(data));
+        self.send(&packet, WRITE_ACK)
+    }
+
+    /// Write a register whose size is that of `data`, e.g. the LED array.
+    pub fn write_reg_vs(&mut self, addr: u8, data: &[u8]) -> io::Result<()> {
+        let size = u16::try_from(data.len()).map_err(|_| invalid("register write too long"))?;
+        let mut packet = vec![WRITE_VS_HEADER, addr, 0];
+        packet.extend_from_slice(&size.to_le_bytes());
+        packet.extend_from_slice(data);
+        packet.push(checksum(data));
+        self.send(&packet, WRITE_VS_ACK)
+    }
+
+    fn send(&mut self, packet: &[u8], ack: u8) -> io::Result<()> {
+        self.port.write_all(packet)?;
+        self.port.flush()?;
+        let mut reply = [0];
+        self.port.read_exact(&mut reply)?;
+        if reply[0] != ack {
+            return Err(invalid("register write refused"));
+        }
+        Ok(())
+    }
+
+    // ack, little endian size, data, checksum of the data
+    fn response(&mut self, ack: u8, data: &mut Vec<u8>) -> io::Result<()> {
+        let mut header = [0; 3];
+        self.port.read_exact(&mut header)?;
+        if header[0] != ack {
+            return Err(invalid("register read refused"));
+        }
+        data.clear();
+        data.resize(u16::from_le_bytes([header[1], header[2]]) as usize + 1, 0);
+        self.port.read_exact(data)?;
+        let sum = data.pop().unwrap_or(0);
+        if sum != checksum(data) {
+            return Err(invalid("bad checksum"));
+        }
+        Ok(())
+    }
+
+    // registers without a size of their own are read and written whole
+    pub fn read<T: Value, A: Readable>(&mut self, reg: Reg<T, A>) -> io::Result<T> {
+        let mut data = vec![];
+        match T::SIZE {
+            0 => self.read_reg_vs(reg.addr, &mut data)?,
+            size => data = self.read_reg(reg.addr, size)?,
+        }
+        Ok(T::from_bytes(&data))
+    }
+
+    pub fn write<T: Value, A: Writable>(&mut self, reg: Reg<T, A>, value: T) -> io::Result<()> {
+        match T::SIZE {
+            0 => self.write_reg_vs(reg.addr, &value.to_bytes()),
+            _ => self.write_reg(reg.addr, &value.to_bytes()),
+        }
+    }
+}
+
+// divisors turning the device's fixed point values into libsensel's units
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Units {
+    dims: f32,
+    force: f32,
+    area: f32,
+    angle: f32,
+}
+
+impl Units {
+    fn read<P: Read + Write>(protocol: &mut Protocol<P>) -> io::Result<Units> {
+        let mut scale = |reg| protocol.read(reg).map(|shift: u8| 2f32.powi(i32::from(shift)));
+        Ok(Units {
+            dims: scale(register::UNIT_SHIFT_DIMS)?,
+            force: scale(register::UNIT_SHIFT_FORCE)?,
+            area: scale(register::UNIT_SHIFT_AREA)?,
+            angle: scale(register::UNIT_SHIFT_ANGLE)?,
+        })
+    }
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("frame too short"));
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.take(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        self.u16().map(|v| v as i16)
+    }
+}
+
+// a frame apart from its contacts
+#[derive(Debug)]
+struct Decoded {
+    content: u8,
+    lost_frame_count: i32,
+    accel: SenselAccelData,
+}
+
+// content mask, lost frame count, then for contacts the contact mask, their
+// number and each contact, then for acceleration x, y and z. Fields are in
+// the order of SenselFrameData and SenselContact, scaled by the UNIT_SHIFT
+// registers; this hasn't been checked against a capture from a device.
+fn decode_frame(data: &[u8], units: Units, contacts: &mut Vec<SenselContact>) -> io::Result<Decoded> {
+    let mut bytes = Bytes(data);
+    let content = bytes.u8()?;
+    let lost_frame_count = i32::from(bytes.u8()?);
+    if u32::from(content) & (FRAME_CONTENT_PRESSURE_MASK | FRAME_CONTENT_LABELS_MASK) != 0 {
+        return Err(invalid("pressure and label images aren't supported"));
+    }
+
+    contacts.clear();
+    if u32::from(content) & FRAME_CONTENT_CONTACTS_MASK != 0 {
+        let mask = bytes.u8()?;
+        let has = |bit: u32| u32::from(mask) & bit != 0;
+        for _ in 0..bytes.u8()? {
+            let mut c = SenselContact {
+                content_bit_mask: mask,
+                id: bytes.u8()?,
+                state: u32::from(bytes.u8()?),
+                x_pos: f32::from(bytes.u16()?) / units.dims,
+                y_pos: f32::from(bytes.u16()?) / units.dims,
+                total_force: f32::from(bytes.u16()?) / units.force,
+                area: f32::from(bytes.u16()?) / units.area,
+                orientation: 0.0,
+                major_axis: 0.0,
+                minor_axis: 0.0,
+                delta_x: 0.0,
+                delta_y: 0.0,
+                delta_force: 0.0,
+                delta_area: 0.0,
+                min_x: 0.0,
+                min_y: 0.0,
+                max_x: 0.0,
+                max_y: 0.0,
+                peak_x: 0.0,
+                peak_y: 0.0,
+                peak_force: 0.0,
+            };
+            if has(CONTACT_MASK_ELLIPSE) {
+                c.orientation = f32::from(bytes.i16()?) / units.angle;
+                c.major_axis = f32::from(bytes.u16()?) / units.dims;
+                c.minor_axis = f32::from(bytes.u16()?) / units.dims;
+            }
+            if has(CONTACT_MASK_DELTAS) {
+                c.delta_x = f32::from(bytes.i16()?) / units.dims;
+                c.delta_y = f32::from(bytes.i16()?) / units.dims;
+                c.delta_force = f32::from(bytes.i16()?) / units.force;
+                c.delta_area = f32::from(bytes.i16()?) / units.area;
+            }
+            if has(CONTACT_MASK_BOUNDING_BOX) {
+                c.min_x = f32::from(bytes.u16()?) / units.dims;
+                c.min_y = f32::from(bytes.u16()?) / units.dims;
+                c.max_x = f32::from(bytes.u16()?) / units.dims;
+                c.max_y = f32::from(bytes.u16()?) / units.dims;
+            }
+            if has(CONTACT_MASK_PEAK) {
+                c.peak_x = f32::from(bytes.u16()?) / units.dims;
+                c.peak_y = f32::from(bytes.u16()?) / units.dims;
+                c.peak_force = f32::from(bytes.u16()?) / units.force;
+            }
+            contacts.push(c);
+        }
+    }
+
+    let mut accel = SenselAccelData { x: 0, y: 0, z: 0 };
+    if u32::from(content) & FRAME_CONTENT_ACCEL_MASK != 0 {
+        accel.x = i32::from(bytes.i16()?);
+        accel.y = i32::from(bytes.i16()?);
+        accel.z = i32::from(bytes.i16()?);
+    }
+    Ok(Decoded { content, lost_frame_count, accel })
+}
+
+fn status<T>(result: io::Result<T>) -> Result<T, SenselError> {
+    result.map_err(|e| debug!("Sensel serial: {}", e)).or(Err(SenselError))
+}
+
+struct Serial<P> {
+    protocol: RefCell<Protocol<P>>,
+    units: Units,
+    led_format: led::Format,
+    scan_mode: Cell<SenselScanMode>,
+    frames: RefCell<VecDeque<Vec<u8>>>,
+    // buffers of frames already decoded, read into again
+    spare: RefCell<Vec<Vec<u8>>>,
+    // the last frame's contacts and acceleration, which frames point into
+    // as they do into libsensel's, never reallocated
+    contacts: RefCell<Vec<SenselContact>>,
+    accel: RefCell<SenselAccelData>,
+}
+
+/// Open the Morph on the serial port at `path`, e.g. /dev/ttyACM0.
+pub fn open(path: &str) -> Result<BaseDevice, String> {
+    let port = serialport::new(path, BAUD_RATE)
+        .timeout(TIMEOUT)
+        .open()
+        .map_err(|e| e.to_string())?;
+    open_port(port).map_err(|e| e.to_string())
+}
+
+fn open_port<P: Read + Write + Send + 'static>(port: P) -> io::Result<BaseDevice> {
+    let mut protocol = Protocol::new(port);
+    if protocol.read(register::MAGIC)? != MAGIC {
+        return Err(invalid("not a Sensel device"));
+    }
+    let fw_info = SenselFirmwareInfo {
+        fw_protocol_version: protocol.read(register::FW_PROTOCOL_VERSION)?,
+        fw_version_major: protocol.read(register::FW_VERSION_MAJOR)?,
+        fw_version_minor: protocol.read(register::FW_VERSION_MINOR)?,
+        fw_version_build: protocol.read(register::FW_VERSION_BUILD)?,
+        fw_version_release: protocol.read(register::FW_VERSION_RELEASE)?,
+        device_id: protocol.read(register::DEVICE_ID)?,
+        device_revision: protocol.read(register::DEVICE_REVISION)?,
+    };
+    // the active area is in micrometres, libsensel gives millimetres
+    let sensor_info = SenselSensorInfo {
+        max_contacts: protocol.read(register::CONTACTS_MAX_COUNT)?,
+        num_rows: protocol.read(register::SENSOR_NUM_ROWS)?,
+        num_cols: protocol.read(register::SENSOR_NUM_COLS)?,
+        width: protocol.read(register::SENSOR_ACTIVE_AREA_WIDTH_UM)? as f32 / 1000.0,
+        height: protocol.read(register::SENSOR_ACTIVE_AREA_HEIGHT_UM)? as f32 / 1000.0,
+    };
+    let decoded = (FRAME_CONTENT_CONTACTS_MASK | FRAME_CONTENT_ACCEL_MASK) as u8;
+    let supported_frame_content = protocol.read(register::FRAME_CONTENT_SUPPORTED)? & decoded;
+    let num_leds = protocol.read(register::LED_COUNT)?;
+    let led_format = led::Format {
+        size: usize::from(protocol.read(register::LED_BRIGHTNESS_SIZE)?),
+        max_brightness: protocol.read(register::LED_BRIGHTNESS_MAX)?,
+    };
+    let units = Units::read(&mut protocol)?;
+
+    let serial = Serial {
+        protocol: RefCell::new(protocol),
+        units,
+        led_format,
+        scan_mode: Cell::new(SenselScanMode::SCAN_MODE_SYNC),
+        frames: RefCell::new(VecDeque::new()),
+        spare: RefCell::new(Vec::new()),
+        contacts: RefCell::new(Vec::with_capacity(MAX_CONTACTS)),
+        accel: RefCell::new(SenselAccelData { x: 0, y: 0, z: 0 }),
+    };
+    let info = DeviceInfo::new(sensor_info, fw_info, supported_frame_content, num_leds, led_format, false);
+    Ok(BaseDevice::new(Box::new(serial), info))
+}
+
+impl<P: Read + Write> Serial<P> {
+    fn read<T: Value, A: Readable>(&self, reg: Reg<T, A>) -> Result<T, SenselError> {
+        status(self.protocol.borrow_mut().read(reg))
+    }
+
+    fn write<T: Value, A: Writable>(&self, reg: Reg<T, A>, value: T) -> Result<(), SenselError> {
+        status(self.protocol.borrow_mut().write(reg, value))
+    }
+}
+
+impl<P: Read + Write + Send> Backend for Serial<P> {
+    fn start_scanning(&self) -> Result<(), SenselError> {
+        self.write(register::SCAN_ENABLED, 1)
+    }
+    fn stop_scanning(&self) -> Result<(), SenselError> {
+        self.spare.borrow_mut().extend(self.frames.borrow_mut().drain(..));
+        self.write(register::SCAN_ENABLED, 0)
+    }
+    // one frame per read, asked for as libsensel does in sync mode
+    fn read_sensor(&self) -> Result<(), SenselError> {
+        let mut data = self.spare.borrow_mut().pop().unwrap_or_default();
+        let read = self.protocol.borrow_mut().read_reg_vs(register::SCAN_READ_FRAME.addr, &mut data);
+        if read.is_ok() && !data.is_empty() {
+            self.frames.borrow_mut().push_back(data);
+        } else {
+            self.spare.borrow_mut().push(data);
+        }
+        status(read)
+    }
+    fn get_num_available_frames(&self) -> Result<usize, SenselError> {
+        Ok(self.frames.borrow().len())
+    }
+    fn get_frame(&self) -> Result<SenselFrameData, SenselError> {
+        let data = self.frames.borrow_mut().pop_front().ok_or(SenselError)?;
+        let mut contacts = self.contacts.borrow_mut();
+        let decoded = decode_frame(&data, self.units, &mut contacts);
+        self.spare.borrow_mut().push(data);
+        let decoded = status(decoded)?;
+        *self.accel.borrow_mut() = decoded.accel;
+        Ok(SenselFrameData {
+            content_bit_mask: decoded.content,
+            lost_frame_count: decoded.lost_frame_count,
+            n_contacts: contacts.len() as u8,
+            contacts: contacts.as_mut_ptr(),
+            force_array: ptr::null_mut(),
+            labels_array: ptr::null_mut(),
+            accel_data: self.accel.as_ptr(),
+        })
+    }
+
+    fn get_power_button_pressed(&self) -> Result<bool, SenselError> {
+        self.read(register::POWER_BUTTON_PRESSED).map(|v| v != 0)
+    }
+    fn soft_reset(&self) -> Result<(), SenselError> {
+        self.write(register::SOFT_RESET, 1)
+    }
+
+    // single LEDs have no registers of their own, only the whole array does
+    fn set_led_brightness(&self, led_id: u8, brightness: u16) -> Result<(), SenselError> {
+        let mut array = self.read(register::LED_BRIGHTNESS)?;
+        let mut bytes = Vec::with_capacity(self.led_format.size);
+        self.led_format.encode(brightness, &mut bytes);
+        array.get_mut(self.led_format.entry(led_id)).ok_or(SenselError)?.copy_from_slice(&bytes);
+        self.write(register::LED_BRIGHTNESS, array)
+    }
+    fn get_led_brightness(&self, led_id: u8) -> Result<u16, SenselError> {
+        let array = self.read(register::LED_BRIGHTNESS)?;
+        array.get(self.led_format.entry(led_id)).map(|bytes| self.led_format.decode(bytes)).ok_or(SenselError)
+    }
+
+    // frames are only read when asked for, DeviceInfo says async isn't
+    // supported
+    fn set_scan_mode(&self, mode: SenselScanMode) -> Result<(), SenselError> {
+        match mode {
+            SenselScanMode::SCAN_MODE_SYNC => {
+                self.scan_mode.set(mode);
+                Ok(())
+            }
+            _ => Err(SenselError),
+        }
+    }
+    fn get_scan_mode(&self) -> Result<SenselScanMode, SenselError> {
+        Ok(self.scan_mode.get())
+    }
+
+    fn set_scan_detail(&self, detail: SenselScanDetail) -> Result<(), SenselError> {
+        self.write(register::SCAN_DETAIL_CONTROL, detail as u8)
+    }
+    fn get_scan_detail(&self) -> Result<SenselScanDetail, SenselError> {
+        self.read(register::SCAN_DETAIL_CONTROL).map(|detail| match detail {
+            0 => SenselScanDetail::SCAN_DETAIL_HIGH,
+            1 => SenselScanDetail::SCAN_DETAIL_MEDIUM,
+            2 => SenselScanDetail::SCAN_DETAIL_LOW,
+            _ => SenselScanDetail::SCAN_DETAIL_UNKNOWN,
+        })
+    }
+
+    fn set_buffer_control(&self, num: u8) -> Result<(), SenselError> {
+        self.write(register::SCAN_BUFFER_CONTROL, num)
+    }
+    fn get_buffer_control(&self) -> Result<u8, SenselError> {
+        self.read(register::SCAN_BUFFER_CONTROL)
+    }
+
+    fn set_max_frame_rate(&self, val: u16) -> Result<(), SenselError> {
+        self.write(register::SCAN_FRAME_RATE, val)
+    }
+    fn get_max_frame_rate(&self) -> Result<u16, SenselError> {
+        self.read(register::SCAN_FRAME_RATE)
+    }
+
+    fn set_frame_content(&self, mask: u8) -> Result<(), SenselError> {
+        self.write(register::FRAME_CONTENT_CONTROL, mask)
+    }
+    fn get_frame_content(&self) -> Result<u8, SenselError> {
+        self.read(register::FRAME_CONTENT_CONTROL)
+    }
+
+    fn set_contacts_mask(&self, mask: u8) -> Result<(), SenselError> {
+        self.write(register::CONTACTS_MASK, mask)
+    }
+    fn get_contacts_mask(&self) -> Result<u8, SenselError> {
+        self.read(register::CONTACTS_MASK)
+    }
+
+    fn set_contacts_min_force(&self, val: u16) -> Result<(), SenselError> {
+        self.write(register::CONTACTS_MIN_FORCE, val)
+    }
+    fn get_contacts_min_force(&self) -> Result<u16, SenselError> {
+        self.read(register::CONTACTS_MIN_FORCE)
+    }
+
+    fn set_contacts_enable_blob_merge(&self, val: bool) -> Result<(), SenselError> {
+        self.write(register::CONTACTS_ENABLE_BLOB_MERGE, val as u8)
+    }
+    fn get_contacts_enable_blob_merge(&self) -> Result<bool, SenselError> {
+        self.read(register::CONTACTS_ENABLE_BLOB_MERGE).map(|v| v != 0)
+    }
+
+    fn set_dynamic_baseline_enabled(&self, val: bool) -> Result<(), SenselError> {
+        self.write(register::BASELINE_DYNAMIC_ENABLED, val as u8)
+    }
+    fn get_dynamic_baseline_enabled(&self) -> Result<bool, SenselError> {
+        self.read(register::BASELINE_DYNAMIC_ENABLED).map(|v| v != 0)
+    }
+
+    fn read_reg(&self, addr: u8, size: usize) -> Result<Vec<u8>, SenselError> {
+        status(self.protocol.borrow_mut().read_reg(addr, size))
+    }
+    fn write_reg(&self, addr: u8, data: &[u8]) -> Result<(), SenselError> {
+        status(self.protocol.borrow_mut().write_reg(addr, data))
+    }
+    fn read_reg_vs(&self, addr: u8) -> Result<Vec<u8>, SenselError> {
+        let mut data = vec![];
+        status(self.protocol.borrow_mut().read_reg_vs(addr, &mut data)).and(Ok(data))
+    }
+    fn write_reg_vs(&self, addr: u8, data: &[u8]) -> Result<(), SenselError> {
+        status(self.protocol.borrow_mut().write_reg_vs(addr, data))
+    }
+}
+
+/// A Morph at the other end of a port, for testing what talks to one.
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Answers every request. Registers hold what was written to them, zero
+    /// until then apart from the magic number and the frame content
+    /// supported, contacts alone, or empty if of variable size, and frame
+    /// reads return `frames` in turn, over and over.
+    pub(crate) struct FakePort {
+        regs: Arc<Mutex<[u8; 256]>>,
+        vs_regs: HashMap<usize, Vec<u8>>,
+        frames: VecDeque<Vec<u8>>,
+        request: Vec<u8>,
+        reply: VecDeque<u8>,
+    }
+
+    /// A device on a fake port, and its registers.
+    pub(crate) fn open(frames: &[&[u8]]) -> (BaseDevice, Arc<Mutex<[u8; 256]>>) {
+        let mut regs = [0; 256];
+        let magic = usize::from(register::MAGIC.addr);
+        regs[magic..magic + MAGIC.len()].copy_from_slice(&MAGIC);
+        regs[usize::from(register::FRAME_CONTENT_SUPPORTED.addr)] = FRAME_CONTENT_CONTACTS_MASK as u8;
+        let regs = Arc::new(Mutex::new(regs));
+        let frames = frames.iter().map(|frame| frame.to_vec()).collect();
+        let port = FakePort { regs: regs.clone(), vs_regs: HashMap::new(), frames, request: vec![], reply: VecDeque::new() };
+        (open_port(port).unwrap(), regs)
+    }
+
+    impl FakePort {
+        fn respond(&mut self, ack: u8, data: &[u8]) {
+            self.reply.push_back(ack);
+            self.reply.extend((data.len() as u16).to_le_bytes());
+            self.reply.extend(data);
+            self.reply.push_back(checksum(data));
+        }
+
+        // the length of the request at the front, once all of it has arrived
+        fn complete(&self) -> Option<usize> {
+            let len = match *self.request.first()? {
+                READ_HEADER | READ_VS_HEADER => 3,
+                WRITE_HEADER => 3 + usize::from(*self.request.get(2)?) + 1,
+                _ => 5 + usize::from(u16::from_le_bytes([*self.request.get(3)?, *self.request.get(4)?])) + 1,
+            };
+            Some(len).filter(|&len| self.request.len() >= len)
+        }
+    }
+
+    impl Read for FakePort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.reply.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let n = buf.len().min(self.reply.len());
+            for (b, r) in buf.iter_mut().zip(self.reply.drain(..n)) {
+                *b = r;
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for FakePort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.request.extend_from_slice(buf);
+            while let Some(len) = self.complete() {
+                let request: Vec<u8> = self.request.drain(..len).collect();
+                let addr = usize::from(request[1]);
+                match request[0] {
+                    READ_HEADER => {
+                        let data = self.regs.lock().unwrap()[addr..addr + usize::from(request[2])].to_vec();
+                        self.respond(READ_ACK, &data);
+                    }
+                    READ_VS_HEADER if addr == register::SCAN_READ_FRAME.addr as usize => {
+                        if !self.frames.is_empty() {
+                            self.frames.rotate_left(1);
+                        }
+                        let frame = self.frames.back().cloned().unwrap_or_default();
+                        self.respond(READ_VS_ACK, &frame);
+                    }
+                    READ_VS_HEADER => {
+                        let data = self.vs_regs.get(&addr).cloned().unwrap_or_default();
+                        self.respond(READ_VS_ACK, &data);
+                    }
+                    WRITE_HEADER => {
+                        let data = &request[3..len - 1];
+                        self.regs.lock().unwrap()[addr..addr + data.len()].copy_from_slice(data);
+                        self.reply.push_back(WRITE_ACK);
+                    }
+                    _ => {
+                        self.vs_regs.insert(addr, request[5..len - 1].to_vec());
+                        self.reply.push_back(WRITE_VS_ACK);
+                    }
+                }
+            }
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensel::device::Device;
+
+    // replies with `input`, keeping what was sent
+    struct MockPort {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockPort {
+        fn new(input: Vec<u8>) -> Self {
+            MockPort { input: io::Cursor::new(input), output: Vec::new() }
+        }
+    }
+
+    impl Read for MockPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn reply(ack: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![ack, data.len() as u8, 0];
+        bytes.extend_from_slice(data);
+        bytes.push(checksum(data));
+        bytes
+    }
+
+    #[test]
+    fn reads_and_writes_registers() {
+        let mut input = reply(READ_ACK, &500u16.to_le_bytes());
+        input.push(WRITE_ACK);
+        let mut protocol = Protocol::new(MockPort::new(input));
+        assert_eq!(protocol.read(register::SCAN_FRAME_RATE).unwrap(), 500);
+        protocol.write(register::CONTACTS_MASK, 0x0f).unwrap();
+        assert_eq!(protocol.port.output, vec![
+            READ_HEADER, SENSEL_REG_SCAN_FRAME_RATE as u8, 2,
+            WRITE_HEADER, SENSEL_REG_CONTACTS_MASK as u8, 1, 0x0f, 0x0f,
+        ]);
+    }
+
+    #[test]
+    fn rejects_bad_replies() {
+        let mut corrupt = reply(READ_ACK, &[1, 2]);
+        *corrupt.last_mut().unwrap() ^= 0xff;
+        let mut protocol = Protocol::new(MockPort::new(corrupt));
+        assert!(protocol.read(register::SCAN_FRAME_RATE).is_err());
+
+        let mut protocol = Protocol::new(MockPort::new(vec![WRITE_ACK + 1]));
+        assert!(protocol.write(register::SCAN_ENABLED, 1).is_err());
+
+        let mut protocol = Protocol::new(MockPort::new(vec![READ_ACK, 2]));
+        assert_eq!(protocol.read(register::SCAN_FRAME_RATE).unwrap_err().kind(),
+                   io::ErrorKind::UnexpectedEof);
+    }
+
+    fn serial(input: Vec<u8>, led_format: led::Format) -> Serial<MockPort> {
+        Serial {
+            protocol: RefCell::new(Protocol::new(MockPort::new(input))),
+            units: Units { dims: 1.0, force: 1.0, area: 1.0, angle: 1.0 },
+            led_format,
+            scan_mode: Cell::new(SenselScanMode::SCAN_MODE_SYNC),
+            frames: RefCell::new(VecDeque::new()),
+            spare: RefCell::new(Vec::new()),
+            contacts: RefCell::new(Vec::new()),
+            accel: RefCell::new(SenselAccelData { x: 0, y: 0, z: 0 }),
+        }
+    }
+
+    #[test]
+    fn single_leds_only_touch_the_led_array() {
+        let format = led::Format { size: 2, max_brightness: 1000 };
+        let array = [1, 0, 2, 0, 3, 0, 4, 0, 5, 0];
+        let mut input = reply(READ_VS_ACK, &array);
+        input.push(WRITE_VS_ACK);
+        input.extend(reply(READ_VS_ACK, &array));
+        input.extend(reply(READ_VS_ACK, &array));
+        let serial = serial(input, format);
+        serial.set_led_brightness(4, 0x0203).unwrap();
+        assert_eq!(serial.get_led_brightness(1).unwrap(), 2);
+        assert!(serial.get_led_brightness(5).is_err());
+
+        let led = register::LED_BRIGHTNESS.addr;
+        let written = [1, 0, 2, 0, 3, 0, 4, 0, 3, 2];
+        let mut expected = vec![READ_VS_HEADER, led, 0, WRITE_VS_HEADER, led, 0, 10, 0];
+        expected.extend_from_slice(&written);
+        expected.extend_from_slice(&[checksum(&written), READ_VS_HEADER, led, 0, READ_VS_HEADER, led, 0]);
+        assert_eq!(serial.protocol.borrow().port.output, expected);
+    }
+
+    #[test]
+    fn reads_frames_from_the_port() {
+        let content = (FRAME_CONTENT_CONTACTS_MASK | FRAME_CONTENT_ACCEL_MASK) as u8;
+        let mut frame = vec![content, 2, 0, 1, 3, 1];
+        for v in [120u16, 60, 250, 9, 1, 2, 3] {
+            frame.extend_from_slice(&v.to_le_bytes());
+        }
+        let (device, _) = fake::open(&[&frame]);
+        let device = device.start_scanning().unwrap();
+
+        device.read_sensor().unwrap();
+        assert_eq!(device.get_num_available_frames().unwrap(), 1);
+        let frame = device.get_frame().unwrap();
+        assert_eq!(frame.lost_frame_count, 2);
+        let contacts = frame.contacts.unwrap();
+        assert_eq!(contacts.len(), 1);
+        let c = contacts[0];
+        assert_eq!((c.id, c.state), (3, 1));
+        assert_eq!((c.x_pos, c.y_pos, c.total_force, c.area), (120.0, 60.0, 250.0, 9.0));
+        let accel = frame.accel_data.unwrap();
+        assert_eq!((accel.x, accel.y, accel.z), (1, 2, 3));
+
+        assert_eq!(device.get_num_available_frames().unwrap(), 0);
+        assert!(device.get_frame().is_err());
+    }
+
+    #[test]
+    fn async_scanning_is_reported_unsupported() {
+        let (device, _) = fake::open(&[]);
+        assert!(!device.get_info().async_scanning);
+        assert!(device.set_scan_mode(SenselScanMode::SCAN_MODE_ASYNC).is_err());
+        assert_eq!(device.get_scan_mode().ok(), Some(SenselScanMode::SCAN_MODE_SYNC));
+    }
+
+    #[test]
+    fn decodes_contacts_in_device_units() {
+        let units = Units { dims: 256.0, force: 8.0, area: 1.0, angle: 16.0 };
+        let content = (FRAME_CONTENT_CONTACTS_MASK | FRAME_CONTENT_ACCEL_MASK) as u8;
+        let mut frame = vec![content, 3, CONTACT_MASK_ELLIPSE as u8, 1, 7, 1];
+        for v in [2560u16, 5120, 800, 40] {
+            frame.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [-720i16, 1024, 512, 1, -1, 4096] {
+            frame.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let mut contacts = Vec::new();
+        let decoded = decode_frame(&frame, units, &mut contacts).unwrap();
+        assert_eq!(decoded.lost_frame_count, 3);
+        assert_eq!((decoded.accel.x, decoded.accel.y, decoded.accel.z), (1, -1, 4096));
+        assert_eq!(contacts.len(), 1);
+        let c = contacts[0];
+        assert_eq!((c.id, c.state), (7, 1));
+        assert_eq!((c.x_pos, c.y_pos, c.total_force, c.area), (10.0, 20.0, 100.0, 40.0));
+        assert_eq!((c.orientation, c.major_axis, c.minor_axis), (-45.0, 4.0, 2.0));
+
+        assert!(decode_frame(&frame[..frame.len() - 1], units, &mut contacts).is_err());
+    }
+}
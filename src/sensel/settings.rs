@@ -0,0 +1,337 @@
+use std::fmt;
+
+use super::bindings::*;
+use super::result::*;
+use super::device::{BaseDevice, Device, ScanningDevice};
+use super::frame;
+use super::contact;
+
+/// A single device setting with its value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SettingValue {
+    ScanMode(SenselScanMode),
+    ScanDetail(SenselScanDetail),
+    FrameContent(frame::Mask),
+    BufferControl(u8),
+    MaxFrameRate(u16),
+    ContactsMask(contact::Mask),
+    ContactsMinForce(u16),
+    ContactsEnableBlobMerge(bool),
+    DynamicBaselineEnabled(bool),
+}
+
+impl SettingValue {
+    pub fn name(self) -> &'static str {
+        match self {
+            SettingValue::ScanMode(_) => "scan_mode",
+            SettingValue::ScanDetail(_) => "scan_detail",
+            SettingValue::FrameContent(_) => "frame_content",
+            SettingValue::BufferControl(_) => "buffer_control",
+            SettingValue::MaxFrameRate(_) => "max_frame_rate",
+            SettingValue::ContactsMask(_) => "contacts_mask",
+            SettingValue::ContactsMinForce(_) => "contacts_min_force",
+            SettingValue::ContactsEnableBlobMerge(_) => "contacts_enable_blob_merge",
+            SettingValue::DynamicBaselineEnabled(_) => "dynamic_baseline_enabled",
+        }
+    }
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SettingValue::ScanMode(mode) => write!(f, "{:?}", mode),
+            SettingValue::ScanDetail(detail) => write!(f, "{:?}", detail),
+            SettingValue::FrameContent(mask) => write!(f, "{:?}", mask),
+            SettingValue::BufferControl(v) => write!(f, "{}", v),
+            SettingValue::MaxFrameRate(v) => write!(f, "{}", v),
+            SettingValue::ContactsMask(mask) => write!(f, "{:?}", mask),
+            SettingValue::ContactsMinForce(v) => write!(f, "{}", v),
+            SettingValue::ContactsEnableBlobMerge(v) => write!(f, "{}", v),
+            SettingValue::DynamicBaselineEnabled(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Reading and writing single settings, all that applying settings needs
+/// from a device.
+pub trait Registers {
+    fn write_setting(&self, value: SettingValue) -> Result<(), SenselError>;
+    /// Read the setting `like` is a value of.
+    fn read_setting(&self, like: SettingValue) -> Result<SettingValue, SenselError>;
+}
+
+impl<D: Device> Registers for D {
+    fn write_setting(&self, value: SettingValue) -> Result<(), SenselError> {
+        match value {
+            SettingValue::ScanMode(mode) => self.set_scan_mode(mode),
+            SettingValue::ScanDetail(detail) => self.set_scan_detail(detail),
+            SettingValue::FrameContent(mask) => self.set_frame_content(mask),
+            SettingValue::BufferControl(v) => self.set_buffer_control(v),
+            SettingValue::MaxFrameRate(v) => self.set_max_frame_rate(v),
+            SettingValue::ContactsMask(mask) => self.set_contacts_mask(mask),
+            SettingValue::ContactsMinForce(v) => self.set_contacts_min_force(v),
+            SettingValue::ContactsEnableBlobMerge(v) => self.set_contacts_enable_blob_merge(v),
+            SettingValue::DynamicBaselineEnabled(v) => self.set_dynamic_baseline_enabled(v),
+        }
+    }
+
+    fn read_setting(&self, like: SettingValue) -> Result<SettingValue, SenselError> {
+        match like {
+            SettingValue::ScanMode(_) => self.get_scan_mode().map(SettingValue::ScanMode),
+            SettingValue::ScanDetail(_) => self.get_scan_detail().map(SettingValue::ScanDetail),
+            SettingValue::FrameContent(_) => self.get_frame_content().map(SettingValue::FrameContent),
+            SettingValue::BufferControl(_) => self.get_buffer_control().map(SettingValue::BufferControl),
+            SettingValue::MaxFrameRate(_) => self.get_max_frame_rate().map(SettingValue::MaxFrameRate),
+            SettingValue::ContactsMask(_) => self.get_contacts_mask().map(SettingValue::ContactsMask),
+            SettingValue::ContactsMinForce(_) => self.get_contacts_min_force().map(SettingValue::ContactsMinForce),
+            SettingValue::ContactsEnableBlobMerge(_) => self.get_contacts_enable_blob_merge().map(SettingValue::ContactsEnableBlobMerge),
+            SettingValue::DynamicBaselineEnabled(_) => self.get_dynamic_baseline_enabled().map(SettingValue::DynamicBaselineEnabled),
+        }
+    }
+}
+
+/// A setting the device didn't take, either failing to write or reading
+/// back something else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rejected {
+    pub wanted: SettingValue,
+    pub read_back: Option<SettingValue>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SettingsError {
+    // the current settings couldn't be read, nothing was changed
+    Read(&'static str),
+    // the settings changed before the rejections were restored, unless
+    // restoring failed too
+    Rejected { rejected: Vec<Rejected>, rolled_back: bool },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Read(name) => write!(f, "failed to read {}", name),
+            SettingsError::Rejected { rejected, rolled_back } => {
+                write!(f, "device rejected ")?;
+                for (i, r) in rejected.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match r.read_back {
+                        Some(got) => write!(f, "{} {} (read back {})", r.wanted.name(), r.wanted, got)?,
+                        None => write!(f, "{} {}", r.wanted.name(), r.wanted)?,
+                    }
+                }
+                if *rolled_back {
+                    write!(f, ", previous settings restored")
+                } else {
+                    write!(f, ", failed to restore previous settings")
+                }
+            }
+        }
+    }
+}
+
+/// All of a device's settings, to read and apply as a whole.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeviceSettings {
+    pub scan_mode: SenselScanMode,
+    pub scan_detail: SenselScanDetail,
+    pub frame_content: frame::Mask,
+    pub buffer_control: u8,
+    pub max_frame_rate: u16,
+    pub contacts_mask: contact::Mask,
+    pub contacts_min_force: u16,
+    pub contacts_enable_blob_merge: bool,
+    pub dynamic_baseline_enabled: bool,
+}
+
+impl DeviceSettings {
+    /// In the order they're applied, what's scanned before how contacts are
+    /// reported.
+    pub fn values(&self) -> [SettingValue; 9] {
+        [
+            SettingValue::ScanMode(self.scan_mode),
+            SettingValue::ScanDetail(self.scan_detail),
+            SettingValue::FrameContent(self.frame_content),
+            SettingValue::BufferControl(self.buffer_control),
+            SettingValue::MaxFrameRate(self.max_frame_rate),
+            SettingValue::ContactsMask(self.contacts_mask),
+            SettingValue::ContactsMinForce(self.contacts_min_force),
+            SettingValue::ContactsEnableBlobMerge(self.contacts_enable_blob_merge),
+            SettingValue::DynamicBaselineEnabled(self.dynamic_baseline_enabled),
+        ]
+    }
+
+    pub fn set(&mut self, value: SettingValue) {
+        match value {
+            SettingValue::ScanMode(mode) => self.scan_mode = mode,
+            SettingValue::ScanDetail(detail) => self.scan_detail = detail,
+            SettingValue::FrameContent(mask) => self.frame_content = mask,
+            SettingValue::BufferControl(v) => self.buffer_control = v,
+            SettingValue::MaxFrameRate(v) => self.max_frame_rate = v,
+            SettingValue::ContactsMask(mask) => self.contacts_mask = mask,
+            SettingValue::ContactsMinForce(v) => self.contacts_min_force = v,
+            SettingValue::ContactsEnableBlobMerge(v) => self.contacts_enable_blob_merge = v,
+            SettingValue::DynamicBaselineEnabled(v) => self.dynamic_baseline_enabled = v,
+        }
+    }
+
+    pub fn read<R: Registers>(device: &R) -> Result<DeviceSettings, SettingsError> {
+        let mut settings = DeviceSettings {
+            scan_mode: SenselScanMode::SCAN_MODE_DISABLE,
+            scan_detail: SenselScanDetail::SCAN_DETAIL_UNKNOWN,
+            frame_content: frame::Mask::empty(),
+            buffer_control: 0,
+            max_frame_rate: 0,
+            contacts_mask: contact::Mask::empty(),
+            contacts_min_force: 0,
+            contacts_enable_blob_merge: false,
+            dynamic_baseline_enabled: false,
+        };
+        for &like in settings.values().iter() {
+            let value = device.read_setting(like).map_err(|_| SettingsError::Read(like.name()))?;
+            settings.set(value);
+        }
+        Ok(settings)
+    }
+
+    /// Apply to a device that isn't scanning, as scan mode, detail and frame
+    /// content only take effect when scanning starts. Only settings that
+    /// differ are written, each is read back to check the device took it.
+    /// If any are rejected the ones changed are put back as they were.
+    pub fn apply(&self, device: &BaseDevice) -> Result<(), SettingsError> {
+        self.apply_to(device)
+    }
+
+    /// Apply to a scanning device, stopping it around the writes as `apply`
+    /// needs and starting it again whether they were taken or not. The device
+    /// is lost if it can't be stopped or started.
+    pub fn apply_scanning(&self, device: ScanningDevice) -> Result<(ScanningDevice, Result<(), SettingsError>), SenselError> {
+        let device = device.stop_scanning()?;
+        let applied = self.apply(&device);
+        Ok((device.start_scanning()?, applied))
+    }
+
+    fn apply_to<R: Registers>(&self, device: &R) -> Result<(), SettingsError> {
+        let previous = DeviceSettings::read(device)?;
+        let mut changed = vec![];
+        let mut rejected = vec![];
+        for (&wanted, &was) in self.values().iter().zip(previous.values().iter()) {
+            if wanted == was {
+                continue;
+            }
+            changed.push(was);
+            let read_back = device.write_setting(wanted).and_then(|_| device.read_setting(wanted));
+            match read_back {
+                Ok(got) if got == wanted => (),
+                Ok(got) => rejected.push(Rejected { wanted, read_back: Some(got) }),
+                Err(_) => rejected.push(Rejected { wanted, read_back: None }),
+            }
+        }
+        if rejected.is_empty() {
+            return Ok(());
+        }
+
+        let mut rolled_back = true;
+        for &was in changed.iter().rev() {
+            if device.write_setting(was).is_err() {
+                rolled_back = false;
+            }
+        }
+        Err(SettingsError::Rejected { rejected, rolled_back })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use crate::sensel::register;
+    use crate::sensel::serial::fake;
+
+    // a device that refuses frame rates over 1000 and rounds min force down
+    // to a multiple of 8
+    struct Fake {
+        settings: RefCell<DeviceSettings>,
+        writes: RefCell<Vec<&'static str>>,
+    }
+
+    impl Registers for Fake {
+        fn write_setting(&self, value: SettingValue) -> Result<(), SenselError> {
+            self.writes.borrow_mut().push(value.name());
+            match value {
+                SettingValue::MaxFrameRate(v) if v > 1000 => return Err(SenselError),
+                SettingValue::ContactsMinForce(v) => self.settings.borrow_mut().contacts_min_force = v & !7,
+                _ => self.settings.borrow_mut().set(value),
+            }
+            Ok(())
+        }
+
+        fn read_setting(&self, like: SettingValue) -> Result<SettingValue, SenselError> {
+            let settings = self.settings.borrow();
+            Ok(*settings.values().iter().find(|v| v.name() == like.name()).unwrap())
+        }
+    }
+
+    fn fake() -> Fake {
+        Fake {
+            settings: RefCell::new(DeviceSettings {
+                scan_mode: SenselScanMode::SCAN_MODE_SYNC,
+                scan_detail: SenselScanDetail::SCAN_DETAIL_MEDIUM,
+                frame_content: frame::Mask::CONTACTS,
+                buffer_control: 0,
+                max_frame_rate: 500,
+                contacts_mask: contact::Mask::empty(),
+                contacts_min_force: 24,
+                contacts_enable_blob_merge: true,
+                dynamic_baseline_enabled: true,
+            }),
+            writes: RefCell::new(vec![]),
+        }
+    }
+
+    #[test]
+    fn applies_only_changes() {
+        let device = fake();
+        let mut settings = DeviceSettings::read(&device).unwrap();
+        settings.scan_detail = SenselScanDetail::SCAN_DETAIL_LOW;
+        settings.contacts_mask = contact::Mask::all();
+        settings.apply_to(&device).unwrap();
+        assert_eq!(*device.settings.borrow(), settings);
+        assert_eq!(*device.writes.borrow(), vec!["scan_detail", "contacts_mask"]);
+    }
+
+    #[test]
+    fn rolls_back_rejected() {
+        let device = fake();
+        let before = DeviceSettings::read(&device).unwrap();
+        let mut settings = before;
+        settings.scan_detail = SenselScanDetail::SCAN_DETAIL_HIGH;
+        settings.max_frame_rate = 2000;
+        settings.contacts_min_force = 30;
+
+        let err = settings.apply_to(&device).unwrap_err();
+        assert_eq!(err, SettingsError::Rejected {
+            rejected: vec![
+                Rejected { wanted: SettingValue::MaxFrameRate(2000), read_back: None },
+                Rejected { wanted: SettingValue::ContactsMinForce(30), read_back: Some(SettingValue::ContactsMinForce(24)) },
+            ],
+            rolled_back: true,
+        });
+        assert_eq!(*device.settings.borrow(), before);
+        assert_eq!(err.to_string(), "device rejected max_frame_rate 2000, contacts_min_force 30 (read back 24), previous settings restored");
+    }
+
+    #[test]
+    fn scanning_devices_are_changed_between_scans() {
+        let (device, regs) = fake::open(&[]);
+        let mut settings = DeviceSettings::read(&device).unwrap();
+        let device = device.start_scanning().unwrap();
+        settings.max_frame_rate = 500;
+        let (device, applied) = settings.apply_scanning(device).unwrap();
+        assert_eq!(applied, Ok(()));
+        assert_eq!(device.get_max_frame_rate().ok(), Some(500));
+        assert_eq!(regs.lock().unwrap()[usize::from(register::SCAN_ENABLED.addr)], 1);
+    }
+}
@@ -0,0 +1,288 @@
+// Frames as an async Stream, read on a thread of their own, for services
+// built on tokio or any other runtime.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+
+use futures_core::Stream;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::oneshot;
+
+use super::bindings::*;
+use super::contact::Contact;
+use super::device::{BaseDevice, Device, ScanningDevice};
+use super::frame::Frame;
+use super::reader;
+use super::remote::Remote;
+use super::result::*;
+use super::settings::{Registers, SettingValue};
+
+// frames held for a consumer that falls behind before newer ones are dropped
+const BUFFERED_FRAMES: usize = 64;
+
+type FrameResult = Result<OwnedFrame, SenselError>;
+
+/// A frame copied out of the device's buffers, to keep as long as needed.
+#[derive(Clone, Debug)]
+pub struct OwnedFrame {
+    pub lost_frame_count: i32,
+    pub contacts: Option<Vec<Contact>>,
+    #[cfg(feature = "forces")]
+    pub force_array: Option<Vec<f32>>,
+    #[cfg(feature = "forces")]
+    pub labels_array: Option<Vec<u8>>,
+    pub accel_data: Option<SenselAccelData>,
+}
+
+impl From<Frame<'_>> for OwnedFrame {
+    fn from(frame: Frame<'_>) -> Self {
+        OwnedFrame {
+            lost_frame_count: frame.lost_frame_count,
+            contacts: frame.contacts.map(|c| c.iter().map(|&c| Contact::from(c)).collect()),
+            #[cfg(feature = "forces")]
+            force_array: frame.force_array.map(<[f32]>::to_vec),
+            #[cfg(feature = "forces")]
+            labels_array: frame.labels_array.map(<[u8]>::to_vec),
+            accel_data: frame.accel_data,
+        }
+    }
+}
+
+/// The frames of a scanning device as they arrive. Frames the consumer is too
+/// slow for are dropped and counted in the next one's `lost_frame_count`.
+/// Failed reads are errors in the stream, which ends once the device has
+/// failed `reader::MAX_READ_FAILURES` in a row.
+///
+/// Dropping the stream stops scanning and closes the device, `stop` stops
+/// scanning and gives the device back.
+pub struct FrameStream {
+    frames: mpsc::Receiver<FrameResult>,
+    remote: Remote,
+    running: Arc<AtomicBool>,
+    stopped: Option<oneshot::Receiver<Result<BaseDevice, SenselError>>>,
+}
+
+/// Start scanning `device` on a new thread.
+pub fn scan(device: BaseDevice) -> Result<FrameStream, SenselError> {
+    let remote = device.remote();
+    let device = device.start_scanning()?;
+    let (tx, frames) = mpsc::channel(BUFFERED_FRAMES);
+    let (done, stopped) = oneshot::channel();
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    thread::spawn(move || {
+        read_frames(&device, &tx, &r);
+        let _ = done.send(device.stop_scanning());
+    });
+    Ok(FrameStream { frames, remote, running, stopped: Some(stopped) })
+}
+
+// until stopped, the stream is dropped or the device stops answering
+fn read_frames(device: &ScanningDevice, tx: &mpsc::Sender<FrameResult>, running: &AtomicBool) {
+    let mode = device.get_scan_mode().unwrap_or(SenselScanMode::SCAN_MODE_SYNC);
+    let idle = reader::idle_time(mode, device.get_max_frame_rate().unwrap_or(0));
+    let mut dropped = 0;
+    let mut failures = 0;
+    while running.load(Ordering::SeqCst) {
+        let num_frames = match device.read_sensor() {
+            Ok(()) => {
+                failures = 0;
+                device.get_num_available_frames().unwrap_or(0)
+            }
+            Err(e) => {
+                failures += 1;
+                if let Err(TrySendError::Closed(_)) = tx.try_send(Err(e)) {
+                    return;
+                }
+                if failures == reader::MAX_READ_FAILURES {
+                    return;
+                }
+                thread::sleep(reader::retry_time(failures));
+                continue;
+            }
+        };
+        if num_frames == 0 {
+            if let Some(idle) = idle {
+                thread::sleep(idle);
+            }
+        }
+        for _ in 0..num_frames {
+            let frame = device.get_frame().map(|frame| {
+                let mut frame = OwnedFrame::from(frame);
+                frame.lost_frame_count += dropped;
+                frame
+            });
+            match tx.try_send(frame) {
+                Ok(()) => dropped = 0,
+                Err(TrySendError::Full(_)) => dropped += 1,
+                Err(TrySendError::Closed(_)) => return,
+            }
+        }
+    }
+}
+
+impl FrameStream {
+    pub fn remote(&self) -> AsyncRemote {
+        AsyncRemote(self.remote.clone())
+    }
+
+    pub async fn stop(mut self) -> Result<BaseDevice, SenselError> {
+        self.running.store(false, Ordering::SeqCst);
+        match self.stopped.take() {
+            Some(stopped) => stopped.await.unwrap_or(Err(SenselError)),
+            None => Err(SenselError),
+        }
+    }
+}
+
+impl Stream for FrameStream {
+    type Item = FrameResult;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<FrameResult>> {
+        self.get_mut().frames.poll_recv(cx)
+    }
+}
+
+impl Drop for FrameStream {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// A `Remote` that awaits the scanning thread instead of blocking on it.
+/// Calls fail once the thread has stopped.
+#[derive(Clone)]
+pub struct AsyncRemote(Remote);
+
+impl AsyncRemote {
+    pub async fn call<T, F>(&self, f: F) -> Result<T, SenselError>
+        where T: Send + 'static, F: FnOnce(&ScanningDevice) -> Result<T, SenselError> + Send + 'static
+    {
+        let (tx, rx) = oneshot::channel();
+        self.0.send(Box::new(move |device| {
+            let _ = tx.send(f(device));
+        }))?;
+        rx.await.unwrap_or(Err(SenselError))
+    }
+
+    pub async fn set_led_array(&self, led_array: Vec<u16>) -> Result<(), SenselError> {
+        self.call(move |device| device.set_led_array(&led_array)).await
+    }
+    pub async fn set_led_brightness(&self, led_id: u8, brightness: u16) -> Result<(), SenselError> {
+        self.call(move |device| device.set_led_brightness(led_id, brightness)).await
+    }
+    pub async fn set_led_levels(&self, levels: Vec<f32>) -> Result<Vec<u16>, SenselError> {
+        self.call(move |device| device.set_led_levels(&levels)).await
+    }
+    pub async fn set_led_level(&self, led_id: u8, level: f32) -> Result<u16, SenselError> {
+        self.call(move |device| device.set_led_level(led_id, level)).await
+    }
+    pub async fn write_setting(&self, value: SettingValue) -> Result<(), SenselError> {
+        self.call(move |device| device.write_setting(value)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{poll_fn, Future};
+    use std::mem;
+    use std::pin::pin;
+    use std::task::Wake;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::sensel::register;
+    use crate::sensel::serial::fake;
+
+    // tokio is only here for its channels, so no runtime to run futures on
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(thread::Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Arc::new(Unpark(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    fn next(stream: &mut FrameStream) -> Option<FrameResult> {
+        block_on(poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)))
+    }
+
+    // a frame with nothing in it and nothing lost
+    const EMPTY_FRAME: [u8; 2] = [0, 0];
+
+    #[test]
+    fn frames_are_copied_out_of_the_device_buffers() {
+        let mut contacts: [SenselContact; 2] = unsafe { mem::zeroed() };
+        contacts[1].id = 7;
+        contacts[1].x_pos = 12.5;
+        let frame = OwnedFrame::from(Frame {
+            lost_frame_count: 1,
+            contacts: Some(&contacts),
+            #[cfg(feature = "forces")]
+            force_array: None,
+            #[cfg(feature = "forces")]
+            labels_array: None,
+            accel_data: None,
+        });
+        let owned = frame.contacts.unwrap();
+        assert_eq!(owned.len(), 2);
+        assert_eq!((owned[1].id, owned[1].x), (7, 12.5));
+        assert_eq!(frame.lost_frame_count, 1);
+    }
+
+    #[test]
+    fn stop_gives_the_device_back() {
+        let (device, regs) = fake::open(&[&EMPTY_FRAME]);
+        let mut stream = scan(device).unwrap();
+        assert_eq!(next(&mut stream).unwrap().unwrap().lost_frame_count, 0);
+        let remote = stream.remote();
+        assert_eq!(block_on(remote.call(|_| Ok(5))).ok(), Some(5));
+
+        let device = block_on(stream.stop()).unwrap();
+        assert_eq!(regs.lock().unwrap()[usize::from(register::SCAN_ENABLED.addr)], 0);
+        assert!(block_on(remote.call(|_| Ok(5))).is_err());
+        assert!(device.start_scanning().is_ok());
+    }
+
+    #[test]
+    fn dropping_the_stream_stops_scanning() {
+        let (device, regs) = fake::open(&[&EMPTY_FRAME]);
+        let stream = scan(device).unwrap();
+        let remote = stream.remote();
+        let scanning = || regs.lock().unwrap()[usize::from(register::SCAN_ENABLED.addr)] != 0;
+        assert!(scanning());
+
+        drop(stream);
+        let dropped = Instant::now();
+        while scanning() {
+            assert!(dropped.elapsed() < Duration::from_secs(5), "still scanning");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(block_on(remote.call(|_| Ok(5))).is_err());
+    }
+
+    #[test]
+    fn frames_dropped_for_a_slow_consumer_are_counted() {
+        let (device, _) = fake::open(&[&EMPTY_FRAME]);
+        let mut stream = scan(device).unwrap();
+        // the device keeps on scanning while nothing is taken
+        thread::sleep(Duration::from_millis(200));
+        for _ in 0..BUFFERED_FRAMES {
+            assert_eq!(next(&mut stream).unwrap().unwrap().lost_frame_count, 0);
+        }
+        assert!(next(&mut stream).unwrap().unwrap().lost_frame_count > 0);
+    }
+}
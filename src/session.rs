@@ -0,0 +1,204 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use crate::interface::InterfaceError;
+use crate::sensel::contact::{BoundingBox, Contact, Delta, Ellipse, Peak, State};
+
+// the Morph's active area in mm, when no device is at hand to ask
+pub const MORPH_WIDTH: f32 = 230.0;
+pub const MORPH_HEIGHT: f32 = 130.0;
+
+pub struct SessionFrame {
+    // since the start of the session
+    pub time: f32,
+    pub contacts: Vec<Contact>,
+}
+
+/// Contacts recorded from the Morph, as JSON lines: a header with the
+/// sensor's size in mm, `{"width": w, "height": h}`, then a line per frame
+/// with contacts, `{"time": secs, "contacts": [...]}`.
+pub struct Session {
+    pub width: f32,
+    pub height: f32,
+    pub frames: Vec<SessionFrame>,
+}
+
+fn state_name(state: State) -> &'static str {
+    match state {
+        State::CONTACT_START => "start",
+        State::CONTACT_MOVE => "move",
+        State::CONTACT_END => "end",
+        State::CONTACT_INVALID => "invalid",
+    }
+}
+
+fn contact_json(contact: &Contact) -> Value {
+    let mut obj = Map::new();
+    obj.insert("id".to_string(), json!(contact.id));
+    obj.insert("state".to_string(), json!(state_name(contact.state)));
+    obj.insert("x".to_string(), json!(contact.x));
+    obj.insert("y".to_string(), json!(contact.y));
+    obj.insert("force".to_string(), json!(contact.total_force));
+    obj.insert("area".to_string(), json!(contact.area));
+    if let Some(e) = contact.ellipse {
+        obj.insert("ellipse".to_string(), json!([e.orientation, e.major_axis, e.minor_axis]));
+    }
+    if let Some(d) = contact.delta {
+        obj.insert("delta".to_string(), json!([d.x, d.y, d.force, d.area]));
+    }
+    if let Some(b) = contact.bounding_box {
+        obj.insert("bounding_box".to_string(), json!([b.min_x, b.min_y, b.max_x, b.max_y]));
+    }
+    if let Some(p) = contact.peak {
+        obj.insert("peak".to_string(), json!([p.x, p.y, p.force]));
+    }
+    Value::Object(obj)
+}
+
+fn floats(value: Option<&Value>, n: usize) -> Option<Vec<f32>> {
+    let values: Vec<f32> = value?.as_array()?.iter().filter_map(Value::as_f64).map(|v| v as f32).collect();
+    if values.len() == n { Some(values) } else { None }
+}
+
+fn parse_contact(value: &Value) -> Option<Contact> {
+    let f = |key: &str| value.get(key).and_then(Value::as_f64).map(|v| v as f32);
+    let state = match value.get("state")?.as_str()? {
+        "start" => State::CONTACT_START,
+        "move" => State::CONTACT_MOVE,
+        "end" => State::CONTACT_END,
+        _ => State::CONTACT_INVALID,
+    };
+    Some(Contact {
+        id: value.get("id")?.as_u64()? as u8,
+        state,
+        x: f("x")?,
+        y: f("y")?,
+        total_force: f("force").unwrap_or(0.0),
+        area: f("area").unwrap_or(0.0),
+        ellipse: floats(value.get("ellipse"), 3)
+            .map(|e| Ellipse { orientation: e[0], major_axis: e[1], minor_axis: e[2] }),
+        delta: floats(value.get("delta"), 4)
+            .map(|d| Delta { x: d[0], y: d[1], force: d[2], area: d[3] }),
+        bounding_box: floats(value.get("bounding_box"), 4)
+            .map(|b| BoundingBox { min_x: b[0], min_y: b[1], max_x: b[2], max_y: b[3] }),
+        peak: floats(value.get("peak"), 3)
+            .map(|p| Peak { x: p[0], y: p[1], force: p[2] }),
+    })
+}
+
+impl Session {
+    /// The frame for `N`, or the last frame at or before a time in seconds
+    /// written `SECSs`.
+    pub fn frame(&self, which: &str) -> Option<&SessionFrame> {
+        if let Some(time) = which.strip_suffix('s') {
+            let time: f32 = time.parse().ok()?;
+            self.frames.iter().take_while(|f| f.time <= time).last()
+        } else {
+            self.frames.get(which.parse::<usize>().ok()?)
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Session, InterfaceError> {
+        let invalid = |line: usize| InterfaceError::Invalid(format!("session line {} is invalid", line + 1));
+        let mut lines = BufReader::new(File::open(path)?).lines();
+
+        let header: Value = serde_json::from_str(&lines.next().unwrap_or_else(|| Ok(String::new()))?)?;
+        let size = |key: &str| header.get(key).and_then(Value::as_f64).map(|v| v as f32);
+        let mut session = Session {
+            width: size("width").ok_or_else(|| invalid(0))?,
+            height: size("height").ok_or_else(|| invalid(0))?,
+            frames: vec![],
+        };
+
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let frame: Value = serde_json::from_str(&line)?;
+            let contacts = frame.get("contacts").and_then(Value::as_array).ok_or_else(|| invalid(i + 1))?;
+            session.frames.push(SessionFrame {
+                time: frame.get("time").and_then(Value::as_f64).unwrap_or(0.0) as f32,
+                contacts: contacts.iter()
+                    .map(|c| parse_contact(c).ok_or_else(|| invalid(i + 1)))
+                    .collect::<Result<_, _>>()?,
+            });
+        }
+        Ok(session)
+    }
+}
+
+/// Records the contacts of each frame as they are read.
+pub struct SessionWriter {
+    out: BufWriter<File>,
+}
+
+impl SessionWriter {
+    pub fn create<P: AsRef<Path>>(path: P, width: f32, height: f32) -> io::Result<SessionWriter> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", json!({ "width": width, "height": height }))?;
+        Ok(SessionWriter { out })
+    }
+
+    /// Record a frame, `time` since the start of the session. Frames without
+    /// contacts are skipped.
+    pub fn write_frame(&mut self, time: Duration, contacts: &[Contact]) -> io::Result<()> {
+        if contacts.is_empty() {
+            return Ok(());
+        }
+        let time = time.as_secs() as f64 + f64::from(time.subsec_micros()) * 1e-6;
+        let contacts: Vec<Value> = contacts.iter().map(contact_json).collect();
+        writeln!(self.out, "{}", json!({ "time": time, "contacts": contacts }))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join("sensel_osc_session_test.jsonl");
+        let contact = Contact {
+            id: 2,
+            state: State::CONTACT_MOVE,
+            x: 10.5,
+            y: 20.25,
+            total_force: 300.0,
+            area: 12.0,
+            ellipse: Some(Ellipse { orientation: 45.0, major_axis: 8.0, minor_axis: 6.0 }),
+            delta: None,
+            bounding_box: Some(BoundingBox { min_x: 7.0, min_y: 17.0, max_x: 14.0, max_y: 24.0 }),
+            peak: Some(Peak { x: 10.0, y: 20.0, force: 50.0 }),
+        };
+
+        let mut writer = SessionWriter::create(&path, 230.0, 130.0).unwrap();
+        writer.write_frame(Duration::from_millis(0), &[]).unwrap();
+        writer.write_frame(Duration::from_millis(1500), &[contact]).unwrap();
+        writer.flush().unwrap();
+
+        let session = Session::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!((session.width, session.height), (230.0, 130.0));
+        assert_eq!(session.frames.len(), 1);
+        assert_eq!(session.frames[0].time, 1.5);
+        let read = session.frames[0].contacts[0];
+        assert_eq!((read.id, read.state, read.x, read.y), (2, State::CONTACT_MOVE, 10.5, 20.25));
+        assert_eq!(read.ellipse.unwrap().major_axis, 8.0);
+        assert!(read.delta.is_none());
+        assert!(session.frame("0").is_some());
+        assert!(session.frame("1").is_none());
+        assert!(session.frame("1.49s").is_none());
+        assert!(session.frame("2s").is_some());
+        assert_eq!(read.bounding_box.unwrap().max_x, 14.0);
+        assert_eq!(read.peak.unwrap().force, 50.0);
+    }
+}
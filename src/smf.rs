@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::midi::MidiSink;
+
+// ticks per quarter note
+const DIVISION: u16 = 960;
+// 120 bpm, in microseconds per quarter note
+const TEMPO: u32 = 500_000;
+
+/// Records MIDI to a Standard MIDI File, timed by the capture time of the
+/// frame each message came from. Everything goes on a single track (format
+/// 0) so MPE member channels stay together when imported into a DAW.
+pub struct SmfRecorder {
+    path: PathBuf,
+    start: SystemTime,
+    now: SystemTime,
+    events: Vec<(u32, Vec<u8>)>,
+}
+
+impl SmfRecorder {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<SmfRecorder> {
+        // fail now rather than when the performance is over
+        File::create(&path)?;
+        let start = SystemTime::now();
+        Ok(SmfRecorder {
+            path: path.as_ref().to_path_buf(),
+            start,
+            now: start,
+            events: vec![],
+        })
+    }
+
+    fn ticks(&self, time: SystemTime) -> u32 {
+        let elapsed = time.duration_since(self.start).unwrap_or_else(|_| Duration::from_secs(0));
+        let micros = elapsed.as_secs() * 1_000_000 + u64::from(elapsed.subsec_micros());
+        ((micros * u64::from(DIVISION) + u64::from(TEMPO) / 2) / u64::from(TEMPO)) as u32
+    }
+}
+
+impl MidiSink for SmfRecorder {
+    fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+        let ticks = self.ticks(self.now);
+        self.events.push((ticks, msg.to_vec()));
+        Ok(())
+    }
+
+    fn set_time(&mut self, time: SystemTime) {
+        self.now = time;
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        out.write_all(&encode(&self.events))?;
+        out.flush()
+    }
+}
+
+fn write_vlq(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = [0u8; 4];
+    let mut i = bytes.len() - 1;
+    bytes[i] = (value & 0x7F) as u8;
+    value >>= 7;
+    while value > 0 {
+        i -= 1;
+        bytes[i] = (value & 0x7F) as u8 | 0x80;
+        value >>= 7;
+    }
+    out.extend_from_slice(&bytes[i..]);
+}
+
+/// A format 0 file of `events`, each (ticks from the start, message), in
+/// time order.
+pub fn encode(events: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut track = vec![];
+    // tempo
+    track.extend_from_slice(&[0x00, 0xFF, 0x51, 0x03]);
+    track.extend_from_slice(&TEMPO.to_be_bytes()[1..]);
+
+    let mut last = 0;
+    for (ticks, msg) in events {
+        // frames can be captured out of order across reads, never go backwards
+        let ticks = (*ticks).max(last);
+        write_vlq(&mut track, ticks - last);
+        track.extend_from_slice(msg);
+        last = ticks;
+    }
+    // end of track
+    track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+    let mut file = b"MThd".to_vec();
+    file.extend_from_slice(&6u32.to_be_bytes());
+    file.extend_from_slice(&0u16.to_be_bytes());
+    file.extend_from_slice(&1u16.to_be_bytes());
+    file.extend_from_slice(&DIVISION.to_be_bytes());
+    file.extend_from_slice(b"MTrk");
+    file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    file.extend_from_slice(&track);
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vlq() {
+        let cases: &[(u32, &[u8])] = &[
+            (0, &[0x00]),
+            (0x40, &[0x40]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x81, 0x00]),
+            (0x2000, &[0xC0, 0x00]),
+            (0x0FFF_FFFF, &[0xFF, 0xFF, 0xFF, 0x7F]),
+        ];
+        for (value, bytes) in cases {
+            let mut out = vec![];
+            write_vlq(&mut out, *value);
+            assert_eq!(&out[..], *bytes);
+        }
+    }
+
+    #[test]
+    fn file_layout() {
+        let smf = encode(&[(0, vec![0x90, 60, 100]), (960, vec![0x80, 60, 0])]);
+        assert_eq!(&smf[..14], b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x03\xC0");
+        assert_eq!(&smf[14..22], b"MTrk\x00\x00\x00\x14");
+        assert_eq!(&smf[22..], &[
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20,
+            0x00, 0x90, 60, 100,
+            0x87, 0x40, 0x80, 60, 0,
+            0x00, 0xFF, 0x2F, 0x00,
+        ][..]);
+    }
+
+    #[test]
+    fn ticks_follow_frame_time() {
+        let dir = std::env::temp_dir().join("sensel_osc_smf_test.mid");
+        let mut recorder = SmfRecorder::new(&dir).unwrap();
+        let start = recorder.start;
+        recorder.send(&[0xB0, 101, 0]).unwrap();
+        recorder.set_time(start + Duration::from_millis(500));
+        recorder.send(&[0x90, 60, 100]).unwrap();
+        recorder.set_time(start + Duration::from_millis(1250));
+        recorder.send(&[0x80, 60, 0]).unwrap();
+        assert_eq!(recorder.events.iter().map(|e| e.0).collect::<Vec<_>>(), vec![0, 960, 2400]);
+
+        recorder.finish().unwrap();
+        let smf = std::fs::read(&dir).unwrap();
+        let _ = std::fs::remove_file(&dir);
+        assert_eq!(smf, encode(&recorder.events));
+    }
+}
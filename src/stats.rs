@@ -0,0 +1,208 @@
+use std::fmt::Write;
+use std::mem;
+use std::time::{Duration, Instant};
+
+use rosc::{OscMessage, OscType};
+
+// bucket i of a histogram counts durations under FIRST_BUCKET << i, the last
+// one everything longer
+const FIRST_BUCKET: Duration = Duration::from_micros(16);
+const BUCKETS: usize = 16;
+const BAR_WIDTH: u64 = 40;
+
+/// Durations of one kind: their spread and a histogram.
+#[derive(Clone, Debug, Default)]
+pub struct Series {
+    count: u64,
+    // in µs
+    sum: f64,
+    sum_sq: f64,
+    max: Duration,
+    buckets: [u64; BUCKETS],
+}
+
+impl Series {
+    pub fn record(&mut self, duration: Duration) {
+        let us = duration.as_secs_f64() * 1e6;
+        self.count += 1;
+        self.sum += us;
+        self.sum_sq += us * us;
+        self.max = self.max.max(duration);
+        let bucket = (0..BUCKETS - 1)
+            .find(|&i| duration < FIRST_BUCKET * (1 << i))
+            .unwrap_or(BUCKETS - 1);
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::from_secs(0);
+        }
+        Duration::from_secs_f64(self.sum / self.count as f64 / 1e6)
+    }
+
+    /// The standard deviation, the jitter of frame intervals.
+    pub fn std_dev(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::from_secs(0);
+        }
+        let mean = self.sum / self.count as f64;
+        let variance = (self.sum_sq / self.count as f64 - mean * mean).max(0.0);
+        Duration::from_secs_f64(variance.sqrt() / 1e6)
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    fn histogram(&self, out: &mut String) {
+        if self.count == 0 {
+            return;
+        }
+        let most = self.buckets.iter().copied().max().unwrap_or(0).max(1);
+        let first = self.buckets.iter().position(|&n| n > 0).unwrap_or(0);
+        let last = self.buckets.iter().rposition(|&n| n > 0).unwrap_or(0);
+        for i in first..=last {
+            let label = if i == BUCKETS - 1 {
+                format!(">= {}", us(FIRST_BUCKET * (1 << (i - 1))))
+            } else {
+                format!("< {}", us(FIRST_BUCKET * (1 << i)))
+            };
+            let bar = "#".repeat((self.buckets[i] * BAR_WIDTH).div_ceil(most) as usize);
+            let _ = writeln!(out, "    {:>10} {:<40} {}", label, bar, self.buckets[i]);
+        }
+    }
+}
+
+fn us(duration: Duration) -> String {
+    format!("{}us", duration.as_micros())
+}
+
+/// What the scan loop measured over some time.
+#[derive(Clone, Debug, Default)]
+pub struct Sample {
+    // from read_sensor returning to a frame's OSC being sent
+    pub latency: Series,
+    // handling one frame, from getting it to sending its OSC
+    pub processing: Series,
+    // between reads that returned frames
+    pub interval: Series,
+    pub frames: u64,
+    pub lost_frames: u64,
+}
+
+impl Sample {
+    /// `/sensel/stats frames lost latency_mean latency_max processing_mean
+    /// processing_max interval_mean jitter`, times in µs.
+    pub fn message(&self) -> OscMessage {
+        let us = |d: Duration| OscType::Float(d.as_secs_f32() * 1e6);
+        OscMessage {
+            addr: "/sensel/stats".to_string(),
+            args: Some(vec![
+                OscType::Long(self.frames as i64),
+                OscType::Long(self.lost_frames as i64),
+                us(self.latency.mean()),
+                us(self.latency.max()),
+                us(self.processing.mean()),
+                us(self.processing.max()),
+                us(self.interval.mean()),
+                us(self.interval.std_dev()),
+            ]),
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "frames: {}, lost: {}", self.frames, self.lost_frames);
+        let series = [
+            ("latency (read to OSC sent)", &self.latency),
+            ("processing per frame", &self.processing),
+            ("frame interval", &self.interval),
+        ];
+        for &(name, series) in &series {
+            let _ = writeln!(out, "{}: mean {}, max {}, std dev {}", name,
+                             us(series.mean()), us(series.max()), us(series.std_dev()));
+            series.histogram(&mut out);
+        }
+        out
+    }
+}
+
+/// Measurements of the whole run, and of the time since the last periodic
+/// report.
+#[derive(Default)]
+pub struct Stats {
+    pub total: Sample,
+    window: Sample,
+    last_read: Option<Instant>,
+}
+
+impl Stats {
+    /// A read that returned frames at `now`.
+    pub fn read(&mut self, now: Instant) {
+        if let Some(last) = self.last_read {
+            self.total.interval.record(now - last);
+            self.window.interval.record(now - last);
+        }
+        self.last_read = Some(now);
+    }
+
+    pub fn frame(&mut self, lost_frame_count: i32) {
+        let lost = lost_frame_count.max(0) as u64;
+        for sample in &mut [&mut self.total, &mut self.window] {
+            sample.frames += 1;
+            sample.lost_frames += lost;
+        }
+    }
+
+    /// A frame read at `read` and handled from `started` that sent OSC.
+    pub fn sent(&mut self, read: Instant, started: Instant) {
+        let now = Instant::now();
+        for sample in &mut [&mut self.total, &mut self.window] {
+            sample.latency.record(now - read);
+            sample.processing.record(now - started);
+        }
+    }
+
+    /// What was measured since the last call.
+    pub fn take_window(&mut self) -> Sample {
+        mem::take(&mut self.window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_spread_and_histogram() {
+        let mut series = Series::default();
+        for &us in &[900, 1000, 1100, 1000] {
+            series.record(Duration::from_micros(us));
+        }
+        assert_eq!(series.mean(), Duration::from_micros(1000));
+        assert_eq!(series.max(), Duration::from_micros(1100));
+        assert!((series.std_dev().as_secs_f64() * 1e6 - 70.71).abs() < 0.1);
+        // 900 and 1000 are under 1024us, 1100 under 2048us
+        assert_eq!(series.buckets[6], 3);
+        assert_eq!(series.buckets[7], 1);
+        let mut out = String::new();
+        series.histogram(&mut out);
+        assert_eq!(out.lines().count(), 2);
+    }
+
+    #[test]
+    fn windows_restart_but_totals_keep_counting() {
+        let mut stats = Stats::default();
+        let start = Instant::now();
+        stats.read(start);
+        stats.read(start + Duration::from_millis(2));
+        stats.frame(3);
+        let window = stats.take_window();
+        assert_eq!((window.frames, window.lost_frames), (1, 3));
+        assert_eq!(window.interval.mean(), Duration::from_millis(2));
+        stats.frame(0);
+        assert_eq!(stats.take_window().frames, 1);
+        assert_eq!(stats.total.frames, 2);
+    }
+}
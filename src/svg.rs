@@ -0,0 +1,319 @@
+use roxmltree::{Document, Node};
+use serde_json::{json, Map, Value};
+
+use crate::interface::InterfaceError;
+
+// SVG user units (CSS px) per mm
+const PX_PER_MM: f32 = 96.0 / 25.4;
+
+/// A 2D affine transform, `[a b c d e f]` as in SVG's `matrix()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Transform([f32; 6]);
+
+impl Transform {
+    const IDENTITY: Transform = Transform([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn then(self, inner: Transform) -> Transform {
+        let [a, b, c, d, e, f] = self.0;
+        let [g, h, i, j, k, l] = inner.0;
+        Transform([
+            a * g + c * h, b * g + d * h,
+            a * i + c * j, b * i + d * j,
+            a * k + c * l + e, b * k + d * l + f,
+        ])
+    }
+
+    fn apply(self, x: f32, y: f32) -> (f32, f32) {
+        let [a, b, c, d, e, f] = self.0;
+        (a * x + c * y + e, b * x + d * y + f)
+    }
+
+    /// Parse a `transform` attribute, a list of `name(args)`.
+    fn parse(s: &str) -> Result<Transform, String> {
+        let mut transform = Transform::IDENTITY;
+        let mut rest = s.trim();
+        while !rest.is_empty() {
+            let open = rest.find('(').ok_or_else(|| format!("invalid transform '{}'", s))?;
+            let close = rest.find(')').ok_or_else(|| format!("invalid transform '{}'", s))?;
+            let name = rest[..open].trim_matches(|c: char| c.is_whitespace() || c == ',');
+            let args = numbers(&rest[open + 1..close]).map_err(|_| format!("invalid transform '{}'", s))?;
+            let t = match (name, args.as_slice()) {
+                ("matrix", &[a, b, c, d, e, f]) => [a, b, c, d, e, f],
+                ("translate", &[x]) => [1.0, 0.0, 0.0, 1.0, x, 0.0],
+                ("translate", &[x, y]) => [1.0, 0.0, 0.0, 1.0, x, y],
+                ("scale", &[s]) => [s, 0.0, 0.0, s, 0.0, 0.0],
+                ("scale", &[x, y]) => [x, 0.0, 0.0, y, 0.0, 0.0],
+                ("rotate", &[a]) => {
+                    let (sin, cos) = a.to_radians().sin_cos();
+                    [cos, sin, -sin, cos, 0.0, 0.0]
+                }
+                ("rotate", &[a, x, y]) => {
+                    let (sin, cos) = a.to_radians().sin_cos();
+                    [cos, sin, -sin, cos, x - cos * x + sin * y, y - sin * x - cos * y]
+                }
+                ("skewX", &[a]) => [1.0, 0.0, a.to_radians().tan(), 1.0, 0.0, 0.0],
+                ("skewY", &[a]) => [1.0, a.to_radians().tan(), 0.0, 1.0, 0.0, 0.0],
+                _ => return Err(format!("invalid transform '{}'", s)),
+            };
+            transform = transform.then(Transform(t));
+            rest = &rest[close + 1..];
+        }
+        Ok(transform)
+    }
+}
+
+fn numbers(s: &str) -> Result<Vec<f32>, std::num::ParseFloatError> {
+    s.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|n| !n.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// A length in mm, `None` for unitless (user units).
+fn physical_length(s: &str) -> Option<f32> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_alphabetic() || c == '%').unwrap_or(s.len());
+    let value: f32 = s[..split].parse().ok()?;
+    let mm = match &s[split..] {
+        "mm" => 1.0,
+        "cm" => 10.0,
+        "in" => 25.4,
+        "pt" => 25.4 / 72.0,
+        "pc" => 25.4 / 6.0,
+        "px" => 1.0 / PX_PER_MM,
+        _ => return None,
+    };
+    Some(value * mm)
+}
+
+// from user units of the root element to Morph mm. Without a physical size
+// user units are taken to be mm.
+fn root_transform(svg: Node) -> Transform {
+    let view_box = svg.attribute("viewBox").and_then(|v| numbers(v).ok()).filter(|v| v.len() == 4);
+    let width = svg.attribute("width").and_then(physical_length);
+    let height = svg.attribute("height").and_then(physical_length);
+    match (view_box, width, height) {
+        (Some(vb), Some(w), Some(h)) if vb[2] > 0.0 && vb[3] > 0.0 => {
+            let (sx, sy) = (w / vb[2], h / vb[3]);
+            Transform([sx, 0.0, 0.0, sy, -vb[0] * sx, -vb[1] * sy])
+        }
+        (Some(vb), _, _) => Transform([1.0, 0.0, 0.0, 1.0, -vb[0], -vb[1]]),
+        (None, Some(_), _) | (None, _, Some(_)) => Transform([1.0 / PX_PER_MM, 0.0, 0.0, 1.0 / PX_PER_MM, 0.0, 0.0]),
+        (None, None, None) => Transform::IDENTITY,
+    }
+}
+
+fn attr_f32(node: Node, name: &str) -> Result<f32, String> {
+    match node.attribute(name) {
+        None => Ok(0.0),
+        Some(v) => v.trim().trim_end_matches("px").parse()
+            .map_err(|_| format!("invalid {} '{}' on line {}", name, v, line(node))),
+    }
+}
+
+fn line(node: Node) -> u32 {
+    node.document().text_pos_at(node.range().start).row
+}
+
+// the element's bounding box in its own user units, as (x, y, width, height)
+fn shape_bounds(node: Node) -> Result<Option<(f32, f32, f32, f32)>, String> {
+    let bounds = match node.tag_name().name() {
+        "rect" | "image" | "use" => (attr_f32(node, "x")?, attr_f32(node, "y")?,
+                                     attr_f32(node, "width")?, attr_f32(node, "height")?),
+        "circle" => {
+            let r = attr_f32(node, "r")?;
+            (attr_f32(node, "cx")? - r, attr_f32(node, "cy")? - r, 2.0 * r, 2.0 * r)
+        }
+        "ellipse" => {
+            let (rx, ry) = (attr_f32(node, "rx")?, attr_f32(node, "ry")?);
+            (attr_f32(node, "cx")? - rx, attr_f32(node, "cy")? - ry, 2.0 * rx, 2.0 * ry)
+        }
+        "line" => {
+            let (x1, y1) = (attr_f32(node, "x1")?, attr_f32(node, "y1")?);
+            let (x2, y2) = (attr_f32(node, "x2")?, attr_f32(node, "y2")?);
+            (x1.min(x2), y1.min(y2), (x2 - x1).abs(), (y2 - y1).abs())
+        }
+        "polygon" | "polyline" => {
+            let points = node.attribute("points").and_then(|p| numbers(p).ok()).unwrap_or_default();
+            if points.len() < 2 {
+                return Ok(None);
+            }
+            let xs = points.iter().step_by(2);
+            let ys = points.iter().skip(1).step_by(2);
+            let (min_x, max_x) = xs.fold((f32::MAX, f32::MIN), |(lo, hi), &x| (lo.min(x), hi.max(x)));
+            let (min_y, max_y) = ys.fold((f32::MAX, f32::MIN), |(lo, hi), &y| (lo.min(y), hi.max(y)));
+            (min_x, min_y, max_x - min_x, max_y - min_y)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(bounds))
+}
+
+// an attribute's text as a JSON number if it is one
+fn scalar(s: &str) -> Value {
+    let s = s.trim();
+    if let Ok(i) = s.parse::<i64>() {
+        json!(i)
+    } else if let Ok(f) = s.parse::<f64>() {
+        json!(f)
+    } else if s == "true" || s == "false" {
+        json!(s == "true")
+    } else {
+        json!(s)
+    }
+}
+
+fn control(node: Node, transform: Transform, control_type: &str) -> Result<Option<Value>, String> {
+    let (x, y, width, height) = match shape_bounds(node)? {
+        Some(bounds) => bounds,
+        None => {
+            warn!("SVG: can't find the bounds of <{}> on line {}, skipped", node.tag_name().name(), line(node));
+            return Ok(None);
+        }
+    };
+    // the axis aligned box around the transformed corners
+    let corners = [
+        transform.apply(x, y),
+        transform.apply(x + width, y),
+        transform.apply(x, y + height),
+        transform.apply(x + width, y + height),
+    ];
+    let min_x = corners.iter().map(|c| c.0).fold(f32::MAX, f32::min);
+    let max_x = corners.iter().map(|c| c.0).fold(f32::MIN, f32::max);
+    let min_y = corners.iter().map(|c| c.1).fold(f32::MAX, f32::min);
+    let max_y = corners.iter().map(|c| c.1).fold(f32::MIN, f32::max);
+
+    let mut obj = Map::new();
+    obj.insert("type".to_string(), json!(control_type));
+    obj.insert("x".to_string(), json!(min_x));
+    obj.insert("y".to_string(), json!(min_y));
+    obj.insert("width".to_string(), json!(max_x - min_x));
+    obj.insert("height".to_string(), json!(max_y - min_y));
+    if let Some(id) = node.attribute("id") {
+        obj.insert("id".to_string(), json!(id));
+    }
+    if let Some(address) = node.attribute("osc_address").or_else(|| node.attribute("address")) {
+        obj.insert("address".to_string(), json!(address));
+    }
+    if let Some(args) = node.attribute("args") {
+        let args: Vec<Value> = args.split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|a| !a.is_empty())
+            .map(scalar)
+            .collect();
+        obj.insert("args".to_string(), Value::Array(args));
+    }
+    for key in &["min", "max", "low_note", "keys", "page"] {
+        if let Some(v) = node.attribute(*key) {
+            obj.insert(key.to_string(), scalar(v));
+        }
+    }
+
+    let mut filters = Map::new();
+    for channel in &["position", "force"] {
+        if let Some(spec) = node.attribute(format!("{}_filter", channel).as_str()) {
+            filters.insert(channel.to_string(), json!(spec));
+        }
+    }
+    if !filters.is_empty() {
+        obj.insert("filters".to_string(), Value::Object(filters));
+    }
+
+    let mut midi = Map::new();
+    for key in &["channel", "note", "cc", "cc14", "pressure", "pitch_bend"] {
+        if let Some(v) = node.attribute(format!("midi_{}", key).as_str()) {
+            midi.insert(key.to_string(), scalar(v));
+        }
+    }
+    if !midi.is_empty() {
+        obj.insert("midi".to_string(), Value::Object(midi));
+    }
+
+    Ok(Some(Value::Object(obj)))
+}
+
+fn walk(node: Node, transform: Transform, controls: &mut Vec<Value>) -> Result<(), String> {
+    let transform = match node.attribute("transform") {
+        Some(t) => transform.then(Transform::parse(t)?),
+        None => transform,
+    };
+    match node.attribute("interface_type") {
+        // decoration drawn in the SVG creator
+        None | Some("none") | Some("border") => (),
+        Some(control_type) => {
+            if let Some(control) = control(node, transform, control_type)? {
+                controls.push(control);
+            }
+        }
+    }
+    for child in node.children().filter(Node::is_element) {
+        if child.tag_name().name() != "defs" {
+            walk(child, transform, controls)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read an SVG interface as IR: every element with an `interface_type`
+/// becomes a control, with the same attributes as the IR keys (or
+/// `osc_address` for `address`, `midi_*` for the `midi` object and
+/// `position_filter`/`force_filter` for `filters`). Coordinates are in mm if
+/// the SVG has a physical size, otherwise user units are taken as mm.
+pub fn to_ir(svg: &str) -> Result<Value, InterfaceError> {
+    let doc = Document::parse(svg).map_err(|e| InterfaceError::Invalid(format!("SVG: {}", e)))?;
+    let root = doc.root_element();
+    let mut controls = vec![];
+    walk(root, root_transform(root), &mut controls).map_err(|e| InterfaceError::Invalid(format!("SVG: {}", e)))?;
+    Ok(Value::Array(controls))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::{Arg, ControlType, Interface, Rect};
+
+    #[test]
+    fn same_model_as_ir() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="230mm" height="130mm" viewBox="0 0 460 260">
+            <rect x="0" y="0" width="460" height="260" interface_type="border"/>
+            <g transform="translate(20,20)">
+                <rect id="kick" x="0" y="0" width="40" height="40"
+                      interface_type="pad" osc_address="/drum" args="1 kick" min="0" max="127"
+                      midi_channel="10" midi_note="36"/>
+                <circle id="knob" cx="100" cy="20" r="20" interface_type="pad" osc_address="/knob"/>
+            </g>
+            <rect id="volume" x="400" y="20" width="40" height="200" transform="scale(1)"
+                  interface_type="vert_slider" osc_address="/volume" position_filter="ema(alpha=0.5)"/>
+        </svg>"#;
+        let ir = r#"[
+            { "type": "pad", "id": "kick", "x": 10, "y": 10, "width": 20, "height": 20,
+              "address": "/drum", "args": [1, "kick"], "min": 0, "max": 127,
+              "midi": { "channel": 10, "note": 36 } },
+            { "type": "pad", "id": "knob", "x": 50, "y": 10, "width": 20, "height": 20, "address": "/knob" },
+            { "type": "vert_slider", "id": "volume", "x": 200, "y": 10, "width": 20, "height": 100,
+              "address": "/volume", "filters": { "position": "ema(alpha=0.5)" } }
+        ]"#;
+
+        let from_svg = Interface::from_value(&to_ir(svg).unwrap()).unwrap();
+        assert_eq!(from_svg, Interface::from_json(ir).unwrap());
+        assert_eq!(from_svg.controls[0].args, vec![Arg::Int(1), Arg::Str("kick".to_string())]);
+        assert_eq!(from_svg.controls[2].control_type, ControlType::VertSlider);
+        assert_eq!(from_svg.controls[1].rect, Rect { x: 50.0, y: 10.0, width: 20.0, height: 20.0 });
+    }
+
+    #[test]
+    fn transforms() {
+        let t = Transform::parse("translate(10, 5) scale(2)").unwrap();
+        assert_eq!(t.apply(1.0, 1.0), (12.0, 7.0));
+        let t = Transform::parse("matrix(1 0 0 1 3 4)").unwrap();
+        assert_eq!(t.apply(0.0, 0.0), (3.0, 4.0));
+        assert!(Transform::parse("wobble(1)").is_err());
+    }
+
+    #[test]
+    fn invalid_attributes_are_errors() {
+        let svg = r#"<svg><rect x="a" width="1" height="1" interface_type="pad" osc_address="/a"/></svg>"#;
+        assert!(to_ir(svg).is_err());
+        let svg = r#"<svg><rect width="1" height="1" interface_type="pad"/></svg>"#;
+        assert!(Interface::from_value(&to_ir(svg).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use rosc::{encoder, OscPacket};
+
+use crate::encoder::FrameEncoder;
+
+pub const DEFAULT_DESTINATION: &str = "udp://127.0.0.1:57120";
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const MULTICAST_TTL: u32 = 1;
+
+// SLIP framing, RFC 1055, as required by OSC 1.1 over streams
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Udp,
+    Broadcast,
+    Multicast,
+    Tcp,
+}
+
+/// Where to send OSC, written `SCHEME://HOST:PORT[=PATTERN,...]`, where the
+/// scheme is one of `udp`, `broadcast`, `multicast` or `tcp`. If patterns
+/// are given only matching addresses are sent to the destination, a pattern
+/// ending in `*` matches any address with that prefix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Destination {
+    pub kind: Kind,
+    pub addr: SocketAddr,
+    pub filter: Vec<String>,
+}
+
+impl Destination {
+    pub fn accepts(&self, addr: &str) -> bool {
+        self.filter.is_empty() || self.filter.iter().any(|pattern| {
+            if pattern.ends_with('*') {
+                addr.starts_with(&pattern[..pattern.len() - 1])
+            } else {
+                addr == pattern
+            }
+        })
+    }
+
+    // the packet with any messages this destination does not accept removed
+    fn filter_packet(&self, packet: &OscPacket) -> Option<OscPacket> {
+        match packet {
+            OscPacket::Message(msg) => {
+                if self.accepts(&msg.addr) {
+                    Some(packet.clone())
+                } else {
+                    None
+                }
+            }
+            OscPacket::Bundle(bundle) => {
+                let content: Vec<_> = bundle.content.iter()
+                    .filter_map(|p| self.filter_packet(p))
+                    .collect();
+                if content.is_empty() {
+                    None
+                } else {
+                    Some(OscPacket::Bundle(rosc::OscBundle { timetag: bundle.timetag.clone(), content }))
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, "://");
+        let scheme = parts.next().unwrap_or("");
+        let rest = parts.next().ok_or_else(|| format!("expected SCHEME://HOST:PORT, got '{}'", s))?;
+
+        let kind = match scheme {
+            "udp" => Kind::Udp,
+            "broadcast" => Kind::Broadcast,
+            "multicast" => Kind::Multicast,
+            "tcp" => Kind::Tcp,
+            _ => return Err(format!("unknown transport '{}'", scheme)),
+        };
+
+        let mut parts = rest.splitn(2, '=');
+        let host = parts.next().unwrap_or("");
+        let filter = parts.next()
+            .map(|f| f.split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect())
+            .unwrap_or_else(Vec::new);
+
+        let addr = host.to_socket_addrs()
+            .map_err(|e| format!("cannot resolve '{}': {}", host, e))?
+            .next()
+            .ok_or_else(|| format!("cannot resolve '{}'", host))?;
+
+        if kind == Kind::Multicast && !addr.ip().is_multicast() {
+            return Err(format!("{} is not a multicast address", addr.ip()));
+        }
+
+        Ok(Destination { kind, addr, filter })
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scheme = match self.kind {
+            Kind::Udp => "udp",
+            Kind::Broadcast => "broadcast",
+            Kind::Multicast => "multicast",
+            Kind::Tcp => "tcp",
+        };
+        write!(f, "{}://{}", scheme, self.addr)?;
+        if !self.filter.is_empty() {
+            write!(f, "={}", self.filter.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn slip_encode(packet: &[u8], out: &mut Vec<u8>) {
+    out.push(SLIP_END);
+    for &b in packet {
+        match b {
+            SLIP_END => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            _ => out.push(b),
+        }
+    }
+    out.push(SLIP_END);
+}
+
+trait Transport {
+    fn send(&mut self, buf: &[u8]) -> io::Result<()>;
+}
+
+struct UdpTransport {
+    socket: UdpSocket,
+    addr: SocketAddr,
+}
+
+impl UdpTransport {
+    fn new(dest: &Destination) -> io::Result<Self> {
+        let bind = if dest.addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(bind)?;
+        match dest.kind {
+            Kind::Broadcast => socket.set_broadcast(true)?,
+            Kind::Multicast if dest.addr.is_ipv4() => {
+                socket.set_multicast_ttl_v4(MULTICAST_TTL)?;
+                socket.set_multicast_loop_v4(true)?;
+            }
+            Kind::Multicast => {
+                set_multicast_hops_v6(&socket, MULTICAST_TTL)?;
+                socket.set_multicast_loop_v6(true)?;
+            }
+            _ => (),
+        }
+        Ok(UdpTransport { socket, addr: dest.addr })
+    }
+}
+
+// the IPv6 counterpart of the multicast TTL, which std has no setter for
+#[cfg(target_os = "linux")]
+fn set_multicast_hops_v6(socket: &UdpSocket, hops: u32) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let hops = hops as libc::c_int;
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MULTICAST_HOPS,
+            &hops as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+// without libc only the default of one hop (RFC 3493) is available
+#[cfg(not(target_os = "linux"))]
+fn set_multicast_hops_v6(_socket: &UdpSocket, hops: u32) -> io::Result<()> {
+    if hops == 1 {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, "IPv6 multicast hop limit can only be set on Linux"))
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.socket.send_to(buf, self.addr).map(|_| ())
+    }
+}
+
+// TCP connections are owned by a writer thread so that connecting, and
+// reconnecting after the peer goes away, never stalls the scan loop.
+// Packets sent while disconnected are dropped.
+struct TcpTransport {
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+impl TcpTransport {
+    fn new(dest: &Destination) -> Self {
+        let (tx, rx) = mpsc::channel::<Vec<u8>>();
+        let addr = dest.addr;
+        thread::spawn(move || {
+            let mut stream: Option<TcpStream> = None;
+            let mut framed = Vec::new();
+            for packet in rx.iter() {
+                if stream.is_none() {
+                    stream = TcpStream::connect_timeout(&addr, RECONNECT_INTERVAL)
+                        .inspect(|s| {
+                            info!("Connected to tcp://{}", addr);
+                            let _ = s.set_nodelay(true);
+                        })
+                        .map_err(|e| debug!("tcp://{}: {}", addr, e))
+                        .ok();
+                    if stream.is_none() {
+                        // drop anything queued while we were trying
+                        thread::sleep(RECONNECT_INTERVAL);
+                        while rx.try_recv().is_ok() {}
+                        continue;
+                    }
+                }
+                framed.clear();
+                slip_encode(&packet, &mut framed);
+                if let Some(Err(e)) = stream.as_mut().map(|s| s.write_all(&framed)) {
+                    warn!("Lost connection to tcp://{}: {}", addr, e);
+                    stream = None;
+                }
+            }
+        });
+        TcpTransport { tx }
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.tx.send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "tcp writer thread exited"))
+    }
+}
+
+struct Target {
+    dest: Destination,
+    transport: Box<dyn Transport>,
+}
+
+/// Sends OSC packets to any number of destinations.
+pub struct OscSender {
+    targets: Vec<Target>,
+}
+
+impl OscSender {
+    pub fn new(dests: &[Destination]) -> io::Result<OscSender> {
+        let targets = dests.iter()
+            .map(|dest| {
+                let transport: Box<dyn Transport> = match dest.kind {
+                    Kind::Tcp => Box::new(TcpTransport::new(dest)),
+                    _ => Box::new(UdpTransport::new(dest)?),
+                };
+                Ok(Target { dest: dest.clone(), transport })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(OscSender { targets })
+    }
+
+    /// Send a frame's messages as one bundle at `time`, or each on its own
+    /// without. Nothing is allocated for UDP destinations.
+    pub fn send_frame(&mut self, frame: &mut FrameEncoder, time: Option<SystemTime>) {
+        for Target { dest, transport } in self.targets.iter_mut() {
+            let result = match time {
+                Some(time) => match frame.bundle(time, |addr| dest.accepts(addr)) {
+                    Some(packet) => transport.send(packet),
+                    None => Ok(()),
+                },
+                None => frame.messages()
+                    .filter(|&(addr, _)| dest.accepts(addr))
+                    .try_for_each(|(_, msg)| transport.send(msg)),
+            };
+            if let Err(e) = result {
+                warn!("Failed to send to {}: {}", dest, e);
+            }
+        }
+    }
+
+    pub fn send(&mut self, packet: &OscPacket) {
+        let encoded = match encoder::encode(packet) {
+            Ok(buf) => buf,
+            Err(e) => {
+                warn!("Failed to encode OSC packet: {:?}", e);
+                return;
+            }
+        };
+        for target in self.targets.iter_mut() {
+            let filtered;
+            let buf = if target.dest.filter.is_empty() {
+                &encoded
+            } else {
+                filtered = match target.dest.filter_packet(packet).map(|p| encoder::encode(&p)) {
+                    Some(Ok(buf)) => buf,
+                    _ => continue,
+                };
+                &filtered
+            };
+            if let Err(e) = target.transport.send(buf) {
+                warn!("Failed to send to {}: {}", target.dest, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Instant;
+
+    use rosc::{OscBundle, OscMessage, OscType};
+
+    use super::*;
+
+    fn message(addr: &str) -> OscPacket {
+        OscPacket::Message(OscMessage { addr: addr.to_string(), args: None })
+    }
+
+    fn addrs(packet: &OscPacket) -> Vec<String> {
+        match packet {
+            OscPacket::Message(msg) => vec![msg.addr.clone()],
+            OscPacket::Bundle(bundle) => bundle.content.iter().flat_map(addrs).collect(),
+        }
+    }
+
+    #[test]
+    fn ipv6_multicast_loops_back() {
+        let dest: Destination = "multicast://[ff02::1]:9000".parse().unwrap();
+        let transport = UdpTransport::new(&dest).unwrap();
+        assert!(transport.socket.multicast_loop_v6().unwrap());
+    }
+
+    #[test]
+    fn slip_escapes_end_and_esc() {
+        let mut out = vec![];
+        slip_encode(&[1, SLIP_END, 2, SLIP_ESC, 3], &mut out);
+        assert_eq!(out, vec![SLIP_END, 1, SLIP_ESC, SLIP_ESC_END, 2, SLIP_ESC, SLIP_ESC_ESC, 3, SLIP_END]);
+    }
+
+    #[test]
+    fn destinations_filter_by_address() {
+        let all: Destination = "udp://127.0.0.1:9000".parse().unwrap();
+        let some: Destination = "udp://127.0.0.1:9000=/pad/*,/fader".parse().unwrap();
+        assert!(all.accepts("/anything"));
+        assert!(some.accepts("/pad/x") && some.accepts("/fader"));
+        assert!(!some.accepts("/fader/x") && !some.accepts("/keys"));
+
+        assert_eq!(some.filter_packet(&message("/fader")).as_ref().map(addrs), Some(vec!["/fader".to_string()]));
+        assert_eq!(some.filter_packet(&message("/keys")), None);
+        let bundle = |content| OscPacket::Bundle(OscBundle { timetag: OscType::Time(0, 1), content });
+        let mixed = bundle(vec![message("/keys"), bundle(vec![message("/pad/x"), message("/keys")])]);
+        assert_eq!(some.filter_packet(&mixed).as_ref().map(addrs), Some(vec!["/pad/x".to_string()]));
+        assert_eq!(some.filter_packet(&bundle(vec![message("/keys")])), None);
+    }
+
+    // the first SLIP frame from `stream`
+    fn read_frame(stream: &mut TcpStream) -> Vec<u8> {
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut frame = vec![];
+        let mut byte = [0];
+        while frame.len() < 2 || frame.last() != Some(&SLIP_END) {
+            stream.read_exact(&mut byte).unwrap();
+            frame.push(byte[0]);
+        }
+        frame
+    }
+
+    #[test]
+    fn tcp_reconnects_after_the_peer_goes_away() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dest = format!("tcp://{}", listener.local_addr().unwrap()).parse().unwrap();
+        let mut transport = TcpTransport::new(&dest);
+
+        transport.send(&[1, 2]).unwrap();
+        let (mut first, _) = listener.accept().unwrap();
+        assert_eq!(read_frame(&mut first), vec![SLIP_END, 1, 2, SLIP_END]);
+        drop(first);
+
+        // writes fail once the close is noticed, then the next one reconnects
+        listener.set_nonblocking(true).unwrap();
+        let started = Instant::now();
+        let mut second = loop {
+            assert!(started.elapsed() < Duration::from_secs(10), "no reconnection");
+            transport.send(&[3]).unwrap();
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(_) => thread::sleep(Duration::from_millis(20)),
+            }
+        };
+        second.set_nonblocking(false).unwrap();
+        assert_eq!(read_frame(&mut second), vec![SLIP_END, 3, SLIP_END]);
+    }
+}
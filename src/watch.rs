@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Notices when any of a set of files is modified, by polling their
+/// modification times.
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Watcher {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        Watcher {
+            files: paths.iter().map(|p| (p.clone(), modified(p))).collect(),
+        }
+    }
+
+    pub fn is_watching(&self, paths: &[PathBuf]) -> bool {
+        self.files.len() == paths.len() && self.files.iter().zip(paths).all(|((a, _), b)| a == b)
+    }
+
+    /// Whether any file has changed since the last call. A file that is
+    /// missing, say while an editor replaces it, counts once it reappears.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last) in &mut self.files {
+            let now = modified(path);
+            if now.is_some() && now != *last {
+                changed = true;
+            }
+            *last = now;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn a_change_is_reported_once() {
+        let path = std::env::temp_dir().join("sensel_osc_watch_test.json");
+        File::create(&path).unwrap();
+        let mut watcher = Watcher::new(std::slice::from_ref(&path));
+        assert!(!watcher.changed());
+
+        let later = modified(&path).unwrap() + Duration::from_secs(10);
+        File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_deleted_file_counts_once_it_reappears() {
+        let path = std::env::temp_dir().join("sensel_osc_watch_deleted_test.json");
+        File::create(&path).unwrap();
+        let mut watcher = Watcher::new(std::slice::from_ref(&path));
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.changed());
+        assert!(!watcher.changed());
+        File::create(&path).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        fs::remove_file(&path).unwrap();
+    }
+}